@@ -0,0 +1,40 @@
+//! Compares recovering a plugin by loading a new instance of it with taking
+//! a spare instance from a [`PluginHostPool`].
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use plugin_app::host::{PluginHost, PluginHostPool, SharedCtx};
+use wasmtime::Engine;
+
+fn pool(c: &mut Criterion) {
+    let engine = Engine::default();
+    let path = common::test_plugin();
+
+    c.bench_function("recover with a new instance", |b| {
+        b.iter(|| {
+            let mut host =
+                PluginHost::try_new(engine.clone(), SharedCtx::default(), &path).unwrap();
+            host.try_call_init().unwrap();
+            host
+        })
+    });
+
+    let host = PluginHost::try_new(engine.clone(), SharedCtx::default(), &path).unwrap();
+    c.bench_function("recover with a spare instance", |b| {
+        b.iter_batched(
+            || PluginHostPool::new(&host, 1).unwrap(),
+            // the pool is returned so that it's dropped outside of the
+            // measure.
+            |mut pool| {
+                let host = pool.acquire().unwrap();
+                (pool, host)
+            },
+            BatchSize::PerIteration,
+        )
+    });
+}
+
+criterion_group!(benches, pool);
+criterion_main!(benches);
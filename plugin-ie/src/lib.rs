@@ -3,14 +3,46 @@
 // #[cfg(not(target_arch = "wasm32"))]
 // compile_error!("This crate must be compiled for the wasm32 target!");
 
-wit_bindgen::generate!(in "../wit/plugin.wit");
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+wit_bindgen::generate!({
+    path: "../wit/plugin.wit",
+    world: "plugin",
+});
 use plugin_app::core::{
-    host_app::{log, Level},
-    types::Command,
+    host_app::{get_locale, log, poll_task, read_file, spawn_task, stream_write, Level},
+    types::{Command, EventDescriptor, ParsedArgs, PluginTelemetry, TaskStatus},
 };
 
 pub struct PluginIe;
 
+/// The version of the plugin, in the `plugin-version` custom section so that
+/// the host can read it without calling `init`.
+#[link_section = "plugin-version"]
+#[used]
+static PLUGIN_VERSION: [u8; env!("CARGO_PKG_VERSION").len()] = {
+    let version = env!("CARGO_PKG_VERSION").as_bytes();
+    let mut bytes = [0; env!("CARGO_PKG_VERSION").len()];
+    let mut i = 0;
+    while i < bytes.len() {
+        bytes[i] = version[i];
+        i += 1;
+    }
+    bytes
+};
+
+/// Number of times the plugin said hello, it is kept across restarts with
+/// `export-state` and `import-state`.
+static GREETINGS: AtomicU64 = AtomicU64::new(0);
+
+/// Counters returned by `get-telemetry`.
+static TELEMETRY: Mutex<PluginTelemetry> = Mutex::new(PluginTelemetry {
+    command_calls: Vec::new(),
+    errors: 0,
+    last_error: None,
+});
+
 impl Guest for PluginIe {
     fn init() -> PluginInfo {
         log(Level::Debug, "Hello my friend!");
@@ -19,34 +51,234 @@ impl Guest for PluginIe {
             name: env!("CARGO_PKG_NAME").to_string(),
             description: env!("CARGO_PKG_DESCRIPTION").to_string(),
             version: env!("CARGO_PKG_VERSION").to_string(),
-            commands: vec![Command {
-                name: "hello".to_string(),
-                usage: "hello <language>".to_string(),
-                description: "Says \"hello\" in the specified language, only french, english, italian and german are supported.".to_string()
-            }],
+            categories: vec!["demo".to_string(), "greetings".to_string()],
+            authors: env!("CARGO_PKG_AUTHORS")
+                .split(':')
+                .map(String::from)
+                .collect(),
+            required_features: Vec::new(),
+            commands: vec![
+                Command {
+                    name: "hello".to_string(),
+                    usage: "hello [language]".to_string(),
+                    description: "Says \"hello\" in the specified language, or the language of the locale, only french, english, italian and german are supported.".to_string(),
+                    // the language is optional, a spec can't express it.
+                    spec: None,
+                },
+                Command {
+                    name: "fib".to_string(),
+                    usage: "fib <n>".to_string(),
+                    description: "Computes the n-th Fibonacci number in a background task.".to_string(),
+                    spec: None,
+                },
+                Command {
+                    name: "task".to_string(),
+                    usage: "task <id>".to_string(),
+                    description: "Prints the status of a task.".to_string(),
+                    spec: None,
+                },
+                Command {
+                    name: "cat".to_string(),
+                    usage: "cat <path>".to_string(),
+                    description: "Prints the content of a file.".to_string(),
+                    spec: None,
+                },
+            ],
         }
     }
 
     fn run_command(name: String, args: Vec<String>) {
+        match execute(&name, &args) {
+            Ok(output) => log(Level::Info, &output),
+            Err(err) => log(Level::Error, &err),
+        }
+    }
+
+    fn run_command_parsed(name: String, args: ParsedArgs) {
+        // none of the commands has a spec, the host never calls it.
+        Self::run_command(name, args.positional)
+    }
+
+    fn run_command_with_output(name: String, args: Vec<String>) -> Result<String, String> {
+        execute(&name, &args)
+    }
+
+    fn on_error(msg: String) {
+        log(Level::Error, &format!("the last command failed: {msg}"));
+    }
+
+    fn run_task(name: String, args: Vec<String>) -> Result<String, String> {
         match name.as_str() {
-            "hello" => {
-                if args.len() != 1 {
-                    log(Level::Error, "hello command expects the language you want to say hello in as the first argument");
-                    return;
-                }
-                match args[0].as_str() {
-                    "english" => log(Level::Info, "Hello!"),
-                    "french" => log(Level::Info, "Bonjour!"),
-                    "italian" => log(Level::Info, "Ciao!"),
-                    "german" => log(Level::Info, "Hallo!"),
-                    lang => log(Level::Warn, &format!("unsupported language {lang}")),
+            "fib" => {
+                let n = args
+                    .get(0)
+                    .and_then(|n| n.parse::<u32>().ok())
+                    .ok_or_else(|| "expected a number".to_string())?;
+                let (mut a, mut b) = (0u128, 1u128);
+                for _ in 0..n {
+                    (a, b) = (b, a.checked_add(b).ok_or("the number is too big")?);
                 }
+                Ok(a.to_string())
             }
-            _ => {
-                log(Level::Error, "command not defined in this plugin");
-                return;
+            _ => Err(format!("task {name:?} not defined in this plugin")),
+        }
+    }
+
+    fn export_state() -> Vec<u8> {
+        GREETINGS.load(Ordering::Relaxed).to_le_bytes().to_vec()
+    }
+
+    fn import_state(data: Vec<u8>) {
+        let Ok(bytes) = data.try_into() else {
+            log(Level::Error, "invalid state, expected 8 bytes");
+            return;
+        };
+        GREETINGS.store(u64::from_le_bytes(bytes), Ordering::Relaxed);
+    }
+
+    fn health_check() -> bool {
+        true
+    }
+
+    fn stream_command(name: String, args: Vec<String>) -> Result<(), String> {
+        // the output is written line by line, the host prints the first lines
+        // of a large file like with `cat` before the next ones are written.
+        for line in execute(&name, &args)?.split_inclusive('\n') {
+            if !stream_write(line.as_bytes()) {
+                break;
             }
         }
+        Ok(())
+    }
+
+    fn describe_command(name: String) -> Option<String> {
+        match name.as_str() {
+            "hello" => Some(
+                "Says \"hello\" in the specified language.\n\
+                 \n\
+                 Supported languages: english, french, italian and german.\n\
+                 \n\
+                 Examples:\n\
+                 \x20 hello english   prints \"Hello!\"\n\
+                 \x20 hello french    prints \"Bonjour!\""
+                    .to_string(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn command_schema(name: String) -> Option<String> {
+        match name.as_str() {
+            "fib" => Some(r#"{ "type": "integer", "minimum": 0 }"#.to_string()),
+            _ => None,
+        }
+    }
+
+    fn list_events() -> Vec<EventDescriptor> {
+        vec![
+            EventDescriptor {
+                name: "greeted".to_string(),
+                description: "Emitted after saying hello.".to_string(),
+            },
+            EventDescriptor {
+                name: "task-done".to_string(),
+                description: "Emitted when a task is done.".to_string(),
+            },
+        ]
+    }
+
+    fn get_telemetry() -> PluginTelemetry {
+        TELEMETRY.lock().unwrap().clone()
+    }
+
+    fn on_unload() {
+        log(Level::Debug, "Goodbye my friend!");
+    }
+}
+
+/// Executes a command, returns its output or an error message.
+fn execute(name: &str, args: &[String]) -> Result<String, String> {
+    let res = execute_inner(name, args);
+
+    let mut telemetry = TELEMETRY.lock().unwrap();
+    match telemetry
+        .command_calls
+        .iter_mut()
+        .find(|(cmd, _)| cmd == name)
+    {
+        Some((_, calls)) => *calls += 1,
+        None => telemetry.command_calls.push((name.to_string(), 1)),
+    }
+    if let Err(err) = &res {
+        telemetry.errors += 1;
+        telemetry.last_error = Some(err.clone());
+    }
+    res
+}
+
+fn execute_inner(name: &str, args: &[String]) -> Result<String, String> {
+    match name {
+        "hello" => {
+            if args.len() > 1 {
+                return Err(
+                    "hello command expects the language you want to say hello in as the first argument"
+                        .to_string(),
+                );
+            }
+            let language = match args.get(0) {
+                Some(language) => language.clone(),
+                None => language_of_locale(&get_locale()).to_string(),
+            };
+            let greeting = match language.as_str() {
+                "english" => "Hello!",
+                "french" => "Bonjour!",
+                "italian" => "Ciao!",
+                "german" => "Hallo!",
+                lang => return Err(format!("unsupported language {lang}")),
+            };
+            GREETINGS.fetch_add(1, Ordering::Relaxed);
+            Ok(greeting.to_string())
+        }
+        "fib" => {
+            if args.len() != 1 {
+                return Err("fib command expects a number as the first argument".to_string());
+            }
+            let id = spawn_task("fib", args);
+            Ok(format!("spawned the task #{id}"))
+        }
+        "cat" => {
+            let Some(path) = args.get(0) else {
+                return Err(
+                    "cat command expects the path of a file as the first argument".to_string(),
+                );
+            };
+            let content = read_file(path)?;
+            Ok(String::from_utf8_lossy(&content).into_owned())
+        }
+        "task" => {
+            let Some(Ok(id)) = args.get(0).map(|id| id.parse::<u64>()) else {
+                return Err(
+                    "task command expects the id of a task as the first argument".to_string(),
+                );
+            };
+            match poll_task(id) {
+                TaskStatus::Pending => Ok(format!("task #{id} is running")),
+                TaskStatus::Done(result) => Ok(format!("task #{id} is done: {result}")),
+                TaskStatus::Error(err) => Err(format!("task #{id} failed: {err}")),
+            }
+        }
+        _ => Err("command not defined in this plugin".to_string()),
+    }
+}
+
+/// Returns the language of a locale like `fr` or `en-US`, english if the
+/// language isn't supported.
+fn language_of_locale(locale: &str) -> &'static str {
+    match locale.split(['-', '_']).next() {
+        Some("fr") => "french",
+        Some("it") => "italian",
+        Some("de") => "german",
+        _ => "english",
     }
 }
 
@@ -0,0 +1,117 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Display;
+
+use serde_json::Value;
+
+use crate::host::plugin_app::core::types::{CommandSpec, ParsedArgs as WitParsedArgs};
+
+/// The arguments of a command parsed following its [`CommandSpec`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedArgs {
+    pub positional: Vec<String>,
+    /// The boolean flags present, without the leading `--`
+    pub flags: HashSet<String>,
+    /// Maps the name of a value flag, without the leading `--`, to its value
+    pub values: HashMap<String, String>,
+}
+
+/// The arguments given to `run-command-parsed`, the flags and the values are
+/// sorted so that the plugin gets them in the same order each time.
+impl From<&ParsedArgs> for WitParsedArgs {
+    fn from(args: &ParsedArgs) -> WitParsedArgs {
+        let mut flags = args.flags.iter().cloned().collect::<Vec<_>>();
+        flags.sort();
+        let mut values = args
+            .values
+            .iter()
+            .map(|(flag, value)| (flag.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        values.sort();
+        WitParsedArgs {
+            positional: args.positional.clone(),
+            flags,
+            values,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The positional argument with this name is missing.
+    MissingArg(String),
+    /// There are more positional arguments than the spec declares.
+    UnexpectedArg(String),
+    /// The flag isn't declared by the spec.
+    UnknownFlag(String),
+    /// The value flag is the last argument, it has no value.
+    MissingValue(String),
+    /// The argument doesn't match the JSON schema of the command, with the
+    /// reason.
+    SchemaValidationFailed(String, String),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::MissingArg(name) => write!(f, "the argument <{name}> is missing"),
+            ParseError::UnexpectedArg(arg) => write!(f, "unexpected argument {arg:?}"),
+            ParseError::UnknownFlag(flag) => write!(f, "unknown flag --{flag}"),
+            ParseError::MissingValue(flag) => write!(f, "the flag --{flag} expects a value"),
+            ParseError::SchemaValidationFailed(arg, reason) => {
+                write!(
+                    f,
+                    "the argument {arg:?} doesn't match the schema of the command: {reason}"
+                )
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Checks that each argument matches the schema compiled to `validator`, an
+/// argument is parsed as JSON, or taken as a string if it isn't valid JSON,
+/// eg `5` is a number and `five` a string.
+pub fn validate_args(validator: &jsonschema::Validator, args: &[String]) -> Result<(), ParseError> {
+    for arg in args {
+        let value = serde_json::from_str(arg).unwrap_or_else(|_| Value::String(arg.clone()));
+        validator
+            .validate(&value)
+            .map_err(|err| ParseError::SchemaValidationFailed(arg.clone(), err.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Parses `args` following `spec`, the arguments starting with `--` are flags
+/// and a value flag takes the argument following it as its value.
+pub fn parse_args(spec: &CommandSpec, args: &[String]) -> Result<ParsedArgs, ParseError> {
+    let mut parsed = ParsedArgs::default();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            if parsed.positional.len() == spec.positional.len() {
+                return Err(ParseError::UnexpectedArg(arg.clone()));
+            }
+            parsed.positional.push(arg.clone());
+            continue;
+        };
+
+        if spec.flags.iter().any(|f| f == flag) {
+            parsed.flags.insert(flag.to_string());
+        } else if spec.value_flags.iter().any(|f| f == flag) {
+            let Some(value) = args.next() else {
+                return Err(ParseError::MissingValue(flag.to_string()));
+            };
+            parsed.values.insert(flag.to_string(), value.clone());
+        } else {
+            return Err(ParseError::UnknownFlag(flag.to_string()));
+        }
+    }
+
+    if let Some(name) = spec.positional.get(parsed.positional.len()) {
+        return Err(ParseError::MissingArg(name.clone()));
+    }
+    Ok(parsed)
+}
@@ -1,41 +1,615 @@
+use std::fs;
 use std::path::PathBuf;
 
+use serde::Serialize;
+
+use crate::host::hex;
+use crate::stdio::{out, outln};
 use crate::ExecutionCtx;
 
-pub fn help_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<&str>) -> Result<(), ()> {
+pub fn help_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
     if args.len() != 0 {
-        todo!("Add support for help messages of individual commands")
+        for name in &args {
+            let Some(cmd) = ctx.cmds.get(name) else {
+                outln!(ctx, "ERR: unknown command {name:?}.");
+                return Err(());
+            };
+            outln!(ctx, "usage: {}", cmd.usage);
+            outln!(
+                ctx,
+                "{}",
+                cmd.long_description.as_ref().unwrap_or(&cmd.description)
+            );
+        }
+        return Ok(());
     }
 
-    println!("All commands:");
+    outln!(ctx, "All commands:");
     let mut cmds = ctx.cmds.iter().map(|(_, cmd)| cmd).collect::<Vec<_>>();
     cmds.sort_by(|a, b| a.usage.cmp(&b.usage));
     for cmd in cmds {
-        println!(" {:16} - {}", cmd.usage, cmd.description);
+        let experimental = if cmd.experimental {
+            " [EXPERIMENTAL]"
+        } else {
+            ""
+        };
+        outln!(ctx, " {:16} - {}{experimental}", cmd.usage, cmd.description);
     }
     Ok(())
 }
 
-pub fn list_plugin_exec(ctx: &mut ExecutionCtx, _: &str, _: Vec<&str>) -> Result<(), ()> {
+pub fn list_plugin_exec(ctx: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
     let plugins = &ctx.plugins;
     if plugins.is_empty() {
-        println!("There is currently no plugins loaded!");
+        outln!(ctx, "There is currently no plugins loaded!");
+        return Ok(());
+    }
+
+    outln!(ctx, "All loaded plugins:");
+    for (name, info) in plugins {
+        let Ok(host) = ctx.validate_plugin(name) else {
+            continue;
+        };
+        let calls = host.lock().unwrap().call_count();
+        outln!(
+            ctx,
+            "  {:16} - {} ({calls} calls)",
+            info.name,
+            info.description
+        );
+    }
+    Ok(())
+}
+
+pub fn plugin_info_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(ctx, "ERR: you must give the name of a plugin.");
+        return Err(());
+    };
+    let (Some(info), Some(host)) = (ctx.plugins.get(name), ctx.hosts.get(name)) else {
+        outln!(ctx, "ERR: there is no plugin named {name:?}.");
+        return Err(());
+    };
+    let host = host.lock().unwrap();
+
+    outln!(ctx, "{} v{}", info.name, info.version);
+    outln!(ctx, "  {}", info.description);
+    outln!(ctx, "  categories: {}", info.categories.join(", "));
+    if !info.authors.is_empty() {
+        outln!(ctx, "  authors: {}", info.authors.join(", "));
+    }
+    outln!(ctx, "  commands: {}", info.commands.len());
+    let stats = host.resource_stats();
+    outln!(ctx, "  {} calls", stats.call_count);
+    outln!(ctx, "  fuel consumed: {}", stats.fuel_consumed);
+    outln!(ctx, "  peak memory: {} pages", stats.peak_memory_pages);
+    outln!(ctx, "  loaded {}s ago", host.uptime().as_secs());
+    outln!(ctx, "  sha256: {}", hex(&host.component_hash()));
+    Ok(())
+}
+
+pub fn events_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(ctx, "ERR: you must give the name of a plugin.");
+        return Err(());
+    };
+    let Some(events) = ctx.events.get(name) else {
+        outln!(ctx, "ERR: there is no plugin named {name:?}.");
+        return Err(());
+    };
+    if events.is_empty() {
+        outln!(ctx, "The plugin {name} doesn't emit any event!");
+        return Ok(());
+    }
+
+    outln!(ctx, "Events of the plugin {name}:");
+    for event in events {
+        let mut subscribers = ctx
+            .hosts
+            .iter()
+            .filter(|(_, host)| host.lock().unwrap().subscriptions().contains(&event.name))
+            .map(|(plugin, _)| plugin.as_str())
+            .collect::<Vec<_>>();
+        subscribers.sort();
+        outln!(ctx, "  {:16} - {}", event.name, event.description);
+        if !subscribers.is_empty() {
+            outln!(
+                ctx,
+                "  {:16}   subscribed by {}",
+                "",
+                subscribers.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn telemetry_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let mut plugins = match args.get(0) {
+        Some(name) if !ctx.hosts.contains_key(name) => {
+            outln!(ctx, "ERR: there is no plugin named {name:?}.");
+            return Err(());
+        }
+        Some(name) => vec![name.clone()],
+        None => ctx.hosts.keys().cloned().collect(),
+    };
+    plugins.sort();
+    if plugins.is_empty() {
+        outln!(ctx, "There is currently no plugins loaded!");
+        return Ok(());
+    }
+
+    for name in plugins {
+        let res = ctx.hosts[&name].lock().unwrap().try_call_get_telemetry();
+        let telemetry = match res {
+            Ok(telemetry) => telemetry,
+            Err(err) => {
+                outln!(ctx, "ERR: failed to get the telemetry of {name}: {err}");
+                continue;
+            }
+        };
+        outln!(ctx, "{name}: {} errors", telemetry.errors);
+        if let Some(err) = &telemetry.last_error {
+            outln!(ctx, "  last error: {err}");
+        }
+        for (cmd, calls) in &telemetry.command_calls {
+            outln!(ctx, "  {cmd:16} - {calls} calls");
+        }
+    }
+    Ok(())
+}
+
+pub fn conformance_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(ctx, "ERR: you must give the name of a plugin.");
+        return Err(());
+    };
+    let Some(host) = ctx.hosts.get(name) else {
+        outln!(ctx, "ERR: there is no plugin named {name:?}.");
+        return Err(());
+    };
+    let Err(errors) = host.lock().unwrap().assert_interface_conformance() else {
+        outln!(ctx, "The plugin {name} conforms to the plugin interface.");
+        return Ok(());
+    };
+    outln!(
+        ctx,
+        "The plugin {name} doesn't conform to the plugin interface:"
+    );
+    for err in errors {
+        outln!(ctx, "  {err}");
+    }
+    Err(())
+}
+
+pub fn latency_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(ctx, "ERR: you must give the name of a plugin.");
+        return Err(());
+    };
+    let Some(host) = ctx.hosts.get(name) else {
+        outln!(ctx, "ERR: there is no plugin named {name:?}.");
+        return Err(());
+    };
+    let host = host.lock().unwrap();
+    let (Some(p50), Some(p95), Some(p99)) =
+        (host.p50_latency(), host.p95_latency(), host.p99_latency())
+    else {
+        outln!(ctx, "The plugin {name} wasn't called yet.");
+        return Ok(());
+    };
+    outln!(ctx, "Latency of the plugin {name}:");
+    outln!(ctx, "  p50: {p50:?}");
+    outln!(ctx, "  p95: {p95:?}");
+    outln!(ctx, "  p99: {p99:?}");
+    Ok(())
+}
+
+pub fn inspect_wit_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(ctx, "ERR: you must give the name of a plugin.");
+        return Err(());
+    };
+    let Some(host) = ctx.hosts.get(name) else {
+        outln!(ctx, "ERR: there is no plugin named {name:?}.");
+        return Err(());
+    };
+    out!(ctx, "{}", host.lock().unwrap().export_interface_summary());
+    Ok(())
+}
+
+#[cfg(feature = "debug")]
+pub fn disasm_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(ctx, "ERR: you must give the name of a plugin.");
+        return Err(());
+    };
+    let Some(host) = ctx.hosts.get(name) else {
+        outln!(ctx, "ERR: there is no plugin named {name:?}.");
+        return Err(());
+    };
+    match host.lock().unwrap().try_print_wasm_disassembly() {
+        Ok(text) => outln!(ctx, "{text}"),
+        Err(err) => {
+            outln!(ctx, "ERR: failed to disassemble the plugin {name}: {err}");
+            return Err(());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "debug")]
+pub fn store_stats_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(ctx, "ERR: you must give the name of a plugin.");
+        return Err(());
+    };
+    let Some(host) = ctx.hosts.get(name) else {
+        outln!(ctx, "ERR: there is no plugin named {name:?}.");
+        return Err(());
+    };
+    let stats = host.lock().unwrap().dump_store_stats();
+
+    match stats.fuel_consumed {
+        Some(fuel) => outln!(ctx, "fuel consumed: {fuel}"),
+        None => outln!(ctx, "fuel consumed: fuel disabled"),
+    }
+    outln!(ctx, "epoch ticks: {}", stats.epoch);
+    outln!(ctx, "linear memory: {} pages", stats.linear_memory_pages);
+    Ok(())
+}
+
+pub fn search_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(keyword) = args.get(0).map(|s| s.to_lowercase()) else {
+        outln!(ctx, "ERR: you must give a keyword to search for.");
+        return Err(());
+    };
+
+    let mut found = ctx
+        .plugins
+        .values()
+        .filter(|info| {
+            info.name.to_lowercase().contains(&keyword)
+                || info.description.to_lowercase().contains(&keyword)
+                || info
+                    .categories
+                    .iter()
+                    .any(|category| category.to_lowercase().contains(&keyword))
+        })
+        .collect::<Vec<_>>();
+    if found.is_empty() {
+        outln!(ctx, "No plugin matches {keyword:?}.");
         return Ok(());
     }
 
-    println!("All loaded plugins:");
-    for (_, info) in plugins {
-        println!("  {:16} - {}", info.name, info.description);
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    for info in found {
+        outln!(ctx, "  {:16} - {}", info.name, info.description);
+    }
+    Ok(())
+}
+
+pub fn load_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let no_evict = args.first().is_some_and(|arg| arg == "--no-evict");
+    let args = if no_evict { &args[1..] } else { &args[..] };
+    let Some(path) = args.get(0).map(|s| PathBuf::from(s)) else {
+        outln!(ctx, "ERR: you must give the path to a WASM file to load.");
+        return Err(());
+    };
+    let name = match ctx.try_load_plugin(path) {
+        Ok(name) => name,
+        Err(err) => {
+            outln!(ctx, "ERR: failed to load the plugin, {err}");
+            return Err(());
+        }
+    };
+    if no_evict {
+        ctx.exempt_from_eviction(&name);
+    }
+    outln!(ctx, "Plugin loaded successfully!");
+    Ok(())
+}
+
+pub fn unload_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(ctx, "ERR: you must give the name of the plugin to unload.");
+        return Err(());
+    };
+    if !ctx.is_plugin_loaded(name) {
+        outln!(ctx, "ERR: there is no plugin named {name:?}.");
+        return Err(());
     }
+
+    ctx.plugins_to_unload.push(name.clone());
+    outln!(ctx, "Plugin unloaded successfully!");
     Ok(())
 }
 
-pub fn load_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<&str>) -> Result<(), ()> {
+pub fn load_precompiled_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
     let Some(path) = args.get(0).map(|s| PathBuf::from(s)) else {
-        println!("ERR: you must give the path to a WASM file to load.");
+        outln!(
+            ctx,
+            "ERR: you must give the path to a precompiled WASM file to load."
+        );
+        return Err(());
+    };
+    if let Err(err) = ctx.load_precompiled_plugin(path) {
+        outln!(ctx, "ERR: failed to load the plugin, {err}");
+        return Err(());
+    }
+    outln!(ctx, "Plugin loaded successfully!");
+    Ok(())
+}
+
+pub fn env_exec(ctx: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    let env = ctx.shared.env.lock().unwrap();
+    if env.is_empty() {
+        outln!(ctx, "The shell environment is empty!");
+        return Ok(());
+    }
+
+    let mut vars = env.iter().collect::<Vec<_>>();
+    vars.sort();
+    for (key, value) in vars {
+        outln!(ctx, "  {key:16} = {value}");
+    }
+    Ok(())
+}
+
+pub fn task_list_exec(ctx: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    let mut tasks = Vec::new();
+    for (plugin, host) in &ctx.hosts {
+        for (id, name) in host.lock().unwrap().tasks() {
+            tasks.push((plugin.clone(), id, name));
+        }
+    }
+    if tasks.is_empty() {
+        outln!(ctx, "There is currently no tasks running!");
+        return Ok(());
+    }
+
+    tasks.sort();
+    outln!(ctx, "All running tasks:");
+    for (plugin, id, name) in tasks {
+        outln!(ctx, "  {plugin:16} - #{id} {name}");
+    }
+    Ok(())
+}
+
+pub fn health_exec(ctx: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    if ctx.hosts.is_empty() {
+        outln!(ctx, "There is currently no plugins loaded!");
+        return Ok(());
+    }
+
+    let mut hosts = ctx.hosts.iter().collect::<Vec<_>>();
+    hosts.sort_by(|a, b| a.0.cmp(b.0));
+    outln!(ctx, "Health of the plugins:");
+    for (name, host) in hosts {
+        let status = if host.lock().unwrap().is_alive() {
+            "alive"
+        } else {
+            "dead"
+        };
+        outln!(ctx, "  {name:16} - {status}");
+    }
+    Ok(())
+}
+
+pub fn alias_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let (Some(alias), Some(target)) = (args.get(0), args.get(1)) else {
+        outln!(
+            ctx,
+            "ERR: you must give the name of the alias and the command it stands for."
+        );
+        return Err(());
+    };
+    if !ctx.cmds.contains_key(target) {
+        outln!(ctx, "ERR: unknown command {target:?}.");
+        return Err(());
+    }
+
+    ctx.new_aliases.push((alias.clone(), target.clone()));
+    Ok(())
+}
+
+pub fn macro_record_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(ctx, "ERR: you must give the name of the macro to record.");
+        return Err(());
+    };
+    if let Some((recording, _)) = &ctx.recording_macro {
+        outln!(
+            ctx,
+            "ERR: the macro {recording:?} is already being recorded."
+        );
+        return Err(());
+    }
+
+    ctx.recording_macro = Some((name.clone(), Vec::new()));
+    outln!(
+        ctx,
+        "Recording the macro {name:?}, type \"macro-end\" to stop."
+    );
+    Ok(())
+}
+
+pub fn macro_end_exec(ctx: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    let Some((name, commands)) = ctx.recording_macro.take() else {
+        outln!(ctx, "ERR: no macro is being recorded.");
+        return Err(());
+    };
+
+    outln!(
+        ctx,
+        "Macro {name:?} recorded with {} commands.",
+        commands.len()
+    );
+    ctx.new_macros.push((name, commands));
+    Ok(())
+}
+
+/// The format of the files written by `macro-save`.
+#[derive(Serialize)]
+struct MacroFile<'a> {
+    commands: Vec<MacroCommand<'a>>,
+}
+
+#[derive(Serialize)]
+struct MacroCommand<'a> {
+    name: &'a str,
+    args: &'a [String],
+}
+
+pub fn macro_save_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(ctx, "ERR: you must give the name of the macro to save.");
+        return Err(());
+    };
+    let Some(commands) = ctx.macros.get(name) else {
+        outln!(ctx, "ERR: there is no macro named {name:?}.");
+        return Err(());
+    };
+    let path = args
+        .get(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(format!("{name}.toml")));
+
+    let file = MacroFile {
+        commands: commands
+            .iter()
+            .map(|(name, args)| MacroCommand { name, args })
+            .collect(),
+    };
+    let toml = match toml::to_string_pretty(&file) {
+        Ok(toml) => toml,
+        Err(err) => {
+            outln!(ctx, "ERR: failed to serialize the macro, {err}");
+            return Err(());
+        }
+    };
+    if let Err(err) = fs::write(&path, toml) {
+        outln!(ctx, "ERR: failed to write the macro to {path:?}, {err}");
+        return Err(());
+    }
+    outln!(ctx, "Macro {name:?} saved to {path:?}");
+    Ok(())
+}
+
+pub fn aliases_exec(ctx: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    let aliases = ctx.list_aliases();
+    if aliases.is_empty() {
+        outln!(ctx, "There is currently no aliases!");
+        return Ok(());
+    }
+
+    outln!(ctx, "All aliases:");
+    for (alias, target) in aliases {
+        outln!(ctx, "  {alias:16} = {target}");
+    }
+    Ok(())
+}
+
+pub fn disabled_cmds_exec(ctx: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    let cmds = ctx.list_disabled_commands();
+    if cmds.is_empty() {
+        outln!(ctx, "There is currently no disabled commands!");
+        return Ok(());
+    }
+
+    outln!(ctx, "Disabled commands:");
+    for name in cmds {
+        outln!(ctx, "  {name}");
+    }
+    Ok(())
+}
+
+pub fn error_exec(ctx: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    match &ctx.last_error {
+        Some(err) => outln!(ctx, "The last command failed: {err}"),
+        None => outln!(ctx, "The last command succeeded."),
+    }
+    Ok(())
+}
+
+pub fn metrics_exec(ctx: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    let metrics = &ctx.metrics;
+    let mut invocations = metrics.invocations.iter().collect::<Vec<_>>();
+    invocations.sort_by(|(a_name, a_count), (b_name, b_count)| {
+        b_count.cmp(a_count).then(a_name.cmp(b_name))
+    });
+
+    outln!(ctx, "{} commands run:", metrics.total_invocations);
+    outln!(ctx, "  {:16}   {:>8} {:>8}", "command", "runs", "errors");
+    for (name, count) in invocations {
+        let errors = metrics.errors.get(name).unwrap_or(&0);
+        outln!(ctx, "  {name:16}   {count:>8} {errors:>8}");
+    }
+    Ok(())
+}
+
+pub fn watch_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let (Some(name), Some(path)) = (args.get(0), args.get(1)) else {
+        outln!(
+            ctx,
+            "ERR: you must give the name of a plugin and the path to its WASM file."
+        );
+        return Err(());
+    };
+    if let Err(err) = ctx.watch_plugin(name, PathBuf::from(path)) {
+        outln!(ctx, "ERR: failed to watch {path:?}: {err}");
+        return Err(());
+    }
+    outln!(ctx, "Watching {path:?} for changes.");
+    Ok(())
+}
+
+pub fn unwatch_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(name) = args.get(0) else {
+        outln!(
+            ctx,
+            "ERR: you must give the name of the plugin to stop watching."
+        );
+        return Err(());
+    };
+    if !ctx.unwatch_plugin(name) {
+        outln!(ctx, "ERR: the plugin {name:?} isn't watched.");
+        return Err(());
+    }
+    Ok(())
+}
+
+pub fn export_api_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(path) = args.get(0) else {
+        outln!(
+            ctx,
+            "ERR: you must give the path of the file to write the OpenAPI document to."
+        );
         return Err(());
     };
-    ctx.load_plugin(path);
-    println!("Plugin loaded successfully!");
+    if let Err(err) = fs::write(path, ctx.export_openapi_spec()) {
+        outln!(
+            ctx,
+            "ERR: failed to write the OpenAPI document to {path:?}, {err}"
+        );
+        return Err(());
+    }
+    outln!(ctx, "OpenAPI document written to {path:?}");
+    Ok(())
+}
+
+pub fn export_graph_exec(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let Some(path) = args.get(0) else {
+        outln!(
+            ctx,
+            "ERR: you must give the path of the file to write the graph to."
+        );
+        return Err(());
+    };
+    if let Err(err) = fs::write(path, ctx.export_command_graph()) {
+        outln!(ctx, "ERR: failed to write the graph to {path:?}, {err}");
+        return Err(());
+    }
+    outln!(ctx, "Graph written to {path:?}");
     Ok(())
 }
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::{CmdError, CmdRegistrationError, PluginLoadError};
+
+/// The configuration of a shell written in TOML, see
+/// [`Shell::import_from_toml`](crate::Shell::import_from_toml).
+///
+/// ```toml
+/// plugins = ["plugins/plugin_ie.wasm"]
+/// disabled-commands = ["load", "load-precompiled"]
+/// log-level = "debug"
+/// fuel-limit = 1000000
+///
+/// [aliases]
+/// hi = "hello"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ShellConfig {
+    /// The WASM files of the plugins to load, in order
+    pub plugins: Vec<PathBuf>,
+    /// Maps an alias to the command it stands for
+    pub aliases: HashMap<String, String>,
+    /// The commands that can't be run, see
+    /// [`Shell::disable_cmd`](crate::Shell::disable_cmd)
+    pub disabled_commands: Vec<String>,
+    /// The level of the logs, eg `info` or `debug`, it's up to the
+    /// application to set up its logger with it
+    pub log_level: Option<String>,
+    /// Fuel given to each plugin, see
+    /// [`Shell::with_plugin_fuel_limit`](crate::Shell::with_plugin_fuel_limit)
+    pub fuel_limit: Option<u64>,
+    /// Print the full errors of the plugins, with their wasm backtrace
+    pub verbose: bool,
+}
+
+impl ShellConfig {
+    pub fn from_toml(toml: &str) -> Result<ShellConfig, ConfigError> {
+        toml::from_str(toml).map_err(ConfigError::Parse)
+    }
+}
+
+/// Error returned when a shell can't be built from its [`ShellConfig`].
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The TOML is invalid or doesn't describe a configuration.
+    Parse(toml::de::Error),
+    /// The plugin at this path failed to load.
+    Plugin(PathBuf, PluginLoadError),
+    /// An alias can't be defined.
+    Alias(CmdRegistrationError),
+    /// A command can't be disabled.
+    DisabledCommand(CmdError),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Parse(err) => write!(f, "invalid configuration: {err}"),
+            ConfigError::Plugin(path, err) => {
+                write!(f, "failed to load the plugin {path:?}: {err}")
+            }
+            ConfigError::Alias(err) => write!(f, "{err}"),
+            ConfigError::DisabledCommand(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Parse(err) => Some(err),
+            ConfigError::Plugin(_, err) => Some(err),
+            ConfigError::Alias(err) => Some(err),
+            ConfigError::DisabledCommand(err) => Some(err),
+        }
+    }
+}
@@ -1,19 +1,37 @@
 use std::{
+    collections::{HashMap, VecDeque},
     fmt::{Debug, Display},
-    path::Path,
-    sync::{Arc, Mutex},
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
+use sha2::{Digest, Sha256};
 use wasmtime::{
-    component::{bindgen, Component, Linker},
-    Engine, Result, Store,
+    component::{
+        bindgen, types::ComponentItem, Component, ComponentNamedList, Instance, Lift, Linker, Lower,
+    },
+    Engine, Module, ResourceLimiter, Result, Store, StoreLimits, StoreLimitsBuilder, Trap,
+    UpdateDeadline,
 };
 
-bindgen!(in "wit/plugin.wit");
+bindgen!({
+    path: "wit/plugin.wit",
+    world: "core",
+});
 
 use plugin_app::core::host_app::Level;
+use plugin_app::core::types::{EventDescriptor, PluginTelemetry, TaskStatus};
 
-use crate::Shell;
+use crate::stdio::Output;
+use crate::{PluginLoadError, Shell};
 
 impl Display for Level {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -26,16 +44,820 @@ impl Display for Level {
     }
 }
 
-struct PluginState;
+/// Two plugin infos are equal if they have the same name and version, the
+/// other fields are ignored.
+impl PartialEq for PluginInfo {
+    fn eq(&self, other: &PluginInfo) -> bool {
+        self.name == other.name && self.version == other.version
+    }
+}
+
+impl Eq for PluginInfo {}
+
+impl Hash for PluginInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.version.hash(state);
+    }
+}
+
+impl PluginInfo {
+    /// Returns `true` if both are versions of the same plugin, but different
+    /// ones.
+    pub fn conflicts_with(&self, other: &PluginInfo) -> bool {
+        self.name == other.name && self.version != other.version
+    }
+}
+
+/// Number of plugin hosts alive in the process.
+static LIVE_PLUGINS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of plugin hosts alive above which loading a plugin emits a warning,
+/// each one has its own store and they are costly in memory.
+pub const LIVE_PLUGINS_WARN_THRESHOLD: usize = 64;
+
+/// Returns the number of plugin hosts alive in the process.
+pub fn live_plugin_count() -> usize {
+    LIVE_PLUGINS.load(Ordering::SeqCst)
+}
+
+/// Returns `true` if the file at `path` is owned by the user running the
+/// process, only those files are trusted enough to be deserialized as
+/// precompiled components.
+#[cfg(unix)]
+pub fn is_trusted_path(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match fs::metadata(path) {
+        // SAFETY: `geteuid` is always successful and has no side effect.
+        Ok(metadata) => metadata.uid() == unsafe { libc::geteuid() },
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn is_trusted_path(_path: &Path) -> bool {
+    false
+}
+
+/// The WASM proposals enabled by an engine, see
+/// [`PluginHost::supported_wasm_features`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WasmFeatureSet {
+    pub simd: bool,
+    pub threads: bool,
+    pub gc: bool,
+    pub exceptions: bool,
+}
+
+impl WasmFeatureSet {
+    /// Returns the features enabled by `engine`. The configuration of an
+    /// engine can't be read back, so each feature is detected by validating
+    /// a small module using it.
+    pub fn of_engine(engine: &Engine) -> WasmFeatureSet {
+        const HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        let validates =
+            |sections: &[u8]| Module::validate(engine, &[&HEADER[..], sections].concat()).is_ok();
+
+        WasmFeatureSet {
+            // (func (result v128) v128.const i64x2 0 0)
+            simd: validates(
+                &[
+                    [0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7b].as_slice(),
+                    &[0x03, 0x02, 0x01, 0x00],
+                    &[0x0a, 0x16, 0x01, 0x14, 0x00, 0xfd, 0x0c],
+                    &[0; 16],
+                    &[0x0b],
+                ]
+                .concat(),
+            ),
+            // (memory 1 1 shared)
+            threads: validates(&[0x05, 0x04, 0x01, 0x03, 0x01, 0x01]),
+            // (type (struct))
+            gc: validates(&[0x01, 0x03, 0x01, 0x5f, 0x00]),
+            // (type (func)) (tag (type 0))
+            exceptions: validates(&[
+                0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x0d, 0x03, 0x01, 0x00, 0x00,
+            ]),
+        }
+    }
+
+    /// Is the feature named `feature` enabled? `None` if it isn't one of
+    /// `simd`, `threads`, `gc` and `exceptions`.
+    pub fn supports(&self, feature: &str) -> Option<bool> {
+        match feature {
+            "simd" => Some(self.simd),
+            "threads" => Some(self.threads),
+            "gc" => Some(self.gc),
+            "exceptions" => Some(self.exceptions),
+            _ => None,
+        }
+    }
+}
+
+/// Name of the custom section containing the version of a plugin, see
+/// [`PluginHost::version_string`].
+pub const VERSION_SECTION: &str = "plugin-version";
+
+/// Returns the content of the [`VERSION_SECTION`] custom section of the
+/// component, or of one of its modules, `None` if there is none or if it
+/// isn't UTF-8.
+fn read_version_section(bytes: &[u8]) -> Option<String> {
+    for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+        // eg the bytes are a precompiled component.
+        let Ok(payload) = payload else {
+            return None;
+        };
+        if let wasmparser::Payload::CustomSection(section) = payload {
+            if section.name() == VERSION_SECTION {
+                return String::from_utf8(section.data().to_vec()).ok();
+            }
+        }
+    }
+    None
+}
+
+/// State shared between the shell and all the plugin hosts.
+#[derive(Debug, Clone, Default)]
+pub struct SharedCtx {
+    /// The shell environment, it is kept when plugins are unloaded.
+    pub env: Arc<Mutex<HashMap<String, String>>>,
+    /// If `true` the plugins can't read the environment of the host process.
+    pub sandboxed_env: bool,
+    /// Where the logs of the plugins are written.
+    pub output: Output,
+    /// The locale of the user, eg `fr` or `en-US`, `en` if `None`.
+    pub locale: Option<String>,
+    /// The plugins can only read the files in this directory, they can read
+    /// any file if `None`.
+    pub sandbox_dir: Option<PathBuf>,
+}
+
+/// A task spawned by a plugin, executed by the `run-task` export of a new
+/// instance of the plugin.
+struct Task {
+    name: String,
+    handle: JoinHandle<Result<String, String>>,
+}
+
+/// Maximum number of call durations kept to compute the latencies of a
+/// plugin, see [`PluginHost::p50_latency`].
+pub const MAX_LATENCIES_LEN: usize = 1000;
+
+/// Maximum number of events kept in the event log of a plugin, the oldest
+/// events are dropped.
+pub const MAX_EVENT_LOG_LEN: usize = 1000;
+
+/// Maximum number of epochs the call to `init` can last, 5 seconds with an
+/// epoch incremented every second, see [`PluginHost::try_call_init`].
+pub const INIT_MAX_EPOCHS: u64 = 5;
+
+/// Size of the buffer between a plugin streaming its output and the reader,
+/// in bytes, see [`PluginHost::call_run_command_stream`].
+#[cfg(feature = "async")]
+pub const STREAM_BUFFER_SIZE: usize = 4096;
+
+/// A call of the plugin to an import of the host, see
+/// [`PluginHost::flush_event_log`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginEvent {
+    Log { level: Level, msg: String },
+    EnvGet(String),
+    EnvSet(String, String),
+    SpawnTask { name: String, args: Vec<String> },
+    PollTask(u64),
+    SubscribeEvent(String),
+    GetLocale,
+    ReadFile(String),
+    StreamWrite(usize),
+}
+
+/// Limits of the resources used by a store and the extra imports of its
+/// linker, they apply to the stores of the tasks of the plugin too.
+#[derive(Debug, Clone, Default)]
+struct StoreConfig {
+    /// Fuel given to the store, the engine must be configured to consume fuel
+    fuel: Option<u64>,
+    /// Maximum size of a linear memory, in bytes
+    memory_limit: Option<usize>,
+    /// Imports defined in addition to the imports of the WIT world
+    imports: Vec<ExtraImport>,
+    /// Maximum number of messages logged by a call to the plugin
+    max_log_per_call: Option<usize>,
+}
+
+/// A function defined in the linker of a plugin, see
+/// [`PluginHostBuilder::add_import`].
+#[derive(Clone)]
+struct ExtraImport {
+    interface: String,
+    func: String,
+    define: Arc<dyn Fn(&mut Linker<PluginState>) -> Result<()> + Send + Sync>,
+}
+
+impl Debug for ExtraImport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtraImport")
+            .field("interface", &self.interface)
+            .field("func", &self.func)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The limits of a store, it also records the peak size of its memories.
+struct Limiter {
+    limits: StoreLimits,
+    /// Largest size a linear memory grew to, in bytes
+    peak_memory: usize,
+    /// Total size of the linear memories, in bytes
+    memory: usize,
+}
+
+impl ResourceLimiter for Limiter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> Result<bool> {
+        let allowed = self.limits.memory_growing(current, desired, maximum)?;
+        if allowed {
+            self.peak_memory = self.peak_memory.max(desired);
+            self.memory += desired - current;
+        }
+        Ok(allowed)
+    }
+
+    fn table_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> Result<bool> {
+        self.limits.table_growing(current, desired, maximum)
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.limits.tables()
+    }
+
+    fn memories(&self) -> usize {
+        self.limits.memories()
+    }
+}
+
+/// Receives the chunks of output written by `stream-command`, see
+/// [`PluginHost::try_call_stream_command`].
+type StreamSink = Box<dyn FnMut(&[u8]) -> bool + Send>;
+
+struct PluginState {
+    shared: SharedCtx,
+    /// Used to instantiate the plugin again to execute its tasks
+    engine: Engine,
+    component: Component,
+    config: StoreConfig,
+    limits: Limiter,
+    /// The running call is interrupted at the next epoch after this instant
+    deadline: Option<Instant>,
+    /// Number of epochs the running call can still last
+    epochs_left: Option<u64>,
+    /// Number of epoch ticks observed while the plugin was running
+    epochs: u64,
+    /// The messages logged after this number in a call are dropped
+    max_log_per_call: Option<usize>,
+    /// Number of messages logged by the running call
+    logs_in_call: usize,
+    /// The events the plugin subscribed to
+    subscriptions: Vec<String>,
+    /// The calls to the imports, the oldest first
+    event_log: VecDeque<PluginEvent>,
+    /// Maps a task id to the running task
+    tasks: HashMap<u64, Task>,
+    next_task_id: u64,
+    /// Where the output of the running `stream-command` call goes
+    stream: Option<StreamSink>,
+}
+
+impl PluginState {
+    fn new(
+        engine: &Engine,
+        shared: SharedCtx,
+        component: &Component,
+        config: StoreConfig,
+    ) -> PluginState {
+        let mut limits = StoreLimitsBuilder::new();
+        if let Some(memory_limit) = config.memory_limit {
+            limits = limits.memory_size(memory_limit);
+        }
+        let max_log_per_call = config.max_log_per_call;
+        PluginState {
+            shared,
+            engine: engine.clone(),
+            component: component.clone(),
+            config,
+            limits: Limiter {
+                limits: limits.build(),
+                peak_memory: 0,
+                memory: 0,
+            },
+            epochs: 0,
+            max_log_per_call,
+            logs_in_call: 0,
+            deadline: None,
+            epochs_left: None,
+            subscriptions: Vec::new(),
+            event_log: VecDeque::new(),
+            tasks: HashMap::new(),
+            next_task_id: 0,
+            stream: None,
+        }
+    }
+
+    fn record(&mut self, event: PluginEvent) {
+        if self.event_log.len() == MAX_EVENT_LOG_LEN {
+            self.event_log.pop_front();
+        }
+        self.event_log.push_back(event);
+    }
+}
 
 impl plugin_app::core::host_app::Host for PluginState {
     fn log(&mut self, lvl: Level, msg: String) -> () {
-        println!("{lvl}: {msg}")
+        self.logs_in_call += 1;
+        if self
+            .max_log_per_call
+            .is_none_or(|max| self.logs_in_call <= max)
+        {
+            self.shared.output.write_line(format_args!("{lvl}: {msg}"));
+        }
+        self.record(PluginEvent::Log { level: lvl, msg });
+    }
+
+    fn env_set(&mut self, key: String, value: String) -> () {
+        self.record(PluginEvent::EnvSet(key.clone(), value.clone()));
+        self.shared.env.lock().unwrap().insert(key, value);
+    }
+
+    fn env_get(&mut self, key: String) -> Option<String> {
+        self.record(PluginEvent::EnvGet(key.clone()));
+        let value = self.shared.env.lock().unwrap().get(&key).cloned();
+        if value.is_some() || self.shared.sandboxed_env {
+            return value;
+        }
+        std::env::var(&key).ok()
+    }
+
+    fn spawn_task(&mut self, name: String, args: Vec<String>) -> u64 {
+        self.record(PluginEvent::SpawnTask {
+            name: name.clone(),
+            args: args.clone(),
+        });
+        let id = self.next_task_id;
+        self.next_task_id += 1;
+
+        let engine = self.engine.clone();
+        let shared = self.shared.clone();
+        let component = self.component.clone();
+        let config = self.config.clone();
+        let task_name = name.clone();
+        let handle = thread::spawn(move || -> Result<String, String> {
+            let (_, mut store, _, instance) =
+                instantiate(&engine, shared, &component, config).map_err(|err| err.to_string())?;
+            // `run-task` is an optional export of the `plugin` world.
+            let func = instance
+                .get_typed_func::<(&str, &[String]), (Result<String, String>,)>(
+                    &mut store, "run-task",
+                )
+                .map_err(|_| "the plugin doesn't export run-task".to_string())?;
+            let (res,) = func
+                .call(&mut store, (task_name.as_str(), args.as_slice()))
+                .map_err(|err| err.to_string())?;
+            func.post_return(&mut store)
+                .map_err(|err| err.to_string())?;
+            res
+        });
+
+        self.tasks.insert(id, Task { name, handle });
+        id
+    }
+
+    fn subscribe_event(&mut self, event_name: String) -> () {
+        self.record(PluginEvent::SubscribeEvent(event_name.clone()));
+        if !self.subscriptions.contains(&event_name) {
+            self.subscriptions.push(event_name);
+        }
+    }
+
+    fn get_locale(&mut self) -> String {
+        self.record(PluginEvent::GetLocale);
+        self.shared
+            .locale
+            .clone()
+            .unwrap_or_else(|| "en".to_string())
+    }
+
+    fn read_file(&mut self, path: String) -> Result<Vec<u8>, String> {
+        self.record(PluginEvent::ReadFile(path.clone()));
+        // without a sandbox the plugins can't read any file of the host.
+        let Some(dir) = &self.shared.sandbox_dir else {
+            return Err("permission denied: no sandbox directory".to_string());
+        };
+
+        // the paths are canonicalized so that `..` and the symbolic links
+        // can't escape the sandbox.
+        let file = dir
+            .join(&path)
+            .canonicalize()
+            .map_err(|err| format!("{path}: {err}"))?;
+        let dir = dir
+            .canonicalize()
+            .map_err(|err| format!("{dir:?}: {err}"))?;
+        if !file.starts_with(&dir) {
+            return Err(format!(
+                "{path}: permission denied, the file is outside of the sandbox"
+            ));
+        }
+        fs::read(&file).map_err(|err| format!("{path}: {err}"))
+    }
+
+    fn stream_write(&mut self, chunk: Vec<u8>) -> bool {
+        self.record(PluginEvent::StreamWrite(chunk.len()));
+        // outside of `stream-command` nobody reads the output.
+        self.stream.as_mut().is_some_and(|sink| sink(&chunk))
+    }
+
+    fn poll_task(&mut self, id: u64) -> TaskStatus {
+        self.record(PluginEvent::PollTask(id));
+        let Some(task) = self.tasks.get(&id) else {
+            return TaskStatus::Error(format!("there is no task with the id {id}"));
+        };
+        if !task.handle.is_finished() {
+            return TaskStatus::Pending;
+        }
+
+        let task = self.tasks.remove(&id).unwrap();
+        match task.handle.join() {
+            Ok(Ok(result)) => TaskStatus::Done(result),
+            Ok(Err(err)) => TaskStatus::Error(err),
+            Err(_) => TaskStatus::Error(format!("the task {:?} panicked", task.name)),
+        }
     }
 }
 
 impl plugin_app::core::types::Host for PluginState {}
 
+/// Instantiates the component in a new store.
+fn instantiate(
+    engine: &Engine,
+    shared: SharedCtx,
+    component: &Component,
+    config: StoreConfig,
+) -> Result<(Linker<PluginState>, Store<PluginState>, Core, Instance)> {
+    let mut linker = Linker::new(engine);
+    Core::add_to_linker(&mut linker, |state: &mut PluginState| state)?;
+    for import in &config.imports {
+        (import.define)(&mut linker)?;
+    }
+
+    let fuel = config.fuel;
+    let mut store = Store::new(engine, PluginState::new(engine, shared, component, config));
+    store.limiter(|state| &mut state.limits);
+    if let Some(fuel) = fuel {
+        store.set_fuel(fuel)?;
+    }
+    // the epoch is shared by all the stores of the engine, a store only traps
+    // if its own deadline expired or if it ran out of epochs.
+    store.set_epoch_deadline(1);
+    store.epoch_deadline_callback(|mut ctx| {
+        let state = ctx.data_mut();
+        state.epochs += 1;
+        let timed_out = state
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        let out_of_epochs = state.epochs_left.as_mut().is_some_and(|left| {
+            *left = left.saturating_sub(1);
+            *left == 0
+        });
+        if timed_out || out_of_epochs {
+            return Err(Trap::Interrupt.into());
+        }
+        Ok(UpdateDeadline::Continue(1))
+    });
+
+    // the instance is kept to look up the optional exports.
+    let instance = linker.instantiate(&mut store, component)?;
+    let bindings = Core::new(&mut store, &instance)?;
+
+    Ok((linker, store, bindings, instance))
+}
+
+/// A thread incrementing the epoch of an engine, it is stopped when dropped,
+/// see [`PluginHost::start_epoch_thread`].
+#[derive(Debug)]
+pub struct EpochThread {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for EpochThread {
+    fn drop(&mut self) {
+        // dropping the sender wakes the thread up and stops it.
+        drop(self.stop.take());
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+    }
+}
+
+/// Options of a single call to a command of a plugin, see
+/// [`PluginHost::call_run_command_opts`].
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Maximum duration of the call, instead of the timeout of the host
+    pub timeout: Option<Duration>,
+    /// Fuel given to the store before the call, the engine must be
+    /// configured to consume fuel
+    pub fuel_override: Option<u64>,
+    /// Return the output of the command instead of logging it, with
+    /// `run-command-with-output`
+    pub capture_output: bool,
+}
+
+/// The result of a call to a command of a plugin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunResult {
+    /// The output of the command, only if it was captured
+    pub output: Option<String>,
+    /// How long the call lasted
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+pub enum PluginCallError {
+    /// The command returned an error message, only when the output is
+    /// captured.
+    CommandFailed(String),
+    /// The call failed, eg because of a trap.
+    Wasm(wasmtime::Error),
+}
+
+impl Display for PluginCallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginCallError::CommandFailed(msg) => write!(f, "{msg}"),
+            PluginCallError::Wasm(err) => write!(f, "{}", err.root_cause()),
+        }
+    }
+}
+
+impl std::error::Error for PluginCallError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PluginCallError::CommandFailed(_) => None,
+            PluginCallError::Wasm(err) => Some(&**err),
+        }
+    }
+}
+
+/// Options of a plugin host, kept to build it again when it is reloaded.
+#[derive(Debug, Clone, Default)]
+struct HostOptions {
+    /// Directory where the compiled components are cached
+    cache_dir: Option<PathBuf>,
+    /// Is the file a precompiled component?
+    precompiled: bool,
+    store: StoreConfig,
+    /// Maximum duration of a call to the plugin
+    timeout: Option<Duration>,
+    /// Maximum number of epochs a call to the plugin can last
+    max_epochs: Option<u64>,
+    /// Number of times `init` is called again after it failed
+    init_retries: u32,
+    /// Waited before calling `init` again
+    init_backoff: Duration,
+    /// Fraction of the memory limit above which a warning is logged after a
+    /// command
+    memory_warn_threshold: Option<f32>,
+}
+
+/// The resources used by a plugin since it was loaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceStats {
+    /// Fuel consumed by the calls to the plugin, it stays at 0 if the engine
+    /// doesn't consume fuel
+    pub fuel_consumed: u64,
+    /// Largest size of the memory of the plugin, in WASM pages of 64KiB
+    pub peak_memory_pages: u32,
+    /// Number of commands of the plugin that were run
+    pub call_count: u64,
+}
+
+/// The state of the store of a plugin, see [`PluginHost::dump_store_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    /// Fuel consumed by the calls to the plugin, `None` if the engine doesn't
+    /// consume fuel
+    pub fuel_consumed: Option<u64>,
+    /// Number of epoch ticks observed while the plugin was running, the
+    /// epoch of the engine can't be read
+    pub epoch: u64,
+    /// Total size of the linear memories of the plugin, in WASM pages of
+    /// 64KiB
+    pub linear_memory_pages: u32,
+}
+
+/// Builds a [`PluginHost`] with optional limits on the resources it uses.
+///
+/// The fuel limit and the timeout need an engine configured with
+/// `Config::consume_fuel` and `Config::epoch_interruption` respectively.
+#[derive(Debug, Clone)]
+pub struct PluginHostBuilder {
+    engine: Engine,
+    shared: SharedCtx,
+    path: PathBuf,
+    options: HostOptions,
+}
+
+impl PluginHostBuilder {
+    pub fn new(engine: Engine, shared: SharedCtx, path: impl AsRef<Path>) -> PluginHostBuilder {
+        PluginHostBuilder {
+            engine,
+            shared,
+            path: path.as_ref().to_path_buf(),
+            options: HostOptions::default(),
+        }
+    }
+
+    /// Caches the compiled component in `dir`, so that the next loads of the
+    /// same WASM file don't compile it again.
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> PluginHostBuilder {
+        self.options.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// The file is a precompiled component, see
+    /// [`PluginHost::try_new_precompiled`].
+    pub fn with_precompiled(mut self, precompiled: bool) -> PluginHostBuilder {
+        self.options.precompiled = precompiled;
+        self
+    }
+
+    /// Traps once the plugin consumed `fuel` units of fuel, in total.
+    pub fn with_fuel_limit(mut self, fuel: u64) -> PluginHostBuilder {
+        self.options.store.fuel = Some(fuel);
+        self
+    }
+
+    /// Limits each linear memory of the plugin to `pages` WASM pages of 64KiB.
+    pub fn with_memory_limit(mut self, pages: usize) -> PluginHostBuilder {
+        self.options.store.memory_limit = Some(pages * 0x10000);
+        self
+    }
+
+    /// Interrupts the calls to the plugin that last longer than `timeout`, the
+    /// deadline is checked each time the epoch is incremented, eg by
+    /// [`PluginHost::start_epoch_thread`].
+    pub fn with_timeout(mut self, timeout: Duration) -> PluginHostBuilder {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Interrupts the calls to the plugin that last longer than `max_epochs`
+    /// epochs, the epoch must be incremented regularly, eg by
+    /// [`PluginHost::start_epoch_thread`].
+    pub fn with_max_epochs(mut self, max_epochs: u64) -> PluginHostBuilder {
+        self.options.max_epochs = Some(max_epochs);
+        self
+    }
+
+    /// Defines the function `func` of the interface `interface`, eg
+    /// `plugin-app:core/mock`, in addition to the imports of the WIT world.
+    /// It makes it possible to test a plugin against a mock host providing
+    /// imports that aren't in the canonical WIT.
+    ///
+    /// The parameters and the results are tuples, eg `(String,)`. The
+    /// interface must not be one of the interfaces of the WIT world.
+    pub fn add_import<Params, Return>(
+        mut self,
+        interface: &str,
+        func: &str,
+        f: impl Fn(Params) -> Result<Return> + Send + Sync + 'static,
+    ) -> PluginHostBuilder
+    where
+        Params: ComponentNamedList + Lift + 'static,
+        Return: ComponentNamedList + Lower + 'static,
+    {
+        let f = Arc::new(f);
+        let (interface_name, func_name) = (interface.to_string(), func.to_string());
+        let define = move |linker: &mut Linker<PluginState>| {
+            let f = f.clone();
+            linker
+                .root()
+                .instance(&interface_name)?
+                .func_wrap(&func_name, move |_, params: Params| f(params))
+        };
+
+        self.options.store.imports.push(ExtraImport {
+            interface: interface.to_string(),
+            func: func.to_string(),
+            define: Arc::new(define),
+        });
+        self
+    }
+
+    /// Drops the messages logged by a call to the plugin after the first
+    /// `max` ones, a single warning is printed at the end of the call instead.
+    pub fn with_max_log_per_call(mut self, max: usize) -> PluginHostBuilder {
+        self.options.store.max_log_per_call = Some(max);
+        self
+    }
+
+    /// Logs a warning after a command if the plugin uses more than
+    /// `threshold` of its memory limit, eg `0.8` for 80%. It has no effect
+    /// without a memory limit, see [`PluginHostBuilder::with_memory_limit`].
+    pub fn with_memory_warn_threshold(mut self, threshold: f32) -> PluginHostBuilder {
+        self.options.memory_warn_threshold = Some(threshold);
+        self
+    }
+
+    /// Calls `init` again, up to `retries` times, if it fails, eg because of
+    /// a transient error under memory pressure. Each retry waits `backoff`
+    /// and instantiates the plugin in a new store.
+    pub fn with_init_retries(mut self, retries: u32, backoff: Duration) -> PluginHostBuilder {
+        self.options.init_retries = retries;
+        self.options.init_backoff = backoff;
+        self
+    }
+
+    pub fn build(self) -> Result<PluginHost> {
+        if self.options.precompiled && !is_trusted_path(&self.path) {
+            anyhow::bail!(
+                "{:?} isn't owned by the current user, it can't be trusted",
+                self.path
+            );
+        }
+
+        let bytes = fs::read(&self.path)?;
+        let hash: [u8; 32] = Sha256::digest(&bytes).into();
+        let component = if self.options.precompiled {
+            // SAFETY: the precompiled code is executed without being
+            // validated, the file is owned by the user running the shell so
+            // it is as trusted as the shell itself.
+            unsafe { Component::deserialize(&self.engine, &bytes)? }
+        } else if let Some(cache_dir) = &self.options.cache_dir {
+            self.compile_cached(cache_dir, &bytes, &hash)?
+        } else {
+            Component::new(&self.engine, &bytes)?
+        };
+        if let Err(errors) = check_conformance(&self.engine, &component) {
+            let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+            anyhow::bail!(
+                "{:?} isn't a valid plugin: {}",
+                self.path,
+                errors.join(", ")
+            );
+        }
+        PluginHost::from_component(
+            self.engine,
+            self.shared,
+            component,
+            self.path,
+            hash,
+            bytes,
+            self.options,
+        )
+    }
+
+    /// Loads the component from the cache, or compiles it and caches it. The
+    /// component is cached under the hash of the WASM file.
+    fn compile_cached(&self, cache_dir: &Path, bytes: &[u8], hash: &[u8; 32]) -> Result<Component> {
+        let cached = cache_dir.join(format!("{}.cwasm", hex(hash)));
+
+        if is_trusted_path(&cached) {
+            // SAFETY: same as for precompiled plugins, and the cached file
+            // was written by this function.
+            match unsafe { Component::deserialize_file(&self.engine, &cached) } {
+                Ok(component) => return Ok(component),
+                // eg the cache was written by another version of wasmtime.
+                Err(err) => tracing::warn!("the cached component {cached:?} is invalid: {err}"),
+            }
+        }
+
+        let component = Component::new(&self.engine, bytes)?;
+        let serialized = component.serialize()?;
+        if let Err(err) =
+            fs::create_dir_all(cache_dir).and_then(|()| fs::write(&cached, serialized))
+        {
+            tracing::warn!("failed to cache the component in {cached:?}: {err}");
+        }
+        Ok(component)
+    }
+}
+
 /// Anything needed to execute the WASM plugin.
 #[allow(unused)] // TODO: this is temporary.
 pub struct PluginHost {
@@ -43,6 +865,29 @@ pub struct PluginHost {
     linker: Linker<PluginState>,
     store: Store<PluginState>,
     bindings: Core,
+    /// The instance of the component, the bindings only have the exports of
+    /// the `core` world
+    instance: Instance,
+    /// Path of the WASM file the plugin was loaded from
+    path: PathBuf,
+    /// SHA-256 of the WASM file
+    hash: [u8; 32],
+    /// Content of the WASM file
+    raw_bytes: Vec<u8>,
+    /// Content of the version custom section, read when the plugin is loaded
+    version: Option<String>,
+    /// Number of times `run-command` was called
+    call_count: u64,
+    /// Fuel consumed by the calls to the plugin
+    fuel_consumed: u64,
+    /// Durations of the last calls to `init` and to the commands, the oldest
+    /// first
+    latencies: VecDeque<Duration>,
+    /// When the plugin was loaded, or reloaded for the last time
+    loaded_at: Instant,
+    /// When a command of the plugin was run for the last time
+    last_called_at: Option<Instant>,
+    options: HostOptions,
 }
 
 impl Debug for PluginHost {
@@ -51,38 +896,1027 @@ impl Debug for PluginHost {
     }
 }
 
+impl Drop for PluginHost {
+    fn drop(&mut self) {
+        LIVE_PLUGINS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 impl PluginHost {
-    pub fn try_new(engine: Engine, path: impl AsRef<Path>) -> Result<PluginHost> {
-        let component = Component::from_file(&engine, path)?;
+    pub fn try_new(
+        engine: Engine,
+        shared: SharedCtx,
+        path: impl AsRef<Path>,
+    ) -> Result<PluginHost> {
+        PluginHostBuilder::new(engine, shared, path).build()
+    }
 
-        let mut linker = Linker::new(&engine);
-        Core::add_to_linker(&mut linker, |state: &mut PluginState| state)?;
+    /// Loads a plugin precompiled with `Component::serialize` or `wasmtime
+    /// compile` (a `.cwasm` file), it skips the compilation entirely. The file
+    /// must be owned by the current user, see [`is_trusted_path`].
+    pub fn try_new_precompiled(
+        engine: Engine,
+        shared: SharedCtx,
+        path: impl AsRef<Path>,
+    ) -> Result<PluginHost> {
+        PluginHostBuilder::new(engine, shared, path)
+            .with_precompiled(true)
+            .build()
+    }
+
+    fn from_component(
+        engine: Engine,
+        shared: SharedCtx,
+        component: Component,
+        path: PathBuf,
+        hash: [u8; 32],
+        raw_bytes: Vec<u8>,
+        options: HostOptions,
+    ) -> Result<PluginHost> {
+        let (linker, store, bindings, instance) =
+            instantiate(&engine, shared, &component, options.store.clone())?;
 
-        let mut store = Store::new(&engine, PluginState {});
-        let bindings = Core::instantiate(&mut store, &component, &linker)?;
+        let count = LIVE_PLUGINS.fetch_add(1, Ordering::SeqCst) + 1;
+        if count > LIVE_PLUGINS_WARN_THRESHOLD {
+            tracing::warn!("{count} plugin hosts are alive, each of them has its own store");
+        }
 
         Ok(PluginHost {
             component,
             linker,
             store,
             bindings,
+            instance,
+            path,
+            hash,
+            version: read_version_section(&raw_bytes),
+            raw_bytes,
+            call_count: 0,
+            fuel_consumed: 0,
+            latencies: VecDeque::new(),
+            loaded_at: Instant::now(),
+            last_called_at: None,
+            options,
         })
     }
 
+    /// Reloads the plugin from the WASM file at `path`, with the same options,
+    /// and initializes it again, the current instance is kept if anything
+    /// fails.
+    pub fn try_reload(&mut self, path: impl AsRef<Path>) -> Result<PluginInfo> {
+        let mut host = PluginHostBuilder {
+            engine: self.store.engine().clone(),
+            shared: self.store.data().shared.clone(),
+            path: path.as_ref().to_path_buf(),
+            options: self.options.clone(),
+        }
+        .build()?;
+        let info = host.try_call_init()?;
+        *self = host;
+        Ok(info)
+    }
+
+    /// Replaces the plugin with the one in the WASM file at `new_path`, the
+    /// host keeps its options. The current version is kept if the new one
+    /// fails to load or to initialize.
+    pub fn upgrade(&mut self, new_path: &Path) -> Result<PluginInfo, PluginLoadError> {
+        self.try_reload(new_path)
+            .map_err(|err| match err.downcast::<std::io::Error>() {
+                Ok(err) => PluginLoadError::Io(err),
+                Err(err) => PluginLoadError::Wasm(err),
+            })
+    }
+
     #[track_caller]
-    pub fn new(engine: Engine, path: impl AsRef<Path>) -> PluginHost {
-        PluginHost::try_new(engine, path).unwrap()
+    pub fn new(engine: Engine, shared: SharedCtx, path: impl AsRef<Path>) -> PluginHost {
+        PluginHost::try_new(engine, shared, path).unwrap()
+    }
+
+    /// Calls `init`, it traps if it lasts longer than [`INIT_MAX_EPOCHS`]
+    /// epochs, or than the maximum number of epochs of the host if it is
+    /// lower. The engine must be configured with `Config::epoch_interruption`
+    /// and its epoch incremented, eg by [`PluginHost::start_epoch_thread`].
+    ///
+    /// If it fails, it is retried in a new store as many times as set with
+    /// [`PluginHostBuilder::with_init_retries`], the last error is returned.
+    pub fn try_call_init(&mut self) -> Result<PluginInfo> {
+        let mut retries = self.options.init_retries;
+        loop {
+            match self.call_init_once() {
+                Err(err) if retries > 0 => {
+                    tracing::warn!("the init of {:?} failed, retrying: {err}", self.path);
+                    retries -= 1;
+                    thread::sleep(self.options.init_backoff);
+                    self.reset_store()?;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Instantiates the plugin again in a new store, the state of the
+    /// current one may be poisoned.
+    fn reset_store(&mut self) -> Result<()> {
+        let engine = self.store.engine().clone();
+        let shared = self.store.data().shared.clone();
+        let (linker, store, bindings, instance) =
+            instantiate(&engine, shared, &self.component, self.options.store.clone())?;
+        self.linker = linker;
+        self.store = store;
+        self.bindings = bindings;
+        self.instance = instance;
+        Ok(())
+    }
+
+    fn call_init_once(&mut self) -> Result<PluginInfo> {
+        let start = Instant::now();
+        let max_epochs = self
+            .options
+            .max_epochs
+            .map_or(INIT_MAX_EPOCHS, |max| max.min(INIT_MAX_EPOCHS));
+        let res = self.with_limits(self.options.timeout, Some(max_epochs), |host| {
+            host.bindings.call_init(&mut host.store)
+        });
+        self.record_latency(start.elapsed());
+        res
+    }
+
+    fn record_latency(&mut self, latency: Duration) {
+        if self.latencies.len() == MAX_LATENCIES_LEN {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+    }
+
+    /// Returns the latency under which `percent`% of the last calls are, `None`
+    /// if the plugin wasn't called yet.
+    fn latency_percentile(&self, percent: usize) -> Option<Duration> {
+        let mut latencies = self.latencies.iter().copied().collect::<Vec<_>>();
+        latencies.sort();
+        let rank = (latencies.len() * percent).div_ceil(100);
+        latencies.get(rank.checked_sub(1)?).copied()
+    }
+
+    /// Returns the median duration of the last calls to `init` and to the
+    /// commands of the plugin.
+    pub fn p50_latency(&self) -> Option<Duration> {
+        self.latency_percentile(50)
+    }
+
+    pub fn p95_latency(&self) -> Option<Duration> {
+        self.latency_percentile(95)
+    }
+
+    pub fn p99_latency(&self) -> Option<Duration> {
+        self.latency_percentile(99)
+    }
+
+    /// Calls `f`, the plugin traps if the call lasts longer than `timeout`,
+    /// or than the maximum number of epochs of the host.
+    fn with_deadline<T>(
+        &mut self,
+        timeout: Option<Duration>,
+        f: impl FnOnce(&mut PluginHost) -> T,
+    ) -> T {
+        self.with_limits(timeout, self.options.max_epochs, f)
+    }
+
+    /// Calls `f`, the plugin traps if the call lasts longer than `timeout`,
+    /// or than `max_epochs` epochs.
+    fn with_limits<T>(
+        &mut self,
+        timeout: Option<Duration>,
+        max_epochs: Option<u64>,
+        f: impl FnOnce(&mut PluginHost) -> T,
+    ) -> T {
+        let state = self.store.data_mut();
+        state.epochs_left = max_epochs;
+        state.logs_in_call = 0;
+        // the deadline is checked at each epoch, the epochs are shared by all
+        // the stores of the engine so no thread is needed per call.
+        state.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        // the epoch kept advancing while the plugin was idle, the deadline is
+        // reset so that the call gets all its epochs.
+        self.store.set_epoch_deadline(1);
+        let fuel_before = self.store.get_fuel().ok();
+
+        let res = f(self);
+        self.store.data_mut().deadline = None;
+        self.end_call(fuel_before);
+        res
+    }
+
+    /// Records the fuel consumed by the call that ended, and warns if it
+    /// logged more messages than allowed.
+    fn end_call(&mut self, fuel_before: Option<u64>) {
+        self.store.data_mut().epochs_left = None;
+        self.record_fuel(fuel_before);
+
+        let state = self.store.data();
+        if let Some(max) = state.max_log_per_call {
+            if state.logs_in_call > max {
+                state.shared.output.write_line(format_args!(
+                    "WARNING: log limit reached, {} messages of the plugin were dropped",
+                    state.logs_in_call - max
+                ));
+            }
+        }
+    }
+
+    /// Adds the fuel consumed since `fuel_before` to the resource stats.
+    fn record_fuel(&mut self, fuel_before: Option<u64>) {
+        if let (Some(before), Ok(after)) = (fuel_before, self.store.get_fuel()) {
+            self.fuel_consumed += before.saturating_sub(after);
+        }
+    }
+
+    /// Spawns a thread incrementing the epoch of `engine` every
+    /// `tick_interval`, until the returned [`EpochThread`] is dropped.
+    pub fn start_epoch_thread(engine: Engine, tick_interval: Duration) -> EpochThread {
+        let (stop, rx) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            while rx.recv_timeout(tick_interval) == Err(RecvTimeoutError::Timeout) {
+                engine.increment_epoch();
+            }
+        });
+        EpochThread {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
     }
 
     #[track_caller]
     pub fn call_init(&mut self) -> PluginInfo {
-        self.bindings.call_init(&mut self.store).unwrap()
+        self.try_call_init().unwrap()
+    }
+
+    /// Measures how long the plugin takes to initialize, by calling `init`
+    /// again on the already instantiated plugin.
+    pub fn warmup(&mut self) -> Result<Duration> {
+        let start = Instant::now();
+        self.try_call_init()?;
+        Ok(start.elapsed())
+    }
+
+    /// Runs the command of the plugin, if it fails `on-error` is called with
+    /// the error message.
+    pub fn try_call_run_command(&mut self, name: &str, args: &[String]) -> Result<()> {
+        self.run_command_with(self.options.timeout, |host| {
+            host.bindings.call_run_command(&mut host.store, name, args)
+        })
+    }
+
+    /// Runs a command that has a spec with the arguments parsed following
+    /// it, like [`PluginHost::try_call_run_command`].
+    pub fn try_call_run_command_parsed(
+        &mut self,
+        name: &str,
+        args: &crate::ParsedArgs,
+    ) -> Result<()> {
+        let args = plugin_app::core::types::ParsedArgs::from(args);
+        self.run_command_with(self.options.timeout, |host| {
+            host.bindings
+                .call_run_command_parsed(&mut host.store, name, &args)
+        })
+    }
+
+    /// Runs the command of the plugin with `stream-command`, `sink` is called
+    /// with each chunk of its output as soon as the plugin writes it, and
+    /// returns `false` to stop the command. The inner result is the error
+    /// message of the command. If the plugin doesn't export `stream-command`
+    /// its whole output is a single chunk, from `run-command-with-output`.
+    pub fn try_call_stream_command(
+        &mut self,
+        name: &str,
+        args: &[String],
+        mut sink: impl FnMut(&[u8]) -> bool + Send + 'static,
+    ) -> Result<Result<(), String>> {
+        let Ok(func) = self
+            .instance
+            .get_typed_func::<(&str, &[String]), (Result<(), String>,)>(
+                &mut self.store,
+                "stream-command",
+            )
+        else {
+            let output = self.try_call_run_command_returning_string(name, args)?;
+            return Ok(output.map(|output| {
+                sink(output.as_bytes());
+            }));
+        };
+
+        self.store.data_mut().stream = Some(Box::new(sink));
+        let res = self.run_command_with(self.options.timeout, |host| {
+            let (res,) = func.call(&mut host.store, (name, args))?;
+            func.post_return(&mut host.store)?;
+            Ok(res)
+        });
+        self.store.data_mut().stream = None;
+        res
+    }
+
+    /// Runs the command like [`PluginHost::try_call_stream_command`] on a new
+    /// thread, the returned stream is read while the plugin writes the
+    /// output. The thread gives the host back once the command is done.
+    #[cfg(feature = "async")]
+    pub fn call_run_command_stream(
+        mut self,
+        name: String,
+        args: Vec<String>,
+    ) -> (
+        tokio::io::DuplexStream,
+        JoinHandle<(PluginHost, Result<Result<(), String>>)>,
+    ) {
+        use tokio::io::AsyncWriteExt;
+
+        let (reader, mut writer) = tokio::io::duplex(STREAM_BUFFER_SIZE);
+        let handle = thread::spawn(move || {
+            // the plugin is synchronous, each chunk is written to the stream
+            // with a runtime of this thread only.
+            let rt = match tokio::runtime::Builder::new_current_thread().build() {
+                Ok(rt) => rt,
+                Err(err) => return (self, Err(err.into())),
+            };
+            let res = self.try_call_stream_command(&name, &args, move |chunk| {
+                rt.block_on(writer.write_all(chunk)).is_ok()
+            });
+            (self, res)
+        });
+        (reader, handle)
+    }
+
+    /// Runs a command with `call`, it calls `run-command`,
+    /// `run-command-parsed`, `run-command-with-output` or `stream-command`.
+    /// The call is counted and timed, and `on-error` is called if it fails.
+    fn run_command_with<T>(
+        &mut self,
+        timeout: Option<Duration>,
+        call: impl FnOnce(&mut PluginHost) -> Result<T>,
+    ) -> Result<T> {
+        self.call_count += 1;
+        let start = Instant::now();
+        self.last_called_at = Some(start);
+        let res = self.with_deadline(timeout, call);
+        self.record_latency(start.elapsed());
+        self.check_memory_usage();
+        if let Err(err) = &res {
+            let msg = err.root_cause().to_string();
+            self.call_on_error(&msg);
+        }
+        res
+    }
+
+    /// Calls `on-error` with the error message of a failed call, if the
+    /// plugin exports it.
+    fn call_on_error(&mut self, msg: &str) {
+        let Ok(func) = self
+            .instance
+            .get_typed_func::<(&str,), ()>(&mut self.store, "on-error")
+        else {
+            return;
+        };
+        // the store may be unusable after a trap so calling `on-error` can
+        // fail too, this second error isn't useful so it's discarded.
+        if func.call(&mut self.store, (msg,)).is_ok() {
+            _ = func.post_return(&mut self.store);
+        }
+    }
+
+    /// Calls the optional export `name` of the `plugin` world, returns `None`
+    /// without calling anything if the plugin doesn't export it.
+    fn call_optional<Params, Return>(
+        &mut self,
+        name: &str,
+        params: Params,
+    ) -> Result<Option<Return>>
+    where
+        Params: ComponentNamedList + Lower,
+        Return: ComponentNamedList + Lift,
+    {
+        let Ok(func) = self
+            .instance
+            .get_typed_func::<Params, Return>(&mut self.store, name)
+        else {
+            return Ok(None);
+        };
+        self.with_deadline(self.options.timeout, |host| {
+            let res = func.call(&mut host.store, params)?;
+            func.post_return(&mut host.store)?;
+            Ok(Some(res))
+        })
     }
 
     #[track_caller]
     pub fn call_run_command(&mut self, name: &str, args: &[String]) {
-        self.bindings
-            .call_run_command(&mut self.store, name, args)
+        self.call_run_command_opts(name, args, RunOptions::default())
+            .unwrap();
+    }
+
+    /// Runs the command of the plugin with the given options, they override
+    /// the options of the host for this call only.
+    pub fn call_run_command_opts(
+        &mut self,
+        name: &str,
+        args: &[String],
+        opts: RunOptions,
+    ) -> Result<RunResult, PluginCallError> {
+        if let Some(fuel) = opts.fuel_override {
+            self.store.set_fuel(fuel).map_err(PluginCallError::Wasm)?;
+        }
+        let timeout = opts.timeout.or(self.options.timeout);
+
+        let start = Instant::now();
+        let res = self.run_command_with(timeout, |host| {
+            if opts.capture_output {
+                host.bindings
+                    .call_run_command_with_output(&mut host.store, name, args)
+                    .map(|res| res.map(Some))
+            } else {
+                host.bindings
+                    .call_run_command(&mut host.store, name, args)
+                    .map(|()| Ok(None))
+            }
+        });
+        let duration = start.elapsed();
+
+        match res {
+            Ok(Ok(output)) => Ok(RunResult { output, duration }),
+            Ok(Err(msg)) => Err(PluginCallError::CommandFailed(msg)),
+            Err(err) => Err(PluginCallError::Wasm(err)),
+        }
+    }
+
+    /// Runs the command of the plugin with `run-command-with-output`, the
+    /// inner result is the output of the command or its error message. Like
+    /// with [`PluginHost::try_call_run_command`], `on-error` is called if the
+    /// call fails.
+    pub fn try_call_run_command_returning_string(
+        &mut self,
+        name: &str,
+        args: &[String],
+    ) -> Result<Result<String, String>> {
+        self.run_command_with(self.options.timeout, |host| {
+            host.bindings
+                .call_run_command_with_output(&mut host.store, name, args)
+        })
+    }
+
+    #[track_caller]
+    pub fn call_run_command_returning_string(
+        &mut self,
+        name: &str,
+        args: &[String],
+    ) -> Result<String, String> {
+        self.try_call_run_command_returning_string(name, args)
             .unwrap()
     }
+
+    /// Returns the long description of the command `name`, if the plugin has
+    /// one, `None` if it doesn't export `describe-command`.
+    pub fn try_call_describe_command(&mut self, name: &str) -> Result<Option<String>> {
+        let res = self.call_optional::<_, (Option<String>,)>("describe-command", (name,))?;
+        Ok(res.and_then(|(description,)| description))
+    }
+
+    #[track_caller]
+    pub fn call_describe_command(&mut self, name: &str) -> Option<String> {
+        self.try_call_describe_command(name).unwrap()
+    }
+
+    /// Returns the JSON schema of the arguments of the command `name`, if the
+    /// plugin declares one, `None` if it doesn't export `command-schema`.
+    pub fn try_call_command_schema(&mut self, name: &str) -> Result<Option<String>> {
+        let res = self.call_optional::<_, (Option<String>,)>("command-schema", (name,))?;
+        Ok(res.and_then(|(schema,)| schema))
+    }
+
+    #[track_caller]
+    pub fn call_command_schema(&mut self, name: &str) -> Option<String> {
+        self.try_call_command_schema(name).unwrap()
+    }
+
+    /// Returns the events the plugin can emit, none if it doesn't export
+    /// `list-events`.
+    pub fn try_call_list_events(&mut self) -> Result<Vec<EventDescriptor>> {
+        let res = self.call_optional::<_, (Vec<EventDescriptor>,)>("list-events", ())?;
+        Ok(res.map(|(events,)| events).unwrap_or_default())
+    }
+
+    #[track_caller]
+    pub fn call_list_events(&mut self) -> Vec<EventDescriptor> {
+        self.try_call_list_events().unwrap()
+    }
+
+    /// Returns the counters kept by the plugin, they are all zero if it
+    /// doesn't export `get-telemetry`.
+    pub fn try_call_get_telemetry(&mut self) -> Result<PluginTelemetry> {
+        let res = self.call_optional::<_, (PluginTelemetry,)>("get-telemetry", ())?;
+        Ok(match res {
+            Some((telemetry,)) => telemetry,
+            None => PluginTelemetry {
+                command_calls: Vec::new(),
+                errors: 0,
+                last_error: None,
+            },
+        })
+    }
+
+    #[track_caller]
+    pub fn call_get_telemetry(&mut self) -> PluginTelemetry {
+        self.try_call_get_telemetry().unwrap()
+    }
+
+    /// Returns the calls of the plugin to the imports of the host, the oldest
+    /// first, and clears the event log. It keeps the last
+    /// [`MAX_EVENT_LOG_LEN`] calls.
+    pub fn flush_event_log(&mut self) -> Vec<PluginEvent> {
+        self.store.data_mut().event_log.drain(..).collect()
+    }
+
+    /// Runs the command twice, returns `true` if both runs made the same
+    /// calls to the imports of the host, eg the same logs. The event log is
+    /// cleared.
+    #[cfg(feature = "test-utils")]
+    pub fn try_call_run_command_idempotency_check(
+        &mut self,
+        name: &str,
+        args: &[String],
+    ) -> Result<bool> {
+        self.flush_event_log();
+        self.try_call_run_command(name, args)?;
+        let first = self.flush_event_log();
+        self.try_call_run_command(name, args)?;
+        let second = self.flush_event_log();
+        Ok(first == second)
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[track_caller]
+    pub fn call_run_command_idempotency_check(&mut self, name: &str, args: &[String]) -> bool {
+        self.try_call_run_command_idempotency_check(name, args)
+            .unwrap()
+    }
+
+    /// Returns the events the plugin subscribed to.
+    pub fn subscriptions(&self) -> &[String] {
+        &self.store.data().subscriptions
+    }
+
+    /// Tells the plugin it is about to be unloaded, if it exports
+    /// `on-unload`.
+    pub fn call_on_unload(&mut self) -> Result<()> {
+        self.call_optional::<(), ()>("on-unload", ())?;
+        Ok(())
+    }
+
+    /// Returns the number of commands of the plugin that were run.
+    pub fn call_count(&self) -> u64 {
+        self.call_count
+    }
+
+    /// Returns the resources used by the plugin since it was loaded.
+    pub fn resource_stats(&self) -> ResourceStats {
+        let peak_memory = self.store.data().limits.peak_memory;
+        ResourceStats {
+            fuel_consumed: self.fuel_consumed,
+            peak_memory_pages: (peak_memory / 0x10000) as u32,
+            call_count: self.call_count,
+        }
+    }
+
+    /// Returns the total size of the linear memories of the plugin, in bytes.
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.store.data().limits.memory
+    }
+
+    /// Logs a warning if the plugin uses more than the threshold set with
+    /// [`PluginHostBuilder::with_memory_warn_threshold`] of its memory limit.
+    fn check_memory_usage(&self) {
+        let (Some(threshold), Some(limit)) = (
+            self.options.memory_warn_threshold,
+            self.options.store.memory_limit,
+        ) else {
+            return;
+        };
+        let usage = self.memory_usage_bytes() as f64 / limit as f64;
+        if usage >= threshold as f64 {
+            tracing::warn!(
+                "the plugin {:?} uses {:.0}% of its memory limit",
+                self.path,
+                usage * 100.0
+            );
+        }
+    }
+
+    /// Returns the state of the store of the plugin.
+    pub fn dump_store_stats(&self) -> StoreStats {
+        let state = self.store.data();
+        StoreStats {
+            fuel_consumed: self.store.get_fuel().ok().map(|_| self.fuel_consumed),
+            epoch: state.epochs,
+            linear_memory_pages: (state.limits.memory / 0x10000) as u32,
+        }
+    }
+
+    /// Returns `false` if the instance is unusable, eg after a trap, by
+    /// calling `health-check`, or `run-command-with-output` with an empty
+    /// command name if the plugin doesn't export it.
+    pub fn is_alive(&mut self) -> bool {
+        let health_check = self
+            .instance
+            .get_typed_func::<(), (bool,)>(&mut self.store, "health-check");
+        match health_check {
+            Ok(func) => func
+                .call(&mut self.store, ())
+                .and_then(|(alive,)| {
+                    func.post_return(&mut self.store)?;
+                    Ok(alive)
+                })
+                .unwrap_or(false),
+            // any call fails once the instance is unusable.
+            Err(_) => self
+                .bindings
+                .call_run_command_with_output(&mut self.store, "", &[])
+                .is_ok(),
+        }
+    }
+
+    /// Returns the path of the WASM file the plugin was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the SHA-256 of the file the plugin was loaded from.
+    pub fn component_hash(&self) -> [u8; 32] {
+        self.hash
+    }
+
+    /// Returns the WASM features enabled by the engine of the plugin.
+    pub fn supported_wasm_features(&self) -> WasmFeatureSet {
+        WasmFeatureSet::of_engine(self.store.engine())
+    }
+
+    /// Returns the version embedded in the [`VERSION_SECTION`] custom section
+    /// of the plugin, it is known without calling `init`. `None` if the
+    /// plugin has no such section or if it was precompiled.
+    pub fn version_string(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Returns the WebAssembly text format of the plugin. It fails for the
+    /// precompiled plugins, their file isn't WASM.
+    #[cfg(feature = "debug")]
+    pub fn try_print_wasm_disassembly(&self) -> Result<String> {
+        wasmprinter::print_bytes(&self.raw_bytes)
+    }
+
+    #[cfg(feature = "debug")]
+    #[track_caller]
+    pub fn print_wasm_disassembly(&self) -> String {
+        self.try_print_wasm_disassembly().unwrap()
+    }
+
+    /// Returns how long ago the plugin was loaded.
+    pub fn uptime(&self) -> Duration {
+        self.loaded_at.elapsed()
+    }
+
+    pub fn last_called_at(&self) -> Option<Instant> {
+        self.last_called_at
+    }
+
+    /// Returns how long ago a command of the plugin was run, or the plugin
+    /// was loaded if none of its commands were run.
+    pub fn idle_time(&self) -> Duration {
+        self.last_called_at.unwrap_or(self.loaded_at).elapsed()
+    }
+
+    /// Returns the names of the imports and exports of the component, in a
+    /// WIT-like format, the exported and imported instances are expanded.
+    pub fn export_interface_summary(&self) -> String {
+        let engine = self.store.engine();
+        let ty = self.component.component_type();
+
+        let mut summary = String::from("world {\n");
+        for (name, item) in ty.imports(engine) {
+            summary.push_str(&describe_item("import", name, &item, engine));
+        }
+        for (name, item) in ty.exports(engine) {
+            summary.push_str(&describe_item("export", name, &item, engine));
+        }
+        summary.push_str("}\n");
+        summary
+    }
+
+    /// Checks that the plugin exports the functions of the `core` world, see
+    /// [`check_conformance`].
+    pub fn assert_interface_conformance(&self) -> Result<(), Vec<ConformanceError>> {
+        check_conformance(self.store.engine(), &self.component)
+    }
+
+    /// Checks that the plugin exports the functions of the `core` world of
+    /// the WIT at `wit_path`, see [`validate_exports_against_wit_source`].
+    pub fn validate_exports_against_wit_source(
+        &self,
+        wit_path: &Path,
+    ) -> Result<Vec<ConformanceError>> {
+        validate_exports_against_wit_source(self.store.engine(), &self.component, wit_path)
+    }
+
+    /// Returns the id and the name of the tasks spawned by the plugin that
+    /// weren't polled to completion yet, sorted by id.
+    pub fn tasks(&self) -> Vec<(u64, String)> {
+        let mut tasks = self
+            .store
+            .data()
+            .tasks
+            .iter()
+            .map(|(id, task)| (*id, task.name.clone()))
+            .collect::<Vec<_>>();
+        tasks.sort();
+        tasks
+    }
+
+    /// Returns the state of the plugin, to restore it later with
+    /// [`PluginHost::import_state`], `None` if it doesn't export
+    /// `export-state`.
+    pub fn export_state(&mut self) -> Result<Option<Vec<u8>>> {
+        let res = self.call_optional::<(), (Vec<u8>,)>("export-state", ())?;
+        Ok(res.map(|(state,)| state))
+    }
+
+    /// Restores a state returned by [`PluginHost::export_state`], it fails
+    /// if the plugin traps, eg because the state is corrupted. The state is
+    /// ignored if the plugin doesn't export `import-state`.
+    pub fn import_state(&mut self, data: &[u8]) -> Result<()> {
+        self.call_optional::<_, ()>("import-state", (data,))?;
+        Ok(())
+    }
+}
+
+/// The functions a plugin must export, with their number of parameters and
+/// of results, they must match the `core` world of `wit/plugin.wit`.
+const REQUIRED_EXPORTS: &[(&str, usize, usize)] = &[
+    ("init", 0, 1),
+    ("run-command", 2, 0),
+    ("run-command-parsed", 2, 0),
+    ("run-command-with-output", 2, 1),
+];
+
+/// The functions a plugin may export, only their signature is checked, they
+/// are the exports of the `plugin` world that aren't in `core`.
+const OPTIONAL_EXPORTS: &[(&str, usize, usize)] = &[
+    ("on-error", 1, 0),
+    ("health-check", 0, 1),
+    ("stream-command", 2, 1),
+    ("run-task", 2, 1),
+    ("export-state", 0, 1),
+    ("import-state", 1, 0),
+    ("describe-command", 1, 1),
+    ("command-schema", 1, 1),
+    ("list-events", 0, 1),
+    ("get-telemetry", 0, 1),
+    ("on-unload", 0, 0),
+];
+
+/// An export of a plugin that doesn't match the `core` world, see
+/// [`check_conformance`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConformanceError {
+    /// The function isn't exported.
+    Missing(String),
+    /// The export isn't a function.
+    NotAFunction(String),
+    /// The function doesn't have the expected numbers of parameters and
+    /// results.
+    WrongSignature {
+        name: String,
+        params: usize,
+        results: usize,
+    },
+}
+
+impl Display for ConformanceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConformanceError::Missing(name) => write!(f, "the function {name:?} isn't exported"),
+            ConformanceError::NotAFunction(name) => {
+                write!(f, "the export {name:?} isn't a function")
+            }
+            ConformanceError::WrongSignature {
+                name,
+                params,
+                results,
+            } => write!(
+                f,
+                "the function {name:?} must have {params} parameters and {results} results"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConformanceError {}
+
+/// Checks that the component exports the functions of the `core` world,
+/// before it is instantiated. The optional exports of the `plugin` world are
+/// only checked if they are exported.
+pub fn check_conformance(
+    engine: &Engine,
+    component: &Component,
+) -> Result<(), Vec<ConformanceError>> {
+    let exports = |list: &[(&str, usize, usize)]| {
+        list.iter()
+            .map(|&(name, params, results)| (name.to_string(), params, results))
+            .collect::<Vec<_>>()
+    };
+    let mut errors = check_exports(engine, component, exports(REQUIRED_EXPORTS));
+    errors.extend(
+        check_exports(engine, component, exports(OPTIONAL_EXPORTS))
+            .into_iter()
+            .filter(|err| !matches!(err, ConformanceError::Missing(_))),
+    );
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks that the component exports the functions exported by the `core`
+/// world of the WIT file or directory at `wit_path`, eg to detect a plugin
+/// that wasn't compiled again after the WIT changed. Returns the missing or
+/// mistyped exports, it fails if the WIT can't be parsed.
+pub fn validate_exports_against_wit_source(
+    engine: &Engine,
+    component: &Component,
+    wit_path: &Path,
+) -> Result<Vec<ConformanceError>> {
+    let mut resolve = wit_parser::Resolve::default();
+    let (package, _) = resolve.push_path(wit_path)?;
+    let world = resolve.select_world(package, Some("core"))?;
+
+    let expected = resolve.worlds[world]
+        .exports
+        .iter()
+        .filter_map(|(key, item)| match item {
+            wit_parser::WorldItem::Function(func) => Some((
+                resolve.name_world_key(key),
+                func.params.len(),
+                func.results.len(),
+            )),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    Ok(check_exports(engine, component, expected))
+}
+
+/// Returns the functions, with their number of parameters and of results,
+/// that the component doesn't export with this signature.
+fn check_exports(
+    engine: &Engine,
+    component: &Component,
+    expected: impl IntoIterator<Item = (String, usize, usize)>,
+) -> Vec<ConformanceError> {
+    let ty = component.component_type();
+    let exports = ty.exports(engine).collect::<HashMap<_, _>>();
+
+    let mut errors = Vec::new();
+    for (name, params, results) in expected {
+        match exports.get(name.as_str()) {
+            Some(ComponentItem::ComponentFunc(func)) => {
+                if func.params().count() != params || func.results().count() != results {
+                    errors.push(ConformanceError::WrongSignature {
+                        name,
+                        params,
+                        results,
+                    });
+                }
+            }
+            Some(_) => errors.push(ConformanceError::NotAFunction(name)),
+            None => errors.push(ConformanceError::Missing(name)),
+        }
+    }
+    errors
+}
+
+/// Describes an import or an export of a component on one line, or on
+/// several lines with its items for an instance.
+fn describe_item(direction: &str, name: &str, item: &ComponentItem, engine: &Engine) -> String {
+    let ComponentItem::ComponentInstance(instance) = item else {
+        return format!("  {direction} {name}: {};\n", item_kind(item));
+    };
+
+    let mut desc = format!("  {direction} {name} {{\n");
+    for (name, item) in instance.exports(engine) {
+        desc.push_str(&format!("    {name}: {};\n", item_kind(&item)));
+    }
+    desc.push_str("  }\n");
+    desc
+}
+
+fn item_kind(item: &ComponentItem) -> &'static str {
+    match item {
+        ComponentItem::ComponentFunc(_) => "func",
+        ComponentItem::CoreFunc(_) => "core func",
+        ComponentItem::Module(_) => "module",
+        ComponentItem::Component(_) => "component",
+        ComponentItem::ComponentInstance(_) => "instance",
+        ComponentItem::Type(_) => "type",
+        ComponentItem::Resource(_) => "resource",
+    }
+}
+
+/// Spare initialized instances of a plugin, a shell replaces an unusable
+/// instance of the plugin with one of them instead of loading the plugin
+/// again, see [`Shell::try_recover_plugin`]. The instances acquired aren't
+/// given back, the pool is refilled instead.
+pub struct PluginHostPool {
+    /// The compiled plugin, the hosts are instances of it
+    template: Component,
+    /// The spare instances, the oldest first
+    idle: VecDeque<PluginHost>,
+    /// Number of idle instances the pool is refilled to
+    min_idle: usize,
+    engine: Engine,
+    shared: SharedCtx,
+    path: PathBuf,
+    hash: [u8; 32],
+    raw_bytes: Vec<u8>,
+    options: HostOptions,
+}
+
+impl Debug for PluginHostPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PluginHostPool")
+            .field("path", &self.path)
+            .field("idle", &self.idle.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl PluginHostPool {
+    /// Creates a pool of instances of the plugin of `host`, with the same
+    /// options, and initializes `min_idle` of them.
+    pub fn new(host: &PluginHost, min_idle: usize) -> Result<PluginHostPool> {
+        let mut pool = PluginHostPool {
+            template: host.component.clone(),
+            idle: VecDeque::with_capacity(min_idle),
+            min_idle,
+            engine: host.store.engine().clone(),
+            shared: host.store.data().shared.clone(),
+            path: host.path.clone(),
+            hash: host.hash,
+            raw_bytes: host.raw_bytes.clone(),
+            options: host.options.clone(),
+        };
+        pool.refill()?;
+        Ok(pool)
+    }
+
+    fn instantiate(&self) -> Result<PluginHost> {
+        let mut host = PluginHost::from_component(
+            self.engine.clone(),
+            self.shared.clone(),
+            self.template.clone(),
+            self.path.clone(),
+            self.hash,
+            self.raw_bytes.clone(),
+            self.options.clone(),
+        )?;
+        host.try_call_init()?;
+        Ok(host)
+    }
+
+    /// Takes a spare instance, or initializes a new one if there is none.
+    pub fn acquire(&mut self) -> Result<PluginHost> {
+        match self.idle.pop_front() {
+            Some(host) => Ok(host),
+            None => self.instantiate(),
+        }
+    }
+
+    /// Initializes new instances until there are at least `min_idle` spare
+    /// ones, eg after an instance was acquired.
+    pub fn refill(&mut self) -> Result<()> {
+        while self.idle.len() < self.min_idle {
+            let host = self.instantiate()?;
+            self.idle.push_back(host);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of spare instances.
+    pub fn idle_count(&self) -> usize {
+        self.idle.len()
+    }
+
+    /// Returns the SHA-256 of the file the plugin was loaded from.
+    pub fn component_hash(&self) -> [u8; 32] {
+        self.hash
+    }
+}
+
+/// Formats the bytes in lowercase hexadecimal.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
 }
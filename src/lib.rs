@@ -1,35 +1,283 @@
 use core::str;
-use std::collections::HashMap;
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
-use std::fmt::Debug;
-use std::io::{stdin, stdout, Write};
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::fmt::{Debug, Display};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Result};
-use host::plugin_app::core::types::Command;
-use host::{PluginHost, PluginInfo};
-use wasmtime::Engine;
+use host::plugin_app::core::types::{Command, CommandSpec, EventDescriptor, PluginTelemetry};
+use host::{
+    EpochThread, PluginHost, PluginHostBuilder, PluginHostPool, PluginInfo, ResourceStats,
+    SharedCtx,
+};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde_json::json;
+use wasmtime::{Config, Engine, Trap};
 
+pub mod args;
 pub mod cmds;
+pub mod config;
 pub mod host;
+pub mod script;
+pub mod stdio;
 
-#[derive(Debug, Clone)]
+pub use args::{ParseError, ParsedArgs};
+pub use config::{ConfigError, ShellConfig};
+pub use host::live_plugin_count;
+pub use script::{Script, ScriptLine};
+pub use stdio::OutputCapture;
+use stdio::{out, outln, Input, LifecycleLogger, SessionRecorder};
+
+/// The thread a built-in command is executed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExecuteIn {
+    /// Executed in the thread running the shell.
+    #[default]
+    MainThread,
+    /// Executed in a new thread with a clone of the execution context, that
+    /// replaces the context of the shell once the command has returned.
+    SpawnedThread,
+}
+
+/// Privilege level of the shell user, commands can require a minimum level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PrivilegeLevel {
+    #[default]
+    User,
+    Admin,
+}
+
+/// Called with the context after a successful run of a command, see
+/// [`Shell::define_cmd_with_hook`].
+type AfterHook = Arc<dyn Fn(&ExecutionCtx) + Send + Sync>;
+
+#[derive(Clone)]
 pub struct Cmd {
     usage: String,
     description: String,
+    /// Where the command is executed, only used by built-in commands
+    execute_in: ExecuteIn,
+    /// Minimum number of arguments, computed from the usage
+    min_args: usize,
+    /// Maximum number of arguments, computed from the usage, `None` if there
+    /// is no maximum
+    max_args: Option<usize>,
+    /// Privilege needed to run the command
+    min_privilege: PrivilegeLevel,
+    /// The command is still in development, a warning is printed before it
+    /// is run
+    experimental: bool,
+    /// The arguments of the command, they are checked before it's run
+    spec: Option<CommandSpec>,
+    /// The group the command was defined in, see [`Shell::register_cmd_group`]
+    group: Option<String>,
+    /// Explains the flags of the command, with examples and caveats, it's
+    /// printed by `help <cmd>` instead of the description
+    long_description: Option<String>,
+    /// Called after each successful run of the command
+    after_hook: Option<AfterHook>,
+    /// The JSON schema each argument must match, only used by the commands
+    /// of the plugins
+    input_schema: Option<serde_json::Value>,
+    /// The input schema compiled once, it validates the arguments
+    input_validator: Option<Arc<jsonschema::Validator>>,
+}
+
+impl Debug for Cmd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cmd")
+            .field("usage", &self.usage)
+            .field("description", &self.description)
+            .field("execute_in", &self.execute_in)
+            .field("min_args", &self.min_args)
+            .field("max_args", &self.max_args)
+            .field("min_privilege", &self.min_privilege)
+            .field("experimental", &self.experimental)
+            .field("spec", &self.spec)
+            .field("group", &self.group)
+            .field("long_description", &self.long_description)
+            .field("after_hook", &self.after_hook.is_some())
+            .field("input_schema", &self.input_schema)
+            .finish()
+    }
 }
 
 impl Cmd {
     pub fn new(usage: impl ToString, description: impl ToString) -> Cmd {
+        let usage = usage.to_string();
+        let (min_args, max_args) = Cmd::arg_bounds(&usage);
         Cmd {
-            usage: usage.to_string(),
+            usage,
             description: description.to_string(),
+            execute_in: ExecuteIn::MainThread,
+            min_args,
+            max_args,
+            min_privilege: PrivilegeLevel::User,
+            experimental: false,
+            spec: None,
+            group: None,
+            long_description: None,
+            after_hook: None,
+            input_schema: None,
+            input_validator: None,
+        }
+    }
+
+    /// Computes the minimum and maximum numbers of arguments from a usage, in
+    /// which `<arg>` is required, `[arg]` is optional and `..` means the
+    /// argument can be repeated, eg `help [cmd..]` takes any number of
+    /// arguments.
+    fn arg_bounds(usage: &str) -> (usize, Option<usize>) {
+        let mut min = 0;
+        let mut max = Some(0);
+        for arg in usage.split_whitespace().skip(1) {
+            if arg.starts_with('<') {
+                min += 1;
+            }
+            max = if arg.contains("..") {
+                None
+            } else {
+                max.map(|max| max + 1)
+            };
         }
+        (min, max)
+    }
+
+    pub fn min_args(&self) -> usize {
+        self.min_args
+    }
+
+    pub fn max_args(&self) -> Option<usize> {
+        self.max_args
+    }
+
+    /// Can the command be called with `count` arguments?
+    pub fn accepts_arg_count(&self, count: usize) -> bool {
+        count >= self.min_args && self.max_args.is_none_or(|max| count <= max)
+    }
+
+    pub fn with_execute_in(mut self, execute_in: ExecuteIn) -> Cmd {
+        self.execute_in = execute_in;
+        self
+    }
+
+    pub fn with_min_privilege(mut self, min_privilege: PrivilegeLevel) -> Cmd {
+        self.min_privilege = min_privilege;
+        self
+    }
+
+    pub fn with_experimental(mut self, experimental: bool) -> Cmd {
+        self.experimental = experimental;
+        self
+    }
+
+    pub fn is_experimental(&self) -> bool {
+        self.experimental
+    }
+
+    pub fn with_spec(mut self, spec: Option<CommandSpec>) -> Cmd {
+        self.spec = spec;
+        self
+    }
+
+    pub fn spec(&self) -> Option<&CommandSpec> {
+        self.spec.as_ref()
+    }
+
+    pub fn with_long_description(mut self, long_description: Option<String>) -> Cmd {
+        self.long_description = long_description;
+        self
+    }
+
+    pub fn long_description(&self) -> Option<&str> {
+        self.long_description.as_deref()
+    }
+
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Sets the JSON schema each argument must match, it's compiled once
+    /// here instead of on each run of the command.
+    pub fn try_with_input_schema(
+        mut self,
+        input_schema: serde_json::Value,
+    ) -> Result<Cmd, jsonschema::ValidationError<'static>> {
+        let validator = jsonschema::validator_for(&input_schema)?;
+        self.input_schema = Some(input_schema);
+        self.input_validator = Some(Arc::new(validator));
+        Ok(self)
+    }
+
+    #[track_caller]
+    pub fn with_input_schema(self, input_schema: serde_json::Value) -> Cmd {
+        self.try_with_input_schema(input_schema).unwrap()
+    }
+
+    pub fn input_schema(&self) -> Option<&serde_json::Value> {
+        self.input_schema.as_ref()
+    }
+
+    pub fn input_validator(&self) -> Option<&jsonschema::Validator> {
+        self.input_validator.as_deref()
+    }
+
+    /// Parses the arguments of a command following `spec`.
+    pub fn parse_args(spec: &CommandSpec, args: &[String]) -> Result<ParsedArgs, ParseError> {
+        crate::args::parse_args(spec, args)
+    }
+}
+
+/// A built-in command, it's given the execution context, the name of the
+/// command and its arguments.
+///
+/// # Migrating from `Vec<&str>` arguments
+///
+/// The arguments used to borrow the input line, they are now owned so they
+/// don't tie the command to the lifetime of the input:
+/// - replace `args: Vec<&str>` with `args: Vec<String>` in the signature,
+/// - `args.get(0)` returns an `Option<&String>`, it derefs to `&str` so it can
+///   still be passed where a `&str` is expected,
+/// - use `args[0].as_str()` to match an argument against string literals.
+pub type BuiltinFn = fn(&mut ExecutionCtx, &str, Vec<String>) -> Result<(), ()>;
+
+/// A set of built-in commands defined at once in a shell with
+/// [`Shell::import_native_cmds`], to distribute commands in a library crate.
+#[derive(Debug, Clone, Default)]
+pub struct NativeCmdRegistry(HashMap<String, (Cmd, BuiltinFn)>);
+
+impl NativeCmdRegistry {
+    pub fn new() -> NativeCmdRegistry {
+        NativeCmdRegistry::default()
+    }
+
+    pub fn register(
+        &mut self,
+        name: impl ToString,
+        cmd: Cmd,
+        func: BuiltinFn,
+    ) -> &mut NativeCmdRegistry {
+        self.0.insert(name.to_string(), (cmd, func));
+        self
     }
 }
 
-type BuiltinFn = fn(&mut ExecutionCtx, &str, Vec<&str>) -> Result<(), ()>;
+/// Related built-in commands defined together with
+/// [`Shell::register_cmd_group`], eg the commands of a git integration.
+#[derive(Debug, Clone)]
+pub struct CmdGroup {
+    pub group_name: String,
+    pub commands: Vec<(String, Cmd, BuiltinFn)>,
+}
 
 #[derive(Debug, Clone)]
 pub enum Runner {
@@ -39,187 +287,2998 @@ pub enum Runner {
         /// The plugin where the command is defined
         plugin: String,
     },
+    /// Another name for a command, it's resolved by the shell.
+    Aliased {
+        /// The command the alias stands for, it's never an alias
+        target: String,
+    },
+    /// A recorded sequence of commands, they are run by the shell.
+    Macro {
+        /// The commands with their arguments, in order
+        commands: Vec<(String, Vec<String>)>,
+    },
+}
+
+/// Maximum length of a command name.
+pub const MAX_CMD_NAME_LEN: usize = 32;
+
+/// Why a command couldn't be defined, see [`Shell::define_cmd`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CmdRegistrationError {
+    EmptyName,
+    NameTooLong(String),
+    ContainsWhitespace(String),
+    NotAlphanumeric(String),
+    /// An alias can't replace a command.
+    AlreadyDefined(String),
+    UnknownAliasTarget(String),
+}
+
+impl Display for CmdRegistrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CmdRegistrationError::EmptyName => write!(f, "a command name can't be empty"),
+            CmdRegistrationError::NameTooLong(name) => write!(
+                f,
+                "{name:?} is too long, a command name must be {MAX_CMD_NAME_LEN} characters or shorter"
+            ),
+            CmdRegistrationError::ContainsWhitespace(name) => {
+                write!(f, "{name:?} contains whitespaces")
+            }
+            CmdRegistrationError::NotAlphanumeric(name) => write!(
+                f,
+                "{name:?} must only contain alphanumeric characters and dashes"
+            ),
+            CmdRegistrationError::AlreadyDefined(name) => {
+                write!(f, "the command {name:?} is already defined")
+            }
+            CmdRegistrationError::UnknownAliasTarget(name) => {
+                write!(f, "can't define an alias of the unknown command {name:?}")
+            }
+        }
+    }
+}
+
+impl Error for CmdRegistrationError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CmdError {
+    NotFound(String),
+    /// The command is a built-in of the shell and the built-ins are
+    /// protected, see [`Shell::protect_builtins`].
+    IsBuiltin(String),
+}
+
+impl Display for CmdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CmdError::NotFound(name) => write!(f, "the command {name:?} isn't defined"),
+            CmdError::IsBuiltin(name) => {
+                write!(f, "the built-in command {name:?} can't be unregistered")
+            }
+        }
+    }
+}
+
+impl Error for CmdError {}
+
+/// How often the epoch of the engine of the shell is incremented, the
+/// deadlines of the calls to the plugins are checked at each tick.
+pub const EPOCH_TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Returns the configuration of the engine of the shell, the plugins are
+/// interrupted with epochs, see [`EPOCH_TICK_INTERVAL`].
+fn engine_config() -> Config {
+    let mut config = Config::new();
+    config.epoch_interruption(true);
+    config
+}
+
+/// The limits of each plugin loaded by the shell, see
+/// [`Shell::with_plugin_fuel_limit`], [`Shell::with_plugin_max_epochs`] and
+/// [`Shell::with_max_log_messages_per_call`].
+#[derive(Debug, Clone, Copy, Default)]
+struct PluginLimits {
+    fuel: Option<u64>,
+    max_epochs: Option<u64>,
+    max_log_per_call: Option<usize>,
+}
+
+/// Returns a builder of the host of the plugin at `path`, with the `limits`
+/// that are set.
+fn host_builder(
+    engine: &Engine,
+    shared: &SharedCtx,
+    limits: PluginLimits,
+    path: impl AsRef<Path>,
+) -> PluginHostBuilder {
+    let mut builder = PluginHostBuilder::new(engine.clone(), shared.clone(), path);
+    if let Some(fuel) = limits.fuel {
+        builder = builder.with_fuel_limit(fuel);
+    }
+    if let Some(max_epochs) = limits.max_epochs {
+        builder = builder.with_max_epochs(max_epochs);
+    }
+    if let Some(max) = limits.max_log_per_call {
+        builder = builder.with_max_log_per_call(max);
+    }
+    builder
+}
+
+thread_local! {
+    /// Is a built-in command running on this thread with its panics caught?
+    /// The panic hook of the shell doesn't forward those panics, see
+    /// [`Shell::register_panic_hook`].
+    static CATCHING_PANICS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Installs the panic hook of the shells once per process.
+static PANIC_HOOK: Once = Once::new();
+
+/// Runs the built-in command `f`, its panics are caught.
+fn catch_builtin_panic<T>(f: impl FnOnce() -> T) -> thread::Result<T> {
+    // a built-in command may run another one, eg a macro.
+    let catching = CATCHING_PANICS.replace(true);
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING_PANICS.set(catching);
+    res
+}
+
+/// Returns the message of a panic from its payload.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|msg| msg.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Checks that the name can be used for a command, it must not be longer than
+/// [`MAX_CMD_NAME_LEN`], not contain whitespaces and only contain alphanumeric
+/// characters and dashes.
+fn validate_cmd_name(name: &str) -> Result<(), CmdRegistrationError> {
+    if name.is_empty() {
+        Err(CmdRegistrationError::EmptyName)
+    } else if name.chars().count() > MAX_CMD_NAME_LEN {
+        Err(CmdRegistrationError::NameTooLong(name.to_string()))
+    } else if name.contains(char::is_whitespace) {
+        Err(CmdRegistrationError::ContainsWhitespace(name.to_string()))
+    } else if !name.chars().all(|c| c.is_alphanumeric() || c == '-') {
+        Err(CmdRegistrationError::NotAlphanumeric(name.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// The kind of a command, from its runner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmdKind {
+    Builtin,
+    Wasm,
+    Alias,
+    Macro,
 }
 
 impl Runner {
-    pub fn run(&self, ctx: &mut ExecutionCtx, cmd: &str, args: Vec<&str>) -> Result<(), ()> {
+    pub fn kind(&self) -> CmdKind {
+        match self {
+            Runner::Builtin(_) => CmdKind::Builtin,
+            Runner::Wasm { .. } => CmdKind::Wasm,
+            Runner::Aliased { .. } => CmdKind::Alias,
+            Runner::Macro { .. } => CmdKind::Macro,
+        }
+    }
+
+    /// Runs the command, [`ExecutionCtx::current_command`] returns its name
+    /// while it runs.
+    pub fn run(&self, ctx: &mut ExecutionCtx, cmd: &str, args: Vec<String>) -> Result<(), ()> {
+        let previous = ctx.current_command.replace(cmd.to_string());
+        ctx.log_lifecycle("command-start", json!({ "command": cmd }));
+        let res = self.run_inner(ctx, cmd, args);
+        if res.is_ok() {
+            if let Some(hook) = ctx.cmds.get(cmd).and_then(|c| c.after_hook.clone()) {
+                hook(ctx);
+            }
+        }
+        ctx.log_lifecycle(
+            "command-end",
+            json!({ "command": cmd, "success": res.is_ok() }),
+        );
+        ctx.current_command = previous;
+        res
+    }
+
+    fn run_inner(&self, ctx: &mut ExecutionCtx, cmd: &str, args: Vec<String>) -> Result<(), ()> {
+        match self {
+            Runner::Builtin(func) => {
+                let execute_in = ctx.cmds.get(cmd).map(|c| c.execute_in);
+                if execute_in != Some(ExecuteIn::SpawnedThread) {
+                    if !ctx.catch_panics {
+                        return (func)(ctx, cmd, args);
+                    }
+                    // the context may be left half updated by the command,
+                    // like after a command that failed.
+                    let res = catch_builtin_panic(|| (func)(ctx, cmd, args));
+                    return res.unwrap_or_else(|payload| {
+                        ctx.last_panic = Some(panic_message(payload));
+                        Err(())
+                    });
+                }
+
+                let (tx, rx) = mpsc::channel();
+                let func = *func;
+                let mut thread_ctx = ctx.clone();
+                let cmd = cmd.to_string();
+                let catch_panics = ctx.catch_panics;
+                thread::spawn(move || {
+                    let res = if catch_panics {
+                        catch_builtin_panic(|| (func)(&mut thread_ctx, &cmd, args))
+                            .map_err(panic_message)
+                    } else {
+                        Ok((func)(&mut thread_ctx, &cmd, args))
+                    };
+                    _ = tx.send((thread_ctx, res));
+                });
+
+                // the sender is dropped without sending anything if the
+                // command panicked without the panics being caught.
+                let Ok((thread_ctx, res)) = rx.recv() else {
+                    outln!(ctx, "ERR: the thread executing the command panicked");
+                    return Err(());
+                };
+                *ctx = thread_ctx;
+                res.unwrap_or_else(|msg| {
+                    ctx.last_panic = Some(msg);
+                    Err(())
+                })
+            }
+            Runner::Wasm { plugin } => {
+                let parsed = match ctx.cmds.get(cmd).and_then(Cmd::spec) {
+                    Some(spec) => match Cmd::parse_args(spec, &args) {
+                        Ok(parsed) => Some(parsed),
+                        Err(err) => {
+                            outln!(ctx, "ERR: {err}");
+                            return Err(());
+                        }
+                    },
+                    None => None,
+                };
+                let validation = ctx
+                    .cmds
+                    .get(cmd)
+                    .and_then(Cmd::input_validator)
+                    .map(|validator| crate::args::validate_args(validator, &args));
+                if let Some(Err(err)) = validation {
+                    outln!(ctx, "ERR: {err}");
+                    return Err(());
+                }
+
+                let host = match ctx.validate_plugin(plugin) {
+                    Ok(host) => host,
+                    Err(err) => {
+                        outln!(ctx, "ERR: {err}");
+                        return Err(());
+                    }
+                };
+                let res = {
+                    let mut host = host.lock().unwrap();
+                    match &parsed {
+                        Some(parsed) => host.try_call_run_command_parsed(cmd, parsed),
+                        None => host.try_call_run_command(cmd, &args),
+                    }
+                };
+                let Err(err) = res else {
+                    return Ok(());
+                };
+                print_plugin_error(ctx, plugin, &err);
+                Err(())
+            }
+            Runner::Aliased { target } => {
+                outln!(
+                    ctx,
+                    "ERR: the alias of {target:?} must be resolved by the shell"
+                );
+                Err(())
+            }
+            Runner::Macro { .. } => {
+                outln!(ctx, "ERR: the macro {cmd:?} must be run by the shell");
+                Err(())
+            }
+        }
+    }
+
+    /// Runs the command and returns its output instead of printing it, only
+    /// the commands of the plugins support it because the output of the
+    /// built-in commands can't be captured.
+    pub fn call_returning_string(
+        &self,
+        ctx: &mut ExecutionCtx,
+        cmd: &str,
+        args: Vec<String>,
+    ) -> Result<String, ()> {
         match self {
-            Runner::Builtin(func) => (func)(ctx, cmd, args),
             Runner::Wasm { plugin } => {
-                // we can unwrap here because we know the plugin exists, and if
-                // it doesn't it's a bug in this app.
-                let mut host = ctx.hosts.get(plugin).unwrap().lock().unwrap();
-                let args = args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
-                host.call_run_command(cmd, &args);
-                Ok(())
+                let host = match ctx.validate_plugin(plugin) {
+                    Ok(host) => host,
+                    Err(err) => {
+                        outln!(ctx, "ERR: {err}");
+                        return Err(());
+                    }
+                };
+                let res = host
+                    .lock()
+                    .unwrap()
+                    .try_call_run_command_returning_string(cmd, &args);
+                match res {
+                    Ok(Ok(output)) => Ok(output),
+                    Ok(Err(msg)) => {
+                        outln!(ctx, "ERR: {msg}");
+                        Err(())
+                    }
+                    Err(err) => {
+                        print_plugin_error(ctx, plugin, &err);
+                        Err(())
+                    }
+                }
+            }
+            Runner::Builtin(_) | Runner::Macro { .. } => {
+                outln!(
+                    ctx,
+                    "ERR: the output of the command {cmd:?} can't be captured"
+                );
+                Err(())
+            }
+            Runner::Aliased { target } => {
+                outln!(
+                    ctx,
+                    "ERR: the alias of {target:?} must be resolved by the shell"
+                );
+                Err(())
             }
         }
     }
 }
 
+/// Prints the error of a call to a plugin, with its wasm backtrace if the
+/// shell is verbose.
+fn print_plugin_error(ctx: &ExecutionCtx, plugin: &str, err: &anyhow::Error) {
+    if ctx.verbose {
+        outln!(ctx, "ERR: the plugin {plugin} failed: {err:?}");
+    } else if let Some(trap) = err.downcast_ref::<Trap>() {
+        outln!(ctx, "ERR: the plugin {plugin} trapped: {trap}");
+    } else {
+        outln!(ctx, "ERR: the plugin {plugin} failed: {}", err.root_cause());
+    }
+}
+
 impl From<BuiltinFn> for Runner {
     fn from(value: BuiltinFn) -> Self {
         Runner::Builtin(value)
     }
 }
 
+/// Counts of the commands run by the shell.
+#[derive(Debug, Clone, Default)]
+pub struct ShellMetrics {
+    /// Maps a command name to the number of times it was run
+    pub invocations: HashMap<String, u64>,
+    /// Maps a command name to the number of times it failed
+    pub errors: HashMap<String, u64>,
+    pub total_invocations: u64,
+}
+
+impl ShellMetrics {
+    fn record(&mut self, cmd: &str, failed: bool) {
+        *self.invocations.entry(cmd.to_string()).or_default() += 1;
+        if failed {
+            *self.errors.entry(cmd.to_string()).or_default() += 1;
+        }
+        self.total_invocations += 1;
+    }
+}
+
+/// Checks the name of a plugin, see [`Shell::with_plugin_name_validator`].
+#[derive(Clone)]
+struct NameValidator(Arc<dyn Fn(&str) -> bool + Send + Sync>);
+
+impl Debug for NameValidator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NameValidator").finish_non_exhaustive()
+    }
+}
+
+/// Maximum number of input lines kept in the history of the shell.
+pub const MAX_HISTORY_LEN: usize = 1000;
+
 #[derive(Debug, Clone)]
 pub struct ExecutionCtx {
     /// Maps a command name to its informations
     cmds: HashMap<String, Cmd>,
     /// Maps a plugin name to its informations
     plugins: HashMap<String, PluginInfo>,
+    /// Maps a plugin name to the events it can emit
+    events: HashMap<String, Vec<EventDescriptor>>,
     /// Maps a plugin name to its plugin host
     hosts: HashMap<String, Arc<Mutex<PluginHost>>>,
+    /// Maps a plugin name to the watcher of its WASM file
+    watchers: HashMap<String, Arc<RecommendedWatcher>>,
+    /// The plugins reloaded by their watcher, with their new informations and
+    /// events, the shell defines their commands again
+    reloaded: Arc<Mutex<Vec<(String, PluginInfo, Vec<EventDescriptor>)>>>,
+    /// Maps a plugin name to the pool of its spare instances, they replace
+    /// its instance when it's recovered
+    pools: HashMap<String, Arc<Mutex<PluginHostPool>>>,
+    /// Number of spare instances of each plugin, no pool is created if 0
+    plugin_pool_size: usize,
+    /// The limits of each plugin loaded
+    plugin_limits: PluginLimits,
+    /// The plugins with a name it rejects fail to load
+    name_validator: Option<NameValidator>,
+    /// The configuration the engine was built from
+    wasm_config: Config,
     /// Wasm engine
     engine: Engine,
-    /// The commands to add after initialization of the plugin
-    new_cmds: Option<(String, Vec<Command>)>,
+    /// Increments the epoch of the engine every [`EPOCH_TICK_INTERVAL`]
+    epoch_thread: Arc<EpochThread>,
+    /// The epoch threads of the shells merged into this one, the plugins
+    /// moved from them still run in their engine
+    merged_epoch_threads: Vec<Arc<EpochThread>>,
+    /// State shared with the plugins, like the shell environment
+    shared: SharedCtx,
+    /// The commands to add after initialization of the plugins, with the
+    /// name of the plugin defining them
+    new_cmds: Vec<(String, Vec<Command>)>,
+    /// Maps an alias to the command it stands for
+    aliases: HashMap<String, String>,
+    /// The aliases to define, with the command they stand for
+    new_aliases: Vec<(String, String)>,
     /// Is the shell running?
     running: bool,
+    /// Print the full errors of the plugins, with their wasm backtrace
+    verbose: bool,
+    /// Is a user typing the commands? The shell doesn't prompt otherwise
+    interactive: bool,
+    /// Counts of the commands run
+    metrics: ShellMetrics,
+    /// Privilege level of the user of the shell
+    current_privilege: PrivilegeLevel,
+    /// The last input lines, after history expansion, with when they were
+    /// entered, the most recent last
+    history: VecDeque<(Instant, String)>,
+    /// The error of the last command, `None` if it succeeded
+    last_error: Option<String>,
+    /// The name of the command being run
+    current_command: Option<String>,
+    /// Records the loads, the unloads, the commands run and their errors
+    lifecycle_logger: Option<LifecycleLogger>,
+    /// Catch the panics of the built-in commands, see
+    /// [`Shell::register_panic_hook`]
+    catch_panics: bool,
+    /// The message of the panic of the last built-in command
+    last_panic: Option<String>,
+    /// The plugins idle for longer than this are unloaded
+    plugin_idle_ttl: Option<Duration>,
+    /// The plugins that are never unloaded when idle
+    no_evict: HashSet<String>,
+    /// The commands that can't be run, see [`Shell::disable_cmd`]
+    disabled_cmds: HashSet<String>,
+    /// The name of the macro being recorded, with the commands recorded so
+    /// far
+    recording_macro: Option<(String, Vec<(String, Vec<String>)>)>,
+    /// Maps a macro name to its commands
+    macros: HashMap<String, Vec<(String, Vec<String>)>>,
+    /// The macros to define, with their commands
+    new_macros: Vec<(String, Vec<(String, Vec<String>)>)>,
+    /// The plugins to unload, see [`Shell::unload_plugin`]
+    plugins_to_unload: Vec<String>,
 }
 
 impl ExecutionCtx {
-    pub fn load_plugin(&mut self, path: PathBuf) {
-        let mut host = PluginHost::new(self.engine.clone(), path);
-        let info = host.call_init();
+    /// Is there a plugin loaded with this name? A name is no longer valid
+    /// once the plugin is unloaded.
+    pub fn is_plugin_loaded(&self, name: &str) -> bool {
+        self.hosts.contains_key(name)
+    }
 
-        if self.hosts.contains_key(&info.name) {
-            println!("ERR: a plugin with the same name is already loaded")
+    /// Is a user typing the commands? The built-ins must not prompt the user
+    /// otherwise, see [`Shell::with_interactive`].
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    /// Returns the name of the command being run, `None` outside of a
+    /// command.
+    pub fn current_command(&self) -> Option<&str> {
+        self.current_command.as_deref()
+    }
+
+    /// Writes the event to the lifecycle logger, if one is registered.
+    fn log_lifecycle(&self, event: &str, fields: serde_json::Value) {
+        if let Some(logger) = &self.lifecycle_logger {
+            logger.log(event, fields);
         }
+    }
 
+    /// Returns the host of the plugin `name`, or an error if there is no
+    /// plugin loaded with this name.
+    pub fn validate_plugin(&self, name: &str) -> Result<&Arc<Mutex<PluginHost>>, PluginLoadError> {
         self.hosts
-            .insert(info.name.clone(), Arc::new(Mutex::new(host)));
-        self.plugins.insert(info.name.clone(), info.clone());
-        self.new_cmds = Some((info.name, info.commands));
+            .get(name)
+            .ok_or_else(|| PluginLoadError::NotLoaded(name.to_string()))
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Shell {
-    /// Maps the command name to its runner
-    runners: HashMap<String, Runner>,
-    exec_ctx: ExecutionCtx,
-}
+    /// Loads the plugin at `path` and returns its name.
+    #[track_caller]
+    pub fn load_plugin(&mut self, path: PathBuf) -> String {
+        self.try_load_plugin(path).unwrap()
+    }
 
-impl Shell {
-    pub fn new() -> Shell {
-        let mut shell = Shell {
-            runners: HashMap::new(),
-            exec_ctx: ExecutionCtx {
-                cmds: HashMap::new(),
-                plugins: HashMap::new(),
-                hosts: HashMap::new(),
-                engine: Engine::default(),
-                new_cmds: None,
-                running: true,
-            },
-        };
+    /// The plugin `name` won't be unloaded when it is idle, see
+    /// [`Shell::with_plugin_idle_ttl`].
+    pub fn exempt_from_eviction(&mut self, name: &str) {
+        self.no_evict.insert(name.to_string());
+    }
 
-        shell.define_cmd(
-            "quit",
-            Cmd::new("quit", "Quit the shell."),
-            (|ctx, _, _| {
-                ctx.running = false;
-                Ok(())
-            }) as BuiltinFn,
-        );
+    /// Loads a plugin precompiled to a `.cwasm` file, see
+    /// [`PluginHost::try_new_precompiled`].
+    pub fn load_precompiled_plugin(&mut self, path: PathBuf) -> Result<()> {
+        let host = self.host_builder(path).with_precompiled(true).build()?;
+        self.try_add_plugin(host)?;
+        Ok(())
+    }
 
-        shell.define_cmd(
-            "help",
-            Cmd::new("help [cmd..]", "Print all commands to the screen or an helpful message if a command is passed as argument"),
-            cmds::help_exec as BuiltinFn
-        );
+    /// Loads a plugin like [`ExecutionCtx::load_plugin`], but returns an
+    /// error instead of panicking if it fails and if a plugin with the same
+    /// name is already loaded.
+    pub fn try_load_plugin(&mut self, path: PathBuf) -> Result<String, PluginLoadError> {
+        let host = self
+            .host_builder(path)
+            .build()
+            .map_err(PluginLoadError::Wasm)?;
+        self.try_add_plugin(host)
+    }
 
-        shell.define_cmd(
-            "list-plugins",
-            Cmd::new("list-plugins", "Print all the plugins currently loaded"),
-            cmds::list_plugin_exec as BuiltinFn,
-        );
+    /// Replaces the engine with one built from `config`, with its own epoch
+    /// thread.
+    fn set_wasm_config(&mut self, config: Config) -> Result<()> {
+        let engine = Engine::new(&config)?;
+        self.epoch_thread = Arc::new(PluginHost::start_epoch_thread(
+            engine.clone(),
+            EPOCH_TICK_INTERVAL,
+        ));
+        self.engine = engine;
+        self.wasm_config = config;
+        Ok(())
+    }
 
-        shell.define_cmd(
+    /// Returns a builder of the host of the plugin at `path`, with the
+    /// options of the shell.
+    fn host_builder(&self, path: impl AsRef<Path>) -> PluginHostBuilder {
+        host_builder(&self.engine, &self.shared, self.plugin_limits, path)
+    }
+
+    /// Initializes the plugin and adds it, returns its name.
+    fn try_add_plugin(&mut self, mut host: PluginHost) -> Result<String, PluginLoadError> {
+        let info = host.try_call_init().map_err(PluginLoadError::Wasm)?;
+        if self.hosts.contains_key(&info.name) {
+            return Err(PluginLoadError::AlreadyLoaded(info.name));
+        }
+        if !self.is_valid_plugin_name(&info.name) {
+            return Err(PluginLoadError::InvalidName(info.name));
+        }
+        let events = host.try_call_list_events().map_err(PluginLoadError::Wasm)?;
+        let name = info.name.clone();
+        self.insert_plugin(host, info, events);
+        Ok(name)
+    }
+
+    /// Does the name pass the validator set with
+    /// [`Shell::with_plugin_name_validator`]?
+    fn is_valid_plugin_name(&self, name: &str) -> bool {
+        self.name_validator
+            .as_ref()
+            .is_none_or(|validator| (validator.0)(name))
+    }
+
+    fn insert_plugin(&mut self, host: PluginHost, info: PluginInfo, events: Vec<EventDescriptor>) {
+        let features = host.supported_wasm_features();
+        for feature in &info.required_features {
+            match features.supports(feature) {
+                Some(true) => {}
+                Some(false) => outln!(
+                    self,
+                    "WARNING: the plugin {} uses the WASM feature {feature}, it isn't enabled",
+                    info.name
+                ),
+                None => outln!(
+                    self,
+                    "WARNING: the plugin {} uses the unknown WASM feature {feature:?}",
+                    info.name
+                ),
+            }
+        }
+
+        if self.plugin_pool_size > 0 {
+            match PluginHostPool::new(&host, self.plugin_pool_size) {
+                Ok(pool) => {
+                    self.pools
+                        .insert(info.name.clone(), Arc::new(Mutex::new(pool)));
+                }
+                Err(err) => outln!(
+                    self,
+                    "ERR: failed to create the pool of {}: {err}",
+                    info.name
+                ),
+            }
+        }
+        self.log_lifecycle(
             "load",
-            Cmd::new("load <path>", "Loads a new plugin."),
-            cmds::load_exec as BuiltinFn,
+            json!({ "plugin": info.name, "version": info.version }),
         );
-
-        shell
+        self.hosts
+            .insert(info.name.clone(), Arc::new(Mutex::new(host)));
+        self.plugins.insert(info.name.clone(), info.clone());
+        self.events.insert(info.name.clone(), events);
+        self.new_cmds.push((info.name, info.commands));
     }
 
-    pub fn run(&mut self) -> Result<()> {
-        let mut input = String::new();
+    /// Watches the WASM file at `path` and reloads the plugin `name` each time
+    /// it is modified. The reload happens on the watcher's thread, the shell
+    /// defines the commands of the new version in [`Shell::handle_new_cmds`].
+    pub fn watch_plugin(&mut self, name: &str, path: PathBuf) -> notify::Result<()> {
+        let Some(host) = self.hosts.get(name) else {
+            return Err(notify::Error::generic(&format!(
+                "there is no plugin named {name:?}"
+            )));
+        };
+        let host = Arc::clone(host);
+        let plugin = name.to_string();
 
-        while self.exec_ctx.running {
-            input.clear();
+        // the file is watched through its directory because build tools tend
+        // to remove the file and create a new one, which ends the watch on it.
+        let file = path.canonicalize()?;
+        let dir = file.parent().map(PathBuf::from).unwrap_or_default();
+        let output = self.shared.output.clone();
+        let reloaded = Arc::clone(&self.reloaded);
 
-            print!(">> ");
-            stdout().flush()?;
+        let mut watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+                let event = match res {
+                    Ok(event) => event,
+                    Err(err) => {
+                        output.write_line(format_args!(
+                            "ERR: failed to watch plugin {plugin}: {err}"
+                        ));
+                        return;
+                    }
+                };
+                if !(event.kind.is_modify() || event.kind.is_create())
+                    || !event.paths.contains(&file)
+                {
+                    return;
+                }
 
-            stdin().read_line(&mut input)?;
+                let mut host = host.lock().unwrap();
+                let res = host
+                    .try_reload(&file)
+                    .and_then(|info| Ok((info, host.try_call_list_events()?)));
+                match res {
+                    Ok((info, events)) => {
+                        output.write_line(format_args!("Plugin {plugin} reloaded"));
+                        reloaded
+                            .lock()
+                            .unwrap()
+                            .push((plugin.clone(), info, events));
+                    }
+                    Err(err) => output
+                        .write_line(format_args!("ERR: failed to reload plugin {plugin}: {err}")),
+                }
+            })?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
 
-            // remove the last character, the newline it's useless.
-            input.pop();
+        self.watchers.insert(name.to_string(), Arc::new(watcher));
+        Ok(())
+    }
 
-            let args = Shell::parse_cmd(&input);
+    /// Returns the aliases with the command they stand for, sorted by alias.
+    pub fn list_aliases(&self) -> Vec<(String, String)> {
+        let mut aliases = self
+            .aliases
+            .iter()
+            .map(|(alias, target)| (alias.clone(), target.clone()))
+            .collect::<Vec<_>>();
+        aliases.sort();
+        aliases
+    }
 
-            if args.len() == 0 {
-                continue;
-            }
+    /// Returns the names of the disabled commands, sorted.
+    pub fn list_disabled_commands(&self) -> Vec<String> {
+        let mut cmds = self.disabled_cmds.iter().cloned().collect::<Vec<_>>();
+        cmds.sort();
+        cmds
+    }
 
-            let Some(runner) = self.runners.get_mut(args[0]) else {
-                eprintln!(
-                    "ERR: unknown command {:?}, type \"help\" to see all commands.",
-                    args[0]
-                );
-                continue;
-            };
+    /// Stops watching the WASM file of the plugin `name`, returns `false` if
+    /// it wasn't watched.
+    pub fn unwatch_plugin(&mut self, name: &str) -> bool {
+        self.watchers.remove(name).is_some()
+    }
 
-            match runner.run(&mut self.exec_ctx, args[0], args[1..].to_vec()) {
-                Ok(()) => {}
-                Err(()) => {
-                    println!("ERROR")
-                }
-            }
+    /// Returns a Graphviz DOT graph of the commands, linked to the plugin
+    /// defining them or to the shell for the built-in ones, and of the
+    /// aliases, linked to the command they stand for.
+    pub fn export_command_graph(&self) -> String {
+        let mut plugins = self.plugins.values().collect::<Vec<_>>();
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+        let aliases = self.list_aliases();
+        let mut builtins = self
+            .cmds
+            .keys()
+            .filter(|name| {
+                !self.aliases.contains_key(*name)
+                    && !plugins
+                        .iter()
+                        .any(|info| info.commands.iter().any(|cmd| &cmd.name == *name))
+            })
+            .collect::<Vec<_>>();
+        builtins.sort();
 
-            self.handle_new_cmds();
+        let mut dot = String::from("digraph commands {\n");
+        dot.push_str("    \"shell\" [shape=box];\n");
+        for name in builtins {
+            dot.push_str(&format!("    \"shell\" -> \"cmd:{name}\";\n"));
+            dot.push_str(&format!("    \"cmd:{name}\" [label=\"{name}\"];\n"));
         }
-        Ok(())
+        for info in plugins {
+            let plugin = &info.name;
+            dot.push_str(&format!(
+                "    \"plugin:{plugin}\" [label=\"{plugin}\", shape=box];\n"
+            ));
+            for cmd in &info.commands {
+                let name = &cmd.name;
+                dot.push_str(&format!("    \"plugin:{plugin}\" -> \"cmd:{name}\";\n"));
+                dot.push_str(&format!("    \"cmd:{name}\" [label=\"{name}\"];\n"));
+            }
+        }
+        for (alias, target) in aliases {
+            dot.push_str(&format!("    \"cmd:{alias}\" [label=\"{alias}\"];\n"));
+            dot.push_str(&format!(
+                "    \"cmd:{alias}\" -> \"cmd:{target}\" [style=dashed];\n"
+            ));
+        }
+        dot.push_str("}\n");
+        dot
     }
 
-    pub fn define_cmd(&mut self, cmd_name: impl ToString, cmd: Cmd, runner: impl Into<Runner>) {
-        let name = cmd_name.to_string();
-
-        if name.len() >= 16
-            && !name.contains(char::is_whitespace)
-            && name.contains(char::is_alphanumeric)
-        {
-            panic!("{name:?} is not a correct command name, it must be 16 charcters or shorter, doesn't contain whitesapces and is alphanumeric")
+    /// Returns an OpenAPI 3.0 JSON document describing each command as a
+    /// `POST /commands/{name}` path, the body of the request is derived from
+    /// the [`CommandSpec`] of the command.
+    pub fn export_openapi_spec(&self) -> String {
+        let mut paths = serde_json::Map::new();
+        for (name, cmd) in &self.cmds {
+            let schema = match &cmd.spec {
+                Some(spec) => {
+                    let mut properties = serde_json::Map::new();
+                    for arg in &spec.positional {
+                        properties.insert(arg.clone(), json!({ "type": "string" }));
+                    }
+                    for flag in &spec.flags {
+                        properties.insert(flag.clone(), json!({ "type": "boolean" }));
+                    }
+                    for flag in &spec.value_flags {
+                        properties.insert(flag.clone(), json!({ "type": "string" }));
+                    }
+                    json!({
+                        "type": "object",
+                        "properties": properties,
+                        "required": spec.positional,
+                    })
+                }
+                None => json!({
+                    "type": "object",
+                    "properties": {
+                        "args": { "type": "array", "items": { "type": "string" } },
+                    },
+                }),
+            };
+            paths.insert(
+                format!("/commands/{name}"),
+                json!({
+                    "post": {
+                        "operationId": name,
+                        "summary": cmd.description,
+                        "description": cmd.usage,
+                        "requestBody": {
+                            "required": true,
+                            "content": { "application/json": { "schema": schema } },
+                        },
+                        "responses": {
+                            "200": { "description": "The command succeeded." },
+                            "500": { "description": "The command failed." },
+                        },
+                    },
+                }),
+            );
         }
 
-        self.exec_ctx.cmds.insert(name.clone(), cmd);
-        self.runners.insert(name.clone(), runner.into());
+        let spec = json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "plugin-app commands",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "paths": paths,
+        });
+        // a `Value` always serializes.
+        serde_json::to_string_pretty(&spec).unwrap()
     }
+}
 
-    pub fn handle_new_cmds(&mut self) {
-        if self.exec_ctx.new_cmds.is_none() {
-            return;
+impl Default for ExecutionCtx {
+    fn default() -> ExecutionCtx {
+        let wasm_config = engine_config();
+        // the default configuration with epochs is valid.
+        let engine = Engine::new(&wasm_config).unwrap();
+        ExecutionCtx {
+            cmds: HashMap::new(),
+            plugins: HashMap::new(),
+            events: HashMap::new(),
+            hosts: HashMap::new(),
+            watchers: HashMap::new(),
+            reloaded: Arc::default(),
+            pools: HashMap::new(),
+            plugin_pool_size: 0,
+            plugin_limits: PluginLimits::default(),
+            name_validator: None,
+            epoch_thread: Arc::new(PluginHost::start_epoch_thread(
+                engine.clone(),
+                EPOCH_TICK_INTERVAL,
+            )),
+            merged_epoch_threads: Vec::new(),
+            wasm_config,
+            engine,
+            shared: SharedCtx::default(),
+            new_cmds: Vec::new(),
+            aliases: HashMap::new(),
+            new_aliases: Vec::new(),
+            running: true,
+            verbose: false,
+            interactive: true,
+            metrics: ShellMetrics::default(),
+            current_privilege: PrivilegeLevel::User,
+            history: VecDeque::new(),
+            last_error: None,
+            current_command: None,
+            lifecycle_logger: None,
+            catch_panics: false,
+            last_panic: None,
+            plugin_idle_ttl: None,
+            no_evict: HashSet::new(),
+            disabled_cmds: HashSet::new(),
+            recording_macro: None,
+            macros: HashMap::new(),
+            new_macros: Vec::new(),
+            plugins_to_unload: Vec::new(),
         }
+    }
+}
 
-        let (plugin_name, commands) = self.exec_ctx.new_cmds.clone().unwrap();
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShellError {
+    /// The input doesn't contain any command.
+    EmptyInput,
+    UnknownCommand(String),
+    /// The interceptor denied the command, with its message.
+    Denied(String),
+    /// The command returned an error.
+    CommandFailed(String),
+    /// The privilege level is too low to run the command.
+    PermissionDenied(String),
+    /// The history expansion refers to an entry that isn't in the history.
+    HistoryOutOfRange(String),
+    /// The command was disabled with [`Shell::disable_cmd`].
+    Disabled(String),
+    /// The built-in command panicked, with the message of the panic, see
+    /// [`Shell::register_panic_hook`].
+    NativePanic(String),
+    /// The deadline of the shell passed, see [`Shell::run_with_deadline`].
+    DeadlineExceeded,
+    /// The input of the shell can't be read, with the error message.
+    InputFailed(String),
+}
 
-        for command in commands {
-            self.define_cmd(
-                command.name,
-                Cmd::new(command.usage, command.description),
-                Runner::Wasm {
-                    plugin: plugin_name.clone(),
-                },
-            );
+impl Display for ShellError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShellError::EmptyInput => write!(f, "there is no command to run"),
+            ShellError::UnknownCommand(name) => write!(
+                f,
+                "unknown command {name:?}, type \"help\" to see all commands."
+            ),
+            ShellError::Denied(msg) => write!(f, "{msg}"),
+            ShellError::CommandFailed(name) => write!(f, "the command {name:?} failed"),
+            ShellError::PermissionDenied(name) => {
+                write!(f, "you don't have the privilege to run {name:?}")
+            }
+            ShellError::HistoryOutOfRange(event) => write!(f, "{event}: event not found"),
+            ShellError::Disabled(name) => write!(f, "the command {name:?} is disabled"),
+            ShellError::NativePanic(msg) => write!(f, "the command panicked: {msg}"),
+            ShellError::DeadlineExceeded => write!(f, "the deadline of the shell passed"),
+            ShellError::InputFailed(err) => write!(f, "failed to read the input: {err}"),
         }
-        self.exec_ctx.new_cmds = None;
     }
+}
+
+impl Error for ShellError {}
+
+/// Error returned when a plugin can't be loaded again, see
+/// [`Shell::try_recover_plugin`].
+#[derive(Debug)]
+pub enum PluginLoadError {
+    /// There is no plugin loaded with this name.
+    NotLoaded(String),
+    /// A plugin with this name is already loaded.
+    AlreadyLoaded(String),
+    /// The name of the plugin is rejected by the name validator of the shell.
+    InvalidName(String),
+    /// The WASM file of the plugin doesn't have the expected hash.
+    HashMismatch(String),
+    /// The plugin or its directory can't be read.
+    Io(std::io::Error),
+    /// The plugin failed to be compiled, instantiated or initialized.
+    Wasm(anyhow::Error),
+}
+
+impl Display for PluginLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginLoadError::NotLoaded(name) => write!(f, "there is no plugin named {name:?}"),
+            PluginLoadError::AlreadyLoaded(name) => {
+                write!(f, "a plugin named {name:?} is already loaded")
+            }
+            PluginLoadError::HashMismatch(name) => {
+                write!(f, "the plugin {name:?} doesn't have the expected hash")
+            }
+            PluginLoadError::InvalidName(name) => {
+                write!(f, "the plugin name {name:?} isn't allowed in this shell")
+            }
+            PluginLoadError::Io(err) => write!(f, "{err}"),
+            PluginLoadError::Wasm(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for PluginLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PluginLoadError::NotLoaded(_)
+            | PluginLoadError::AlreadyLoaded(_)
+            | PluginLoadError::HashMismatch(_)
+            | PluginLoadError::InvalidName(_) => None,
+            PluginLoadError::Io(err) => Some(err),
+            PluginLoadError::Wasm(err) => Some(&**err),
+        }
+    }
+}
+
+/// An error of [`Shell::pipe_through_plugin`].
+#[derive(Debug)]
+pub enum PluginPipelineError {
+    /// A plugin of the pipeline isn't loaded.
+    NotLoaded(PluginLoadError),
+    /// The command of the source plugin returned an error message.
+    CommandFailed(String),
+    /// A call to a plugin failed, eg because of a trap.
+    Wasm(anyhow::Error),
+}
+
+impl Display for PluginPipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginPipelineError::NotLoaded(err) => write!(f, "{err}"),
+            PluginPipelineError::CommandFailed(msg) => {
+                write!(f, "the source command failed: {msg}")
+            }
+            PluginPipelineError::Wasm(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for PluginPipelineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PluginPipelineError::NotLoaded(err) => Some(err),
+            PluginPipelineError::CommandFailed(_) => None,
+            PluginPipelineError::Wasm(err) => Some(&**err),
+        }
+    }
+}
+
+/// The plugins that failed to load, with their error, see
+/// [`Shell::with_auto_load_dir`].
+#[derive(Debug)]
+pub struct AutoLoadErrors(pub Vec<(PathBuf, PluginLoadError)>);
+
+impl Display for AutoLoadErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} plugins failed to load", self.0.len())?;
+        for (path, err) in &self.0 {
+            write!(f, "\n  {path:?}: {err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for AutoLoadErrors {}
+
+/// What would happen if a command was run, see [`Shell::dry_run_command`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DryRunInfo {
+    /// Is the command defined?
+    pub found: bool,
+    pub cmd_name: String,
+    pub args: Vec<String>,
+    /// Does the command accept this number of arguments? `false` if the
+    /// command isn't defined.
+    pub arg_count_valid: bool,
+    /// The kind of the command, `None` if it isn't defined
+    pub kind: Option<CmdKind>,
+}
+
+/// What to do with a command once it went through the interceptor, see
+/// [`Shell::with_command_interceptor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterceptResult {
+    /// Run the command as is.
+    Allow,
+    /// Don't run the command and print the message instead.
+    Deny(String),
+    /// Run the given command with the given arguments instead.
+    Replace(String, Vec<String>),
+}
+
+type Interceptor = Arc<dyn Fn(&str, &[String]) -> InterceptResult + Send + Sync>;
+
+/// Returns the completions of the partial argument it's given, see
+/// [`Shell::register_completion_hook`].
+type CompletionHook = Arc<dyn Fn(&str) -> Vec<String> + Send + Sync>;
+
+/// Why the main loop of the shell stopped, see [`Shell::run_with_deadline`].
+enum RunExit {
+    /// The input is over or the user quit.
+    Done,
+    DeadlineExceeded,
+}
+
+/// What the shell does after a command failed, see
+/// [`Shell::with_error_recovery`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecoveryStrategy {
+    /// Print the error and continue.
+    #[default]
+    Continue,
+    /// Print the error and stop, [`Shell::run`] returns the error.
+    StopOnError,
+    /// Print the error and recover the plugin defining the command, see
+    /// [`Shell::try_recover_plugin`].
+    RestartPlugin,
+}
+
+/// Which command is kept when two shells define the same command, see
+/// [`Shell::merge_into`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShadowPolicy {
+    /// The command already defined is kept.
+    #[default]
+    KeepExisting,
+    /// The command of the merged shell replaces the command already defined.
+    Replace,
+}
+
+/// How the plugins of a directory are loaded, see
+/// [`Shell::with_plugin_init_order`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoadOrder {
+    /// The plugins are loaded one after the other.
+    #[default]
+    Sequential,
+    /// The plugins are compiled concurrently by this number of threads, the
+    /// number of CPUs if it's 0. They are still initialized one after the
+    /// other, in the order of their names.
+    Parallel(usize),
+}
+
+/// Called with the error of each command that failed, see
+/// [`Shell::on_error`].
+type ErrorHook = Arc<dyn Fn(&ShellError) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Shell {
+    /// Maps the command name to its runner
+    runners: HashMap<String, Runner>,
+    exec_ctx: ExecutionCtx,
+    /// Called with every command before it is run
+    interceptor: Option<Interceptor>,
+    /// Maps a command name to the hook completing its arguments
+    completion_hooks: HashMap<String, CompletionHook>,
+    /// Called with the error of each command that failed
+    error_hooks: Vec<ErrorHook>,
+    /// Maximum length of an input line in bytes, the longer lines are
+    /// rejected
+    max_input_len: Option<usize>,
+    /// The macros being run, the innermost last
+    running_macros: Vec<String>,
+    /// What to do after a command failed in [`Shell::run`]
+    recovery: RecoveryStrategy,
+    /// Which command is kept when merging a shell defining the same command
+    shadow_policy: ShadowPolicy,
+    /// Where the input lines are read by [`Shell::run`]
+    input: Input,
+    /// The names of the commands defined by [`Shell::new`]
+    builtins: HashSet<String>,
+    /// If the built-in commands can't be unregistered
+    protect_builtins: bool,
+    /// [`Shell::run`] exits once this instant is passed
+    deadline: Option<Instant>,
+    /// How the plugins of the auto-load directory are loaded
+    load_order: LoadOrder,
+}
+
+impl Debug for Shell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shell")
+            .field("runners", &self.runners)
+            .field("exec_ctx", &self.exec_ctx)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Shell {
+    pub fn new() -> Shell {
+        let mut shell = Shell {
+            runners: HashMap::new(),
+            exec_ctx: ExecutionCtx::default(),
+            interceptor: None,
+            completion_hooks: HashMap::new(),
+            error_hooks: Vec::new(),
+            max_input_len: None,
+            running_macros: Vec::new(),
+            recovery: RecoveryStrategy::Continue,
+            shadow_policy: ShadowPolicy::KeepExisting,
+            input: Input::default(),
+            builtins: HashSet::new(),
+            protect_builtins: true,
+            deadline: None,
+            load_order: LoadOrder::Sequential,
+        };
+
+        // the names of the built-in commands are valid, so defining them
+        // can't fail and we can unwrap.
+        shell
+            .define_cmd(
+                "quit",
+                Cmd::new("quit", "Quit the shell."),
+                (|ctx, _, _| {
+                    ctx.running = false;
+                    Ok(())
+                }) as BuiltinFn,
+            )
+            .unwrap();
+
+        shell.define_cmd(
+            "help",
+            Cmd::new("help [cmd..]", "Print all commands to the screen or an helpful message if a command is passed as argument"),
+            cmds::help_exec as BuiltinFn
+        ).unwrap();
+
+        shell
+            .define_cmd(
+                "list-plugins",
+                Cmd::new("list-plugins", "Print all the plugins currently loaded"),
+                cmds::list_plugin_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "plugin-info",
+                Cmd::new("plugin-info <name>", "Print the informations of a plugin."),
+                cmds::plugin_info_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "events",
+                Cmd::new(
+                    "events <plugin>",
+                    "Print the events a plugin can emit, and their subscribers.",
+                ),
+                cmds::events_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        #[cfg(feature = "debug")]
+        shell
+            .define_cmd(
+                "disasm",
+                Cmd::new(
+                    "disasm <name>",
+                    "Print the WebAssembly text format of a plugin.",
+                ),
+                cmds::disasm_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        #[cfg(feature = "debug")]
+        shell
+            .define_cmd(
+                "store-stats",
+                Cmd::new(
+                    "store-stats <name>",
+                    "Print the fuel, the epoch ticks and the memory of the store of a plugin.",
+                ),
+                cmds::store_stats_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "telemetry",
+                Cmd::new(
+                    "telemetry [plugin]",
+                    "Print the counters kept by a plugin, or by all of them.",
+                ),
+                cmds::telemetry_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "check-conformance",
+                Cmd::new(
+                    "check-conformance <name>",
+                    "Check that a plugin exports all the functions of the plugin interface.",
+                ),
+                cmds::conformance_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "latency",
+                Cmd::new(
+                    "latency <name>",
+                    "Print the percentiles of the durations of the calls to a plugin.",
+                ),
+                cmds::latency_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "inspect-wit",
+                Cmd::new(
+                    "inspect-wit <name>",
+                    "Print the imports and exports of a plugin.",
+                ),
+                cmds::inspect_wit_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "search",
+                Cmd::new(
+                    "search <keyword>",
+                    "Print the plugins whose name, description or categories contain the keyword",
+                ),
+                cmds::search_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "load",
+                Cmd::new("load [--no-evict] <path>", "Loads a new plugin.")
+                    .with_min_privilege(PrivilegeLevel::Admin),
+                cmds::load_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "unload",
+                Cmd::new(
+                    "unload <name>",
+                    "Unloads a plugin and removes its commands.",
+                )
+                .with_min_privilege(PrivilegeLevel::Admin),
+                cmds::unload_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "load-precompiled",
+                Cmd::new(
+                    "load-precompiled <path>",
+                    "Loads a new plugin precompiled to a `.cwasm` file.",
+                )
+                .with_min_privilege(PrivilegeLevel::Admin),
+                cmds::load_precompiled_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "env",
+                Cmd::new("env", "Print all the variables of the shell environment."),
+                cmds::env_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "task-list",
+                Cmd::new("task-list", "Print the tasks spawned by the plugins"),
+                cmds::task_list_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "health",
+                Cmd::new("health", "Print if the plugins are still usable"),
+                cmds::health_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "alias",
+                Cmd::new("alias <name> <cmd>", "Defines another name for a command."),
+                cmds::alias_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "aliases",
+                Cmd::new("aliases", "Print all the aliases."),
+                cmds::aliases_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "macro-record",
+                Cmd::new(
+                    "macro-record <name>",
+                    "Records the next commands in a macro, until macro-end.",
+                ),
+                cmds::macro_record_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "macro-end",
+                Cmd::new("macro-end", "Stops recording the macro and defines it."),
+                cmds::macro_end_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "macro-save",
+                Cmd::new(
+                    "macro-save <name> [path]",
+                    "Saves a macro to a TOML file, <name>.toml by default.",
+                ),
+                cmds::macro_save_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "disabled-cmds",
+                Cmd::new("disabled-cmds", "Print the disabled commands.")
+                    .with_min_privilege(PrivilegeLevel::Admin),
+                cmds::disabled_cmds_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "metrics",
+                Cmd::new("metrics", "Print how many times each command was run"),
+                cmds::metrics_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "error",
+                Cmd::new("error", "Print the error of the last command"),
+                cmds::error_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "watch",
+                Cmd::new(
+                    "watch <plugin> <path>",
+                    "Reloads a plugin each time its WASM file changes.",
+                ),
+                cmds::watch_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "unwatch",
+                Cmd::new(
+                    "unwatch <plugin>",
+                    "Stops reloading a plugin when its WASM file changes.",
+                ),
+                cmds::unwatch_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "export-graph",
+                Cmd::new(
+                    "export-graph <output.dot>",
+                    "Writes a Graphviz graph of the commands and the plugins defining them.",
+                ),
+                cmds::export_graph_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell
+            .define_cmd(
+                "export-api",
+                Cmd::new(
+                    "export-api <output.json>",
+                    "Writes an OpenAPI document describing the commands.",
+                ),
+                cmds::export_api_exec as BuiltinFn,
+            )
+            .unwrap();
+
+        shell.builtins = shell.runners.keys().cloned().collect();
+        shell
+    }
+
+    /// Sets the privilege level of the user, the commands requiring a higher
+    /// level are denied. The level is [`PrivilegeLevel::User`] by default.
+    pub fn set_privilege(&mut self, level: PrivilegeLevel) {
+        self.exec_ctx.current_privilege = level;
+    }
+
+    /// Only loads the plugins with a name accepted by `validator`, eg to
+    /// require a prefix like `org.mycompany.`. The other plugins fail to load
+    /// with [`PluginLoadError::InvalidName`].
+    pub fn with_plugin_name_validator(
+        mut self,
+        validator: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Shell {
+        self.exec_ctx.name_validator = Some(NameValidator(Arc::new(validator)));
+        self
+    }
+
+    /// Only lets the plugins read the files in `dir`, with the `read-file`
+    /// import, without a sandbox directory they can't read any file. It must
+    /// be set before the plugins are loaded.
+    pub fn with_plugin_sandbox_dir(mut self, dir: PathBuf) -> Shell {
+        self.exec_ctx.shared.sandbox_dir = Some(dir);
+        self
+    }
+
+    /// Sets the locale of the user, eg `fr` or `en-US`, the plugins get it to
+    /// localize their output. It must be set before the plugins are loaded.
+    pub fn with_locale(mut self, locale: &str) -> Shell {
+        self.exec_ctx.shared.locale = Some(locale.to_string());
+        self
+    }
+
+    /// Sets if a user is typing the commands, the shell is interactive by
+    /// default. A non-interactive shell, eg running a script piped to its
+    /// input, doesn't print the `>> ` prompt.
+    pub fn with_interactive(mut self, interactive: bool) -> Shell {
+        self.exec_ctx.interactive = interactive;
+        self
+    }
+
+    /// Makes [`Shell::run`] exit once `deadline` is passed, eg in a CI job,
+    /// see [`Shell::run_with_deadline`].
+    pub fn with_deadline(mut self, deadline: Instant) -> Shell {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Reads the input lines from `reader` instead of the standard input.
+    pub fn with_stdin(mut self, reader: impl BufRead + Send + 'static) -> Shell {
+        self.input = Input::new(reader);
+        self
+    }
+
+    /// Writes the output of the shell and of its plugins to `writer` instead
+    /// of the standard output.
+    pub fn with_stdout(mut self, writer: impl Write + Send + 'static) -> Shell {
+        self.exec_ctx.shared.output = self.exec_ctx.shared.output.with_writer(writer);
+        self
+    }
+
+    /// Creates a shell writing its output to a buffer, the lines written are
+    /// returned by [`OutputCapture::take_lines`].
+    pub fn with_output_capture() -> (Shell, OutputCapture) {
+        let capture = OutputCapture::default();
+        let shell = Shell::new().with_stdout(capture.clone());
+        (shell, capture)
+    }
+
+    /// Prevents the plugins from reading the environment of the host process,
+    /// they can only read the shell environment.
+    pub fn with_sandboxed_env(mut self) -> Shell {
+        self.exec_ctx.shared.sandboxed_env = true;
+        self
+    }
+
+    /// Prints the full error, with the wasm backtrace, when a plugin fails
+    /// instead of a one line summary.
+    pub fn with_verbose(mut self, verbose: bool) -> Shell {
+        self.exec_ctx.verbose = verbose;
+        self
+    }
+
+    /// Gives `fuel` units of fuel to each plugin loaded, a plugin traps once
+    /// it consumed all of them. It replaces the engine with one consuming
+    /// fuel, so it must be called before loading any plugin.
+    pub fn with_plugin_fuel_limit(mut self, fuel: u64) -> Shell {
+        let mut config = self.exec_ctx.wasm_config.clone();
+        config.consume_fuel(true);
+        // the configuration was valid without fuel.
+        self.exec_ctx.set_wasm_config(config).unwrap();
+        self.exec_ctx.plugin_limits.fuel = Some(fuel);
+        self
+    }
+
+    /// Replaces the engine with one built from `config`, eg to disable a WASM
+    /// feature, so it must be called before loading any plugin. Epoch
+    /// interruption is enabled on it, and fuel if
+    /// [`Shell::with_plugin_fuel_limit`] was called.
+    pub fn try_with_wasm_config(mut self, mut config: Config) -> Result<Shell> {
+        config.epoch_interruption(true);
+        config.consume_fuel(self.exec_ctx.plugin_limits.fuel.is_some());
+        self.exec_ctx.set_wasm_config(config)?;
+        Ok(self)
+    }
+
+    #[track_caller]
+    pub fn with_wasm_config(self, config: Config) -> Shell {
+        self.try_with_wasm_config(config).unwrap()
+    }
+
+    /// Interrupts the calls to the plugins loaded that last longer than
+    /// `max_epochs` ticks of [`EPOCH_TICK_INTERVAL`].
+    pub fn with_plugin_max_epochs(mut self, max_epochs: u64) -> Shell {
+        self.exec_ctx.plugin_limits.max_epochs = Some(max_epochs);
+        self
+    }
+
+    /// Drops the messages logged by a call to a plugin after the first `max`
+    /// ones, a warning tells how many were dropped.
+    pub fn with_max_log_messages_per_call(mut self, max: usize) -> Shell {
+        self.exec_ctx.plugin_limits.max_log_per_call = Some(max);
+        self
+    }
+
+    /// Builds a shell from its configuration: it loads the plugins, then
+    /// defines the aliases and disables the commands.
+    pub fn from_config(config: &ShellConfig) -> Result<Shell, ConfigError> {
+        let mut shell = Shell::new().with_verbose(config.verbose);
+        if let Some(fuel) = config.fuel_limit {
+            shell = shell.with_plugin_fuel_limit(fuel);
+        }
+
+        for path in &config.plugins {
+            shell
+                .exec_ctx
+                .try_load_plugin(path.clone())
+                .map_err(|err| ConfigError::Plugin(path.clone(), err))?;
+        }
+        shell.handle_new_cmds();
+
+        let mut aliases = config.aliases.iter().collect::<Vec<_>>();
+        aliases.sort();
+        for (alias, target) in aliases {
+            shell
+                .define_alias(alias, target)
+                .map_err(ConfigError::Alias)?;
+        }
+        for name in &config.disabled_commands {
+            shell
+                .disable_cmd(name)
+                .map_err(ConfigError::DisabledCommand)?;
+        }
+        Ok(shell)
+    }
+
+    /// Builds a shell from its configuration written in TOML, see
+    /// [`ShellConfig`].
+    pub fn import_from_toml(toml: &str) -> Result<Shell, ConfigError> {
+        Shell::from_config(&ShellConfig::from_toml(toml)?)
+    }
+
+    /// Unloads the plugins whose commands weren't run for longer than `ttl`,
+    /// it is checked before each command. The plugins loaded with `load
+    /// --no-evict` are kept.
+    pub fn with_plugin_idle_ttl(mut self, ttl: Duration) -> Shell {
+        self.exec_ctx.plugin_idle_ttl = Some(ttl);
+        self
+    }
+
+    /// Loads all the `*.wasm` files of `dir`, in the order of their names,
+    /// returns the errors of the plugins that failed to load.
+    pub fn with_auto_load_dir(mut self, dir: impl AsRef<Path>) -> Result<Shell, AutoLoadErrors> {
+        let errors = self.auto_load_dir(dir.as_ref());
+        if !errors.is_empty() {
+            return Err(AutoLoadErrors(errors));
+        }
+        Ok(self)
+    }
+
+    /// Loads all the `*.wasm` files of `dir` like
+    /// [`Shell::with_auto_load_dir`], but only logs the plugins that failed
+    /// to load.
+    pub fn with_auto_load_dir_lenient(mut self, dir: impl AsRef<Path>) -> Shell {
+        for (path, err) in self.auto_load_dir(dir.as_ref()) {
+            tracing::warn!("failed to load the plugin {path:?}: {err}");
+        }
+        self
+    }
+
+    fn auto_load_dir(&mut self, dir: &Path) -> Vec<(PathBuf, PluginLoadError)> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => return vec![(dir.to_path_buf(), PluginLoadError::Io(err))],
+        };
+
+        let mut errors = Vec::new();
+        let mut paths = Vec::new();
+        for entry in entries {
+            match entry {
+                Ok(entry) => paths.push(entry.path()),
+                Err(err) => errors.push((dir.to_path_buf(), PluginLoadError::Io(err))),
+            }
+        }
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "wasm"));
+        paths.sort();
+
+        let threads = match self.load_order {
+            LoadOrder::Sequential => {
+                for path in paths {
+                    if let Err(err) = self.exec_ctx.try_load_plugin(path.clone()) {
+                        errors.push((path, err));
+                    }
+                }
+                self.handle_new_cmds();
+                return errors;
+            }
+            LoadOrder::Parallel(threads) => threads,
+        };
+
+        let ctx = &self.exec_ctx;
+        let (engine, shared, limits) = (&ctx.engine, &ctx.shared, ctx.plugin_limits);
+        let compile = |path: &PathBuf| {
+            host_builder(engine, shared, limits, path)
+                .build()
+                .map_err(PluginLoadError::Wasm)
+        };
+        // compiling a component is thread-safe, the plugins are only
+        // initialized on this thread.
+        let hosts = match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+            Ok(pool) => pool.install(|| paths.par_iter().map(compile).collect::<Vec<_>>()),
+            Err(err) => {
+                tracing::warn!("failed to start the threads loading the plugins: {err}");
+                paths.iter().map(compile).collect()
+            }
+        };
+
+        for (path, host) in paths.into_iter().zip(hosts) {
+            if let Err(err) = host.and_then(|host| self.exec_ctx.try_add_plugin(host)) {
+                errors.push((path, err));
+            }
+        }
+        self.handle_new_cmds();
+        errors
+    }
+
+    /// Sets how [`Shell::with_auto_load_dir`] loads the plugins, one after the
+    /// other by default. It must be called before loading the directory.
+    pub fn with_plugin_init_order(mut self, order: LoadOrder) -> Shell {
+        self.load_order = order;
+        self
+    }
+
+    /// Keeps `size` spare initialized instances of each plugin loaded, see
+    /// [`PluginHostPool`]. A plugin is recovered with a spare instance
+    /// instead of being loaded again from its file, see
+    /// [`Shell::try_recover_plugin`].
+    pub fn with_plugin_pool_size(mut self, size: usize) -> Shell {
+        self.exec_ctx.plugin_pool_size = size;
+        self
+    }
+
+    /// Sets what [`Shell::run`] does after a command failed, by default it
+    /// continues.
+    pub fn with_error_recovery(mut self, strategy: RecoveryStrategy) -> Shell {
+        self.recovery = strategy;
+        self
+    }
+
+    /// Sets which command is kept when [`Shell::merge_into`] merges a shell
+    /// defining the same command, by default the command already defined.
+    pub fn with_shadow_policy(mut self, policy: ShadowPolicy) -> Shell {
+        self.shadow_policy = policy;
+        self
+    }
+
+    /// Rejects the input lines longer than `max` bytes, before they are
+    /// parsed.
+    pub fn with_max_command_length(mut self, max: usize) -> Shell {
+        self.max_input_len = Some(max);
+        self
+    }
+
+    /// Calls `f` with the name and the arguments of every command before it
+    /// is run, it decides if the command is run, denied or replaced.
+    pub fn with_command_interceptor(
+        mut self,
+        f: impl Fn(&str, &[String]) -> InterceptResult + Send + Sync + 'static,
+    ) -> Shell {
+        self.interceptor = Some(Arc::new(f));
+        self
+    }
+
+    /// Registers a hook called with the error of each command that failed,
+    /// eg to forward the errors to a monitoring system.
+    pub fn on_error(&mut self, hook: impl Fn(&ShellError) + Send + Sync + 'static) {
+        self.error_hooks.push(Arc::new(hook));
+    }
+
+    /// Writes the lifecycle events of the shell to `output` as
+    /// newline-delimited JSON, eg for an audit log. The events are `load`,
+    /// `unload`, `command-start`, `command-end` and `error`, each of them has
+    /// a `ts` field, the milliseconds since the Unix epoch. It replaces the
+    /// previous logger.
+    pub fn register_lifecycle_logger(&mut self, output: impl Write + Send + 'static) {
+        self.exec_ctx.lifecycle_logger = Some(LifecycleLogger::new(output));
+    }
+
+    /// Registers a hook completing the arguments of the command `cmd_name`, it
+    /// is given the partial argument being typed and returns the candidates.
+    /// It replaces the previous hook of the command.
+    pub fn register_completion_hook(
+        &mut self,
+        cmd_name: &str,
+        hook: impl Fn(&str) -> Vec<String> + Send + Sync + 'static,
+    ) {
+        self.completion_hooks
+            .insert(cmd_name.to_string(), Arc::new(hook));
+    }
+
+    /// Registers a hook transforming each line written by the shell and its
+    /// plugins, eg to prepend a timestamp. The hooks are applied in the order
+    /// they were registered.
+    pub fn register_format_hook(&mut self, hook: impl Fn(&str) -> String + Send + Sync + 'static) {
+        self.exec_ctx.shared.output.add_hook(Arc::new(hook));
+    }
+
+    /// Returns the completions of the last word of `line`, the command names
+    /// if it's the first word, otherwise the candidates of the completion hook
+    /// of the command, if it has one.
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let words = Shell::parse_cmd(line);
+        let partial = if line.ends_with(char::is_whitespace) {
+            ""
+        } else {
+            words.last().map(String::as_str).unwrap_or_default()
+        };
+
+        if words.is_empty() || (words.len() == 1 && partial == words[0]) {
+            let mut names = self
+                .runners
+                .keys()
+                .filter(|name| name.starts_with(partial))
+                .cloned()
+                .collect::<Vec<_>>();
+            names.sort();
+            return names;
+        }
+
+        match self.completion_hooks.get(&words[0]) {
+            Some(hook) => hook(partial),
+            None => Vec::new(),
+        }
+    }
+
+    /// Runs the command `name` with the given arguments after it went through
+    /// the interceptor, then defines the commands of the plugins it loaded.
+    ///
+    /// `$?` in the arguments is replaced with the status of the last command,
+    /// `0` if it succeeded and `1` if it failed.
+    pub fn run_cmd(&mut self, name: &str, args: Vec<String>) -> Result<(), ShellError> {
+        let status = if self.exec_ctx.last_error.is_some() {
+            "1"
+        } else {
+            "0"
+        };
+        let args = args
+            .into_iter()
+            .map(|arg| arg.replace("$?", status))
+            .collect();
+
+        // the commands run by a macro are recorded as the macro itself.
+        if let Some((_, commands)) = &mut self.exec_ctx.recording_macro {
+            if self.running_macros.is_empty() && name != "macro-end" {
+                commands.push((name.to_string(), args.clone()));
+            }
+        }
+
+        self.evict_idle_plugins();
+        let res = self.dispatch_cmd(name, args);
+        if let Err(err) = &res {
+            self.exec_ctx.log_lifecycle(
+                "error",
+                json!({ "command": name, "error": err.to_string() }),
+            );
+            for hook in &self.error_hooks {
+                hook(err);
+            }
+        }
+        self.exec_ctx.last_error = res.as_ref().err().map(ToString::to_string);
+        res
+    }
+
+    fn dispatch_cmd(&mut self, name: &str, args: Vec<String>) -> Result<(), ShellError> {
+        let mut name = name.to_string();
+        let mut args = args;
+
+        if let Some(interceptor) = &self.interceptor {
+            match interceptor(&name, &args) {
+                InterceptResult::Allow => {}
+                InterceptResult::Deny(msg) => return Err(ShellError::Denied(msg)),
+                InterceptResult::Replace(new_name, new_args) => {
+                    name = new_name;
+                    args = new_args;
+                }
+            }
+        }
+
+        let Some(runner) = self.runners.get(&name) else {
+            return Err(ShellError::UnknownCommand(name));
+        };
+        let min_privilege = self.exec_ctx.cmds.get(&name).map(|cmd| cmd.min_privilege);
+        if Some(self.exec_ctx.current_privilege) < min_privilege {
+            return Err(ShellError::PermissionDenied(name));
+        }
+
+        // an alias runs its target, under the name of the target.
+        let (runner, cmd_name) = match runner {
+            Runner::Aliased { target } => match self.runners.get(target) {
+                Some(runner) => (runner, target.as_str()),
+                None => return Err(ShellError::UnknownCommand(target.clone())),
+            },
+            runner => (runner, name.as_str()),
+        };
+        if self.exec_ctx.disabled_cmds.contains(cmd_name) {
+            return Err(ShellError::Disabled(cmd_name.to_string()));
+        }
+
+        if let Runner::Macro { commands } = runner {
+            let macro_name = cmd_name.to_string();
+            let commands = commands.clone();
+            let res = self.run_macro(&macro_name, commands);
+            self.exec_ctx.metrics.record(&name, res.is_err());
+            return res.map_err(|()| ShellError::CommandFailed(name));
+        }
+
+        if self
+            .exec_ctx
+            .cmds
+            .get(cmd_name)
+            .is_some_and(|cmd| cmd.experimental)
+        {
+            outln!(
+                self.exec_ctx,
+                "WARNING: '{cmd_name}' is an experimental command and may change"
+            );
+        }
+
+        let res = runner.run(&mut self.exec_ctx, cmd_name, args);
+        self.exec_ctx.metrics.record(&name, res.is_err());
+        self.handle_new_cmds();
+        if let Some(msg) = self.exec_ctx.last_panic.take() {
+            return Err(ShellError::NativePanic(msg));
+        }
+        res.map_err(|()| ShellError::CommandFailed(name))
+    }
+
+    /// Runs the commands of the macro in order, it stops at the first one
+    /// that fails.
+    fn run_macro(&mut self, name: &str, commands: Vec<(String, Vec<String>)>) -> Result<(), ()> {
+        if self.running_macros.iter().any(|running| running == name) {
+            outln!(self.exec_ctx, "ERR: the macro {name:?} runs itself");
+            return Err(());
+        }
+
+        self.running_macros.push(name.to_string());
+        let res = commands
+            .into_iter()
+            .try_for_each(|(cmd, args)| self.run_cmd(&cmd, args));
+        self.running_macros.pop();
+
+        res.map_err(|err| outln!(self.exec_ctx, "ERR: {err}"))
+    }
+
+    /// Runs the commands of the script in order, even if some of them fail,
+    /// and returns the result of each command.
+    pub fn run_script_obj(&mut self, script: &Script) -> Vec<Result<(), ShellError>> {
+        script
+            .lines()
+            .iter()
+            .filter_map(|line| match line {
+                ScriptLine::Command(name, args) => Some(self.run_cmd(name, args.clone())),
+                ScriptLine::Comment(_) => None,
+            })
+            .collect()
+    }
+
+    /// Runs the shell like [`Shell::run`], it exits cleanly once `deadline`
+    /// is passed and returns [`ShellError::DeadlineExceeded`]. The deadline
+    /// is checked before each input line, a command running when it passes
+    /// isn't interrupted.
+    pub fn run_with_deadline(&mut self, deadline: Instant) -> Result<(), ShellError> {
+        self.deadline = Some(deadline);
+        match self.run_loop() {
+            Ok(exit) => {
+                self.shutdown();
+                match exit {
+                    RunExit::Done => Ok(()),
+                    RunExit::DeadlineExceeded => Err(ShellError::DeadlineExceeded),
+                }
+            }
+            Err(err) => Err(err
+                .downcast::<ShellError>()
+                .unwrap_or_else(|err| ShellError::InputFailed(err.to_string()))),
+        }
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        self.run_loop()?;
+        self.shutdown();
+        Ok(())
+    }
+
+    /// Reads and runs the input lines until the shell stops, without shutting
+    /// it down unless it stops on an error, see [`RecoveryStrategy`].
+    fn run_loop(&mut self) -> Result<RunExit> {
+        let mut input = String::new();
+
+        while self.exec_ctx.running {
+            input.clear();
+
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                tracing::info!("the deadline of the shell passed, exiting");
+                return Ok(RunExit::DeadlineExceeded);
+            }
+
+            if self.exec_ctx.interactive {
+                out!(self.exec_ctx, ">> ");
+            }
+
+            // the input is over, eg the user typed Ctrl-D.
+            if self.input.read_line(&mut input)? == 0 {
+                break;
+            }
+            if let Some(recorder) = self.exec_ctx.shared.output.recorder() {
+                recorder.record("i", &input);
+            }
+
+            // remove the line ending, `\n` or `\r\n`, the last line may have
+            // none.
+            let input = input.trim_end_matches(['\n', '\r']);
+
+            if let Some(max) = self.max_input_len.filter(|max| input.len() > *max) {
+                outln!(
+                    self.exec_ctx,
+                    "ERR: the input is too long, it must be {max} bytes or shorter"
+                );
+                continue;
+            }
+
+            let line = match Shell::parse_cmd_with_history_expansion(input, &self.exec_ctx.history)
+            {
+                Ok(line) => line,
+                Err(err) => {
+                    outln!(self.exec_ctx, "ERR: {err}");
+                    continue;
+                }
+            };
+            if line != input {
+                outln!(self.exec_ctx, "{line}");
+            }
+            if !line.trim().is_empty() {
+                if self.exec_ctx.history.len() == MAX_HISTORY_LEN {
+                    self.exec_ctx.history.pop_front();
+                }
+                self.exec_ctx
+                    .history
+                    .push_back((Instant::now(), line.clone()));
+            }
+
+            let mut args = Shell::parse_cmd(&line);
+
+            if args.len() == 0 {
+                continue;
+            }
+            let name = args.remove(0);
+
+            if let Err(err) = self.run_cmd(&name, args) {
+                outln!(self.exec_ctx, "ERR: {err}");
+                match self.recovery {
+                    RecoveryStrategy::Continue => {}
+                    RecoveryStrategy::StopOnError => {
+                        self.shutdown();
+                        return Err(err.into());
+                    }
+                    RecoveryStrategy::RestartPlugin => {
+                        if let Some(plugin) = self.plugin_of_cmd(&name) {
+                            match self.try_recover_plugin(&plugin) {
+                                Ok(()) => outln!(self.exec_ctx, "Plugin {plugin} recovered"),
+                                Err(err) => {
+                                    outln!(
+                                        self.exec_ctx,
+                                        "ERR: failed to recover plugin {plugin}: {err}"
+                                    )
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(RunExit::Done)
+    }
+
+    /// Returns the name of the plugin defining the command `name`, or the
+    /// command it stands for if it's an alias.
+    fn plugin_of_cmd(&self, name: &str) -> Option<String> {
+        let runner = match self.runners.get(name)? {
+            Runner::Aliased { target } => self.runners.get(target)?,
+            runner => runner,
+        };
+        match runner {
+            Runner::Wasm { plugin } => Some(plugin.clone()),
+            _ => None,
+        }
+    }
+
+    /// Catches the panics of the built-in commands, the command fails with
+    /// [`ShellError::NativePanic`] and the shell keeps running. The hook is
+    /// installed once per process: the panics of the built-in commands are
+    /// only logged, the other ones are passed to the previous panic hook.
+    pub fn register_panic_hook(&mut self) {
+        PANIC_HOOK.call_once(|| {
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                if CATCHING_PANICS.get() {
+                    tracing::error!("{info}");
+                } else {
+                    previous(info);
+                }
+            }));
+        });
+        self.exec_ctx.catch_panics = true;
+    }
+
+    /// Records the session in the asciinema v2 format to `output`, eg for a
+    /// demo: the header first, then an event for each input line and each
+    /// output, and an `EOF` marker on [`Shell::shutdown`].
+    pub fn record_session(&mut self, output: impl Write + Send + 'static) {
+        self.exec_ctx
+            .shared
+            .output
+            .set_recorder(SessionRecorder::new(output));
+    }
+
+    /// Unloads all the plugins, the last loaded first, so that they can clean
+    /// up before the shell exits. It ends the recording of the session.
+    pub fn shutdown(&mut self) {
+        let mut plugins = self
+            .exec_ctx
+            .hosts
+            .iter()
+            .map(|(name, host)| (host.lock().unwrap().uptime(), name.clone()))
+            .collect::<Vec<_>>();
+        plugins.sort();
+        for (_, name) in plugins {
+            self.unload_plugin(&name);
+        }
+        if let Some(recorder) = self.exec_ctx.shared.output.take_recorder() {
+            recorder.finish();
+        }
+    }
+
+    pub fn define_cmd(
+        &mut self,
+        cmd_name: impl ToString,
+        cmd: Cmd,
+        runner: impl Into<Runner>,
+    ) -> Result<(), CmdRegistrationError> {
+        let name = cmd_name.to_string();
+        validate_cmd_name(&name)?;
+
+        self.exec_ctx.cmds.insert(name.clone(), cmd);
+        self.runners.insert(name.clone(), runner.into());
+        Ok(())
+    }
+
+    /// Defines a command like [`Shell::define_cmd`], `after_hook` is called
+    /// after each successful run of the command, eg to refresh a menu after
+    /// `load`.
+    pub fn define_cmd_with_hook(
+        &mut self,
+        cmd_name: impl ToString,
+        cmd: Cmd,
+        runner: impl Into<Runner>,
+        after_hook: impl Fn(&ExecutionCtx) + Send + Sync + 'static,
+    ) -> Result<(), CmdRegistrationError> {
+        let cmd = Cmd {
+            after_hook: Some(Arc::new(after_hook)),
+            ..cmd
+        };
+        self.define_cmd(cmd_name, cmd, runner)
+    }
+
+    /// Removes a command, eg `load` in a locked-down deployment. The built-in
+    /// commands can only be removed after `protect_builtins(false)`.
+    pub fn unregister_cmd(&mut self, name: &str) -> Result<(), CmdError> {
+        if !self.runners.contains_key(name) {
+            return Err(CmdError::NotFound(name.to_string()));
+        }
+        if self.protect_builtins && self.builtins.contains(name) {
+            return Err(CmdError::IsBuiltin(name.to_string()));
+        }
+
+        self.runners.remove(name);
+        self.exec_ctx.cmds.remove(name);
+        self.exec_ctx.disabled_cmds.remove(name);
+        self.completion_hooks.remove(name);
+        Ok(())
+    }
+
+    /// Disables a command, running it fails with [`ShellError::Disabled`]
+    /// until it is enabled again, eg in a restricted deployment.
+    pub fn disable_cmd(&mut self, name: &str) -> Result<(), CmdError> {
+        if !self.runners.contains_key(name) {
+            return Err(CmdError::NotFound(name.to_string()));
+        }
+        self.exec_ctx.disabled_cmds.insert(name.to_string());
+        Ok(())
+    }
+
+    /// Enables a command disabled with [`Shell::disable_cmd`], returns `false`
+    /// if it wasn't disabled.
+    pub fn enable_cmd(&mut self, name: &str) -> bool {
+        self.exec_ctx.disabled_cmds.remove(name)
+    }
+
+    /// Returns the names of the disabled commands, sorted.
+    pub fn list_disabled_commands(&self) -> Vec<String> {
+        self.exec_ctx.list_disabled_commands()
+    }
+
+    /// Sets if the built-in commands can't be unregistered with
+    /// [`Shell::unregister_cmd`], they are protected by default.
+    pub fn protect_builtins(&mut self, protect: bool) {
+        self.protect_builtins = protect;
+    }
+
+    /// Defines all the commands of the group, if one of them can't be defined
+    /// the commands already defined are rolled back and the error is returned.
+    pub fn register_cmd_group(&mut self, group: CmdGroup) -> Result<(), CmdRegistrationError> {
+        let mut previous = Vec::new();
+
+        for (name, cmd, func) in group.commands {
+            let cmd = Cmd {
+                group: Some(group.group_name.clone()),
+                ..cmd
+            };
+            let old_cmd = self.exec_ctx.cmds.get(&name).cloned();
+            let old_runner = self.runners.get(&name).cloned();
+
+            if let Err(err) = self.define_cmd(&name, cmd, func) {
+                for (name, old_cmd, old_runner) in previous.into_iter().rev() {
+                    match (old_cmd, old_runner) {
+                        (Some(cmd), Some(runner)) => {
+                            self.exec_ctx.cmds.insert(name.clone(), cmd);
+                            self.runners.insert(name, runner);
+                        }
+                        _ => {
+                            self.exec_ctx.cmds.remove(&name);
+                            self.runners.remove(&name);
+                        }
+                    }
+                }
+                return Err(err);
+            }
+            previous.push((name, old_cmd, old_runner));
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the defined commands with their definition, sorted
+    /// by name.
+    pub fn list_commands(&self) -> Vec<(&str, &Cmd)> {
+        let mut cmds = self
+            .exec_ctx
+            .cmds
+            .iter()
+            .map(|(name, cmd)| (name.as_str(), cmd))
+            .collect::<Vec<_>>();
+        cmds.sort_by_key(|(name, _)| *name);
+        cmds
+    }
+
+    /// Parses the input and checks that the command exists and accepts the
+    /// arguments, without running it.
+    pub fn dry_run_command(&self, input: &str) -> Result<DryRunInfo, ShellError> {
+        let mut args = Shell::parse_cmd(input);
+        if args.is_empty() {
+            return Err(ShellError::EmptyInput);
+        }
+        let cmd_name = args.remove(0);
+
+        let cmd = self.get_cmd(&cmd_name);
+        Ok(DryRunInfo {
+            found: cmd.is_some(),
+            arg_count_valid: cmd.is_some_and(|cmd| cmd.accepts_arg_count(args.len())),
+            kind: self.runners.get(&cmd_name).map(Runner::kind),
+            cmd_name,
+            args,
+        })
+    }
+
+    /// Creates a child shell sharing the engine and the built-in commands of
+    /// this shell, with a copy of its environment and no plugin loaded. What
+    /// is done in the child shell doesn't affect this shell.
+    pub fn fork(&self) -> Shell {
+        let ctx = &self.exec_ctx;
+        let env = ctx.shared.env.lock().unwrap().clone();
+        let mut child = Shell {
+            runners: HashMap::new(),
+            exec_ctx: ExecutionCtx {
+                engine: ctx.engine.clone(),
+                epoch_thread: Arc::clone(&ctx.epoch_thread),
+                wasm_config: ctx.wasm_config.clone(),
+                shared: SharedCtx {
+                    env: Arc::new(Mutex::new(env)),
+                    sandboxed_env: ctx.shared.sandboxed_env,
+                    output: ctx.shared.output.fork(),
+                    locale: ctx.shared.locale.clone(),
+                    sandbox_dir: ctx.shared.sandbox_dir.clone(),
+                },
+                verbose: ctx.verbose,
+                interactive: ctx.interactive,
+                lifecycle_logger: ctx.lifecycle_logger.clone(),
+                catch_panics: ctx.catch_panics,
+                current_privilege: ctx.current_privilege,
+                plugin_idle_ttl: ctx.plugin_idle_ttl,
+                plugin_pool_size: ctx.plugin_pool_size,
+                plugin_limits: ctx.plugin_limits,
+                name_validator: ctx.name_validator.clone(),
+                disabled_cmds: ctx.disabled_cmds.clone(),
+                ..ExecutionCtx::default()
+            },
+            interceptor: self.interceptor.clone(),
+            completion_hooks: self.completion_hooks.clone(),
+            error_hooks: self.error_hooks.clone(),
+            max_input_len: self.max_input_len,
+            running_macros: Vec::new(),
+            recovery: self.recovery,
+            shadow_policy: self.shadow_policy,
+            input: self.input.clone(),
+            builtins: self.builtins.clone(),
+            protect_builtins: self.protect_builtins,
+            deadline: self.deadline,
+            load_order: self.load_order,
+        };
+
+        for (name, runner) in &self.runners {
+            if let Runner::Builtin(_) = runner {
+                child
+                    .exec_ctx
+                    .cmds
+                    .insert(name.clone(), ctx.cmds[name].clone());
+                child.runners.insert(name.clone(), runner.clone());
+            }
+        }
+        child
+    }
+
+    /// Moves the built-in commands and the plugins of `other` into this shell,
+    /// the conflicting commands are resolved with the [`ShadowPolicy`] of
+    /// this shell. A plugin loaded in both shells is unloaded from `other`,
+    /// and the merged plugins keep the environment and the engine of
+    /// `other`, its epoch thread is kept running to interrupt them.
+    pub fn merge_into(&mut self, mut other: Shell) {
+        let replace = self.shadow_policy == ShadowPolicy::Replace;
+
+        let ctx = &mut self.exec_ctx;
+        let threads = [other.exec_ctx.epoch_thread.clone()]
+            .into_iter()
+            .chain(other.exec_ctx.merged_epoch_threads.drain(..));
+        for thread in threads {
+            if !Arc::ptr_eq(&thread, &ctx.epoch_thread)
+                && !ctx
+                    .merged_epoch_threads
+                    .iter()
+                    .any(|t| Arc::ptr_eq(t, &thread))
+            {
+                ctx.merged_epoch_threads.push(thread);
+            }
+        }
+
+        for (name, runner) in &other.runners {
+            if !matches!(runner, Runner::Builtin(_))
+                || (self.runners.contains_key(name) && !replace)
+            {
+                continue;
+            }
+            let cmd = other.exec_ctx.cmds[name].clone();
+            self.exec_ctx.cmds.insert(name.clone(), cmd);
+            self.runners.insert(name.clone(), runner.clone());
+        }
+
+        let plugins = other.exec_ctx.hosts.keys().cloned().collect::<Vec<_>>();
+        for plugin in plugins {
+            if self.exec_ctx.hosts.contains_key(&plugin) {
+                outln!(
+                    self.exec_ctx,
+                    "WARNING: the plugin {plugin} is loaded in both shells, only one is kept"
+                );
+                other.unload_plugin(&plugin);
+                continue;
+            }
+
+            let (ctx, other_ctx) = (&mut self.exec_ctx, &mut other.exec_ctx);
+            ctx.hosts
+                .insert(plugin.clone(), other_ctx.hosts.remove(&plugin).unwrap());
+            if let Some(info) = other_ctx.plugins.remove(&plugin) {
+                ctx.plugins.insert(plugin.clone(), info);
+            }
+            if let Some(events) = other_ctx.events.remove(&plugin) {
+                ctx.events.insert(plugin.clone(), events);
+            }
+            if let Some(watcher) = other_ctx.watchers.remove(&plugin) {
+                ctx.watchers.insert(plugin.clone(), watcher);
+            }
+            if let Some(pool) = other_ctx.pools.remove(&plugin) {
+                ctx.pools.insert(plugin.clone(), pool);
+            }
+            if other_ctx.no_evict.remove(&plugin) {
+                ctx.no_evict.insert(plugin.clone());
+            }
+
+            for (name, runner) in &other.runners {
+                if !matches!(runner, Runner::Wasm { plugin: p } if *p == plugin)
+                    || (self.runners.contains_key(name) && !replace)
+                {
+                    continue;
+                }
+                let cmd = other_ctx.cmds[name].clone();
+                ctx.cmds.insert(name.clone(), cmd);
+                self.runners.insert(name.clone(), runner.clone());
+            }
+        }
+    }
+
+    /// Returns the counters kept by each plugin, sorted by plugin name. The
+    /// plugins failing to return them are skipped.
+    pub fn collect_all_telemetry(&self) -> Vec<(String, PluginTelemetry)> {
+        let mut telemetry = self
+            .exec_ctx
+            .hosts
+            .iter()
+            .filter_map(|(name, host)| {
+                let telemetry = host.lock().unwrap().try_call_get_telemetry().ok()?;
+                Some((name.clone(), telemetry))
+            })
+            .collect::<Vec<_>>();
+        telemetry.sort_by(|(a, _), (b, _)| a.cmp(b));
+        telemetry
+    }
+
+    /// Runs the command `cmd` of the plugin `from` and passes its output, one
+    /// argument per line, to the command `to_cmd` of the plugin `to`.
+    pub fn pipe_through_plugin(
+        &mut self,
+        from: &str,
+        cmd: &str,
+        args: &[String],
+        to: &str,
+        to_cmd: &str,
+    ) -> Result<(), PluginPipelineError> {
+        let ctx = &self.exec_ctx;
+        let from_host = ctx
+            .validate_plugin(from)
+            .map_err(PluginPipelineError::NotLoaded)?;
+        let to_host = ctx
+            .validate_plugin(to)
+            .map_err(PluginPipelineError::NotLoaded)?;
+
+        let output = from_host
+            .lock()
+            .unwrap()
+            .try_call_run_command_returning_string(cmd, args)
+            .map_err(PluginPipelineError::Wasm)?
+            .map_err(PluginPipelineError::CommandFailed)?;
+        let to_args = output.lines().map(String::from).collect::<Vec<_>>();
+        to_host
+            .lock()
+            .unwrap()
+            .try_call_run_command(to_cmd, &to_args)
+            .map_err(PluginPipelineError::Wasm)
+    }
+
+    /// Returns the number of plugins loaded.
+    pub fn plugin_count(&self) -> usize {
+        self.exec_ctx.hosts.len()
+    }
+
+    /// Returns the number of commands of the plugin `name` that were run,
+    /// `None` if there is no plugin with this name.
+    pub fn plugin_call_count(&self, name: &str) -> Option<u64> {
+        let host = self.exec_ctx.hosts.get(name)?;
+        Some(host.lock().unwrap().call_count())
+    }
+
+    /// Returns the resources used by the plugin `name`, `None` if there is no
+    /// plugin with this name.
+    pub fn plugin_resource_stats(&self, name: &str) -> Option<ResourceStats> {
+        let host = self.exec_ctx.hosts.get(name)?;
+        Some(host.lock().unwrap().resource_stats())
+    }
+
+    /// Returns how long ago the plugin `name` was loaded, `None` if there is
+    /// no plugin with this name.
+    pub fn plugin_uptime(&self, name: &str) -> Option<Duration> {
+        let host = self.exec_ctx.hosts.get(name)?;
+        Some(host.lock().unwrap().uptime())
+    }
+
+    /// Loads the plugin `name` again from its WASM file in a fresh store, to
+    /// recover it after its instance became unusable, eg after a trap. Its
+    /// commands keep running the recovered plugin. A spare instance is used
+    /// instead if the shell has a plugin pool, see
+    /// [`Shell::with_plugin_pool_size`].
+    pub fn try_recover_plugin(&mut self, name: &str) -> Result<(), PluginLoadError> {
+        let host = self
+            .exec_ctx
+            .hosts
+            .get(name)
+            .ok_or_else(|| PluginLoadError::NotLoaded(name.to_string()))?;
+        let mut host = host.lock().unwrap();
+
+        // a spare instance is used if the pool is up to date with the file,
+        // the file changed if the plugin was reloaded by its watcher.
+        if let Some(pool) = self.exec_ctx.pools.get(name) {
+            let mut pool = pool.lock().unwrap();
+            if pool.component_hash() == host.component_hash() {
+                *host = pool.acquire().map_err(PluginLoadError::Wasm)?;
+                // the trapped instance isn't released, it's unusable.
+                if let Err(err) = pool.refill() {
+                    outln!(
+                        self.exec_ctx,
+                        "ERR: failed to refill the pool of {name}: {err}"
+                    );
+                }
+                return Ok(());
+            }
+        }
+
+        let path = host.path().to_path_buf();
+        let info = host.try_reload(path).map_err(PluginLoadError::Wasm)?;
+        let events = host.try_call_list_events().map_err(PluginLoadError::Wasm)?;
+        self.exec_ctx.plugins.insert(name.to_string(), info);
+        self.exec_ctx.events.insert(name.to_string(), events);
+        Ok(())
+    }
+
+    /// Upgrades the plugin `name` in place to the WASM file at `new_path`, see
+    /// [`PluginHost::upgrade`]. The plugin keeps its name, the commands it no
+    /// longer defines are removed and the new ones are defined.
+    pub fn upgrade_plugin(
+        &mut self,
+        name: &str,
+        new_path: &Path,
+    ) -> Result<PluginInfo, PluginLoadError> {
+        let host = self.exec_ctx.validate_plugin(name)?.clone();
+        let mut host = host.lock().unwrap();
+        let info = host.upgrade(new_path)?;
+        let events = host.try_call_list_events().map_err(PluginLoadError::Wasm)?;
+        drop(host);
+
+        self.refresh_plugin(name, &info, events);
+        self.handle_new_cmds();
+        Ok(info)
+    }
+
+    /// Updates the informations of the plugin `name` after its host was
+    /// replaced by a new version: the commands it no longer defines are
+    /// removed, the others are defined by [`Shell::handle_new_cmds`] and its
+    /// pool is filled with instances of the new version.
+    fn refresh_plugin(&mut self, name: &str, info: &PluginInfo, events: Vec<EventDescriptor>) {
+        let ctx = &mut self.exec_ctx;
+        let removed = self
+            .runners
+            .iter()
+            .filter(|(cmd, runner)| {
+                matches!(runner, Runner::Wasm { plugin } if plugin == name)
+                    && !info.commands.iter().any(|command| &command.name == *cmd)
+            })
+            .map(|(cmd, _)| cmd.clone())
+            .collect::<HashSet<_>>();
+        let aliases = ctx
+            .aliases
+            .iter()
+            .filter(|(_, target)| removed.contains(*target))
+            .map(|(alias, _)| alias.clone())
+            .collect::<Vec<_>>();
+        for cmd in removed.iter().chain(&aliases) {
+            self.runners.remove(cmd);
+            ctx.cmds.remove(cmd);
+            ctx.aliases.remove(cmd);
+        }
+
+        ctx.plugins.insert(name.to_string(), info.clone());
+        ctx.events.insert(name.to_string(), events);
+        if ctx.pools.contains_key(name) {
+            let host = ctx.hosts[name].lock().unwrap();
+            match PluginHostPool::new(&host, ctx.plugin_pool_size) {
+                Ok(pool) => *ctx.pools[name].lock().unwrap() = pool,
+                Err(err) => {
+                    outln!(ctx, "ERR: failed to create the pool of {name}: {err}");
+                    drop(host);
+                    ctx.pools.remove(name);
+                }
+            }
+        }
+        ctx.new_cmds.push((name.to_string(), info.commands.clone()));
+    }
+
+    /// Returns the input lines entered after `since`, with when they were
+    /// entered, the oldest first.
+    pub fn command_history_since(&self, since: Instant) -> Vec<(Instant, String)> {
+        self.exec_ctx
+            .history
+            .iter()
+            .filter(|(at, _)| *at > since)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the name of the command being run, see
+    /// [`ExecutionCtx::current_command`].
+    pub fn current_command(&self) -> Option<&str> {
+        self.exec_ctx.current_command()
+    }
+
+    /// Returns the whole history, with when each line was entered, the oldest
+    /// first. It keeps the last [`MAX_HISTORY_LEN`] lines.
+    pub fn full_history(&self) -> Vec<(Instant, String)> {
+        self.exec_ctx.history.iter().cloned().collect()
+    }
+
+    /// Writes a shell script running the commands of the history again, in
+    /// order, with one `--exec` argument of the app per line:
+    ///
+    /// ```sh
+    /// #!/bin/sh
+    /// plugin-app \
+    ///   --exec 'load ./plugin.wasm' \
+    ///   --exec 'hello french'
+    /// ```
+    pub fn emit_shell_script(&self, output: &mut dyn Write) -> io::Result<()> {
+        write!(output, "#!/bin/sh\n{}", env!("CARGO_PKG_NAME"))?;
+        for (_, line) in &self.exec_ctx.history {
+            // a single quote can't be escaped in single quotes, the quoted
+            // string is closed before it and opened again after it.
+            let quoted = line.replace('\'', r"'\''");
+            write!(output, " \\\n  --exec '{quoted}'")?;
+        }
+        writeln!(output)
+    }
+
+    /// Returns a Graphviz DOT graph of the commands, see
+    /// [`ExecutionCtx::export_command_graph`].
+    pub fn export_command_graph(&self) -> String {
+        self.exec_ctx.export_command_graph()
+    }
+
+    /// Returns an OpenAPI document of the commands, see
+    /// [`ExecutionCtx::export_openapi_spec`].
+    pub fn export_openapi_spec(&self) -> String {
+        self.exec_ctx.export_openapi_spec()
+    }
+
+    /// Checks that the WASM file the plugin `name` was loaded from has the
+    /// SHA-256 `expected`.
+    pub fn verify_plugin_hash(
+        &self,
+        name: &str,
+        expected: &[u8; 32],
+    ) -> Result<(), PluginLoadError> {
+        let host = self.exec_ctx.validate_plugin(name)?;
+        if host.lock().unwrap().component_hash() != *expected {
+            return Err(PluginLoadError::HashMismatch(name.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> &ShellMetrics {
+        &self.exec_ctx.metrics
+    }
+
+    pub fn get_cmd(&self, name: &str) -> Option<&Cmd> {
+        self.exec_ctx.cmds.get(name)
+    }
+
+    /// Waits until the command `name` is defined, the commands of the plugins
+    /// loaded in the meantime are defined every 10ms. Returns `Err` if the
+    /// command still isn't defined after `timeout`.
+    pub fn wait_for_command(&mut self, name: &str, timeout: Duration) -> Result<(), ()> {
+        let start = Instant::now();
+        loop {
+            self.handle_new_cmds();
+            if self.get_cmd(name).is_some() {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(());
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Returns the aliases with the command they stand for, sorted by alias.
+    pub fn list_aliases(&self) -> Vec<(String, String)> {
+        self.exec_ctx.list_aliases()
+    }
+
+    /// Unloads the plugin `name` and removes its commands, and the aliases of
+    /// its commands. Returns `false` if there is no plugin with this name.
+    pub fn unload_plugin(&mut self, name: &str) -> bool {
+        let ctx = &mut self.exec_ctx;
+        let Some(host) = ctx.hosts.remove(name) else {
+            return false;
+        };
+        // the plugin is unloaded even if it fails to clean up.
+        _ = host.lock().unwrap().call_on_unload();
+        ctx.log_lifecycle("unload", json!({ "plugin": name }));
+        ctx.plugins.remove(name);
+        ctx.events.remove(name);
+        ctx.watchers.remove(name);
+        ctx.pools.remove(name);
+        ctx.no_evict.remove(name);
+
+        let cmds = self
+            .runners
+            .iter()
+            .filter(|(_, runner)| matches!(runner, Runner::Wasm { plugin } if plugin == name))
+            .map(|(cmd, _)| cmd.clone())
+            .collect::<HashSet<_>>();
+        let aliases = ctx
+            .aliases
+            .iter()
+            .filter(|(_, target)| cmds.contains(*target))
+            .map(|(alias, _)| alias.clone())
+            .collect::<Vec<_>>();
+        for cmd in cmds.iter().chain(&aliases) {
+            self.runners.remove(cmd);
+            ctx.cmds.remove(cmd);
+            ctx.aliases.remove(cmd);
+        }
+        true
+    }
+
+    /// Unloads the plugins idle for longer than the TTL set with
+    /// [`Shell::with_plugin_idle_ttl`], returns their names.
+    pub fn evict_idle_plugins(&mut self) -> Vec<String> {
+        let Some(ttl) = self.exec_ctx.plugin_idle_ttl else {
+            return Vec::new();
+        };
+
+        let mut idle = self
+            .exec_ctx
+            .hosts
+            .iter()
+            .filter(|(name, _)| !self.exec_ctx.no_evict.contains(*name))
+            .filter(|(_, host)| host.lock().unwrap().idle_time() > ttl)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        idle.sort();
+        for name in &idle {
+            self.unload_plugin(name);
+            outln!(self.exec_ctx, "Plugin {name} unloaded, it was idle");
+        }
+        idle
+    }
+
+    /// Loads a plugin precompiled to a `.cwasm` file and defines its commands.
+    pub fn load_precompiled_plugin(&mut self, path: impl Into<PathBuf>) -> Result<()> {
+        self.exec_ctx.load_precompiled_plugin(path.into())?;
+        self.handle_new_cmds();
+        Ok(())
+    }
+
+    /// Defines all the commands of the registry.
+    pub fn import_native_cmds(
+        &mut self,
+        registry: &NativeCmdRegistry,
+    ) -> Result<(), CmdRegistrationError> {
+        for (name, (cmd, func)) in &registry.0 {
+            self.define_cmd(name, cmd.clone(), *func)?;
+        }
+        Ok(())
+    }
+
+    /// Defines `alias` as another name of the command `target`. If `target` is
+    /// an alias, the new alias stands for the same command as `target`.
+    pub fn define_alias(&mut self, alias: &str, target: &str) -> Result<(), CmdRegistrationError> {
+        let target = match self.runners.get(target) {
+            Some(Runner::Aliased { target }) => target.clone(),
+            Some(_) => target.to_string(),
+            None => return Err(CmdRegistrationError::UnknownAliasTarget(target.to_string())),
+        };
+        if let Some(runner) = self.runners.get(alias) {
+            if !matches!(runner, Runner::Aliased { .. }) {
+                return Err(CmdRegistrationError::AlreadyDefined(alias.to_string()));
+            }
+        }
+
+        let target_cmd = &self.exec_ctx.cmds[&target];
+        let usage = match target_cmd.usage.split_once(' ') {
+            Some((_, args)) => format!("{alias} {args}"),
+            None => alias.to_string(),
+        };
+        let cmd = Cmd {
+            usage,
+            description: format!("Alias of {target:?}."),
+            ..target_cmd.clone()
+        };
+
+        self.define_cmd(
+            alias,
+            cmd,
+            Runner::Aliased {
+                target: target.clone(),
+            },
+        )?;
+        self.exec_ctx.aliases.insert(alias.to_string(), target);
+        Ok(())
+    }
+
+    /// Defines the macro `name` running `commands` in order, it can replace a
+    /// macro but not another command.
+    pub fn define_macro(
+        &mut self,
+        name: &str,
+        commands: Vec<(String, Vec<String>)>,
+    ) -> Result<(), CmdRegistrationError> {
+        if let Some(runner) = self.runners.get(name) {
+            if !matches!(runner, Runner::Macro { .. }) {
+                return Err(CmdRegistrationError::AlreadyDefined(name.to_string()));
+            }
+        }
+
+        let cmd = Cmd::new(name, format!("Macro of {} commands.", commands.len()));
+        self.define_cmd(
+            name,
+            cmd,
+            Runner::Macro {
+                commands: commands.clone(),
+            },
+        )?;
+        self.exec_ctx.macros.insert(name.to_string(), commands);
+        Ok(())
+    }
+
+    pub fn handle_new_cmds(&mut self) {
+        for name in std::mem::take(&mut self.exec_ctx.plugins_to_unload) {
+            self.unload_plugin(&name);
+        }
+
+        let reloaded = std::mem::take(&mut *self.exec_ctx.reloaded.lock().unwrap());
+        for (name, info, events) in reloaded {
+            // the plugin may have been unloaded since it was reloaded.
+            if self.exec_ctx.hosts.contains_key(&name) {
+                self.refresh_plugin(&name, &info, events);
+            }
+        }
+
+        for (name, commands) in std::mem::take(&mut self.exec_ctx.new_macros) {
+            if let Err(err) = self.define_macro(&name, commands) {
+                outln!(
+                    self.exec_ctx,
+                    "ERR: failed to define the macro {name:?}, {err}"
+                );
+            }
+        }
+
+        for (alias, target) in std::mem::take(&mut self.exec_ctx.new_aliases) {
+            if let Err(err) = self.define_alias(&alias, &target) {
+                outln!(
+                    self.exec_ctx,
+                    "ERR: failed to define the alias {alias:?}, {err}"
+                );
+            }
+        }
+
+        for (plugin_name, commands) in std::mem::take(&mut self.exec_ctx.new_cmds) {
+            let host = self.exec_ctx.hosts.get(&plugin_name).cloned();
+            for command in commands {
+                // a plugin failing to describe its command still defines it.
+                let (long_description, schema) = match &host {
+                    Some(host) => {
+                        let mut host = host.lock().unwrap();
+                        (
+                            host.try_call_describe_command(&command.name).ok().flatten(),
+                            host.try_call_command_schema(&command.name).ok().flatten(),
+                        )
+                    }
+                    None => (None, None),
+                };
+                let mut cmd = Cmd::new(command.usage, command.description)
+                    .with_spec(command.spec)
+                    .with_long_description(long_description);
+                if let Some(schema) = schema {
+                    let res = serde_json::from_str(&schema)
+                        .map_err(|err| err.to_string())
+                        .and_then(|schema| {
+                            cmd.clone()
+                                .try_with_input_schema(schema)
+                                .map_err(|err| err.to_string())
+                        });
+                    match res {
+                        Ok(with_schema) => cmd = with_schema,
+                        Err(err) => outln!(
+                            self.exec_ctx,
+                            "WARNING: the schema of the command {} is invalid, {err}",
+                            command.name
+                        ),
+                    }
+                }
+                let res = self.define_cmd(
+                    command.name,
+                    cmd,
+                    Runner::Wasm {
+                        plugin: plugin_name.clone(),
+                    },
+                );
+                if let Err(err) = res {
+                    outln!(
+                        self.exec_ctx,
+                        "ERR: the plugin {plugin_name} defines an invalid command, {err}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Writes the state exported by each loaded plugin to
+    /// `<dir>/<plugin-name>.state`, the plugins that don't export
+    /// `export-state` have no state.
+    pub fn save_plugin_states(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        for (name, host) in &self.exec_ctx.hosts {
+            if let Some(state) = host.lock().unwrap().export_state()? {
+                fs::write(dir.join(format!("{name}.state")), state)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores the states saved with [`Shell::save_plugin_states`] in the
+    /// loaded plugins, the plugins without a saved state are left untouched.
+    pub fn load_plugin_states(&mut self, dir: &Path) -> Result<()> {
+        for (name, host) in &self.exec_ctx.hosts {
+            let path = dir.join(format!("{name}.state"));
+            if !path.exists() {
+                continue;
+            }
+            let state = fs::read(path)?;
+            host.lock().unwrap().import_state(&state)?;
+        }
+        Ok(())
+    }
+
+    pub fn parse_cmd(cmd: &str) -> Vec<String> {
+        cmd.split_whitespace().map(String::from).collect()
+    }
+
+    /// Expands the history references in `input`, like in bash: `!!` is the
+    /// previous line, `!N` the N-th line of the history, starting at 1, and
+    /// `!-N` the N-th line before the current one. A `!` followed by anything
+    /// else is kept as is.
+    pub fn parse_cmd_with_history_expansion(
+        input: &str,
+        history: &VecDeque<(Instant, String)>,
+    ) -> Result<String, ShellError> {
+        let mut expanded = String::with_capacity(input.len());
+        let mut chars = input.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c != '!' {
+                expanded.push(c);
+                continue;
+            }
+
+            let mut event = String::from("!");
+            let entry = match chars.peek() {
+                Some('!') => {
+                    event.push(chars.next().unwrap());
+                    history.back()
+                }
+                Some('-' | '0'..='9') => {
+                    if chars.peek() == Some(&'-') {
+                        event.push(chars.next().unwrap());
+                    }
+                    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                        event.push(digit);
+                    }
+                    let relative = event.starts_with("!-");
+                    match event.trim_start_matches(['!', '-']).parse::<usize>() {
+                        Ok(n) if relative => history
+                            .len()
+                            .checked_sub(n)
+                            .filter(|_| n != 0)
+                            .and_then(|i| history.get(i)),
+                        Ok(n) => n.checked_sub(1).and_then(|i| history.get(i)),
+                        // a lone `!-` isn't a history reference.
+                        Err(_) => {
+                            expanded.push_str(&event);
+                            continue;
+                        }
+                    }
+                }
+                _ => {
+                    expanded.push(c);
+                    continue;
+                }
+            };
+
+            match entry {
+                Some((_, entry)) => expanded.push_str(entry),
+                None => return Err(ShellError::HistoryOutOfRange(event)),
+            }
+        }
+
+        Ok(expanded)
+    }
+}
 
-    pub fn parse_cmd(cmd: &str) -> Vec<&str> {
-        cmd.split_whitespace().collect()
+impl Default for Shell {
+    fn default() -> Shell {
+        Shell::new()
     }
 }
@@ -1,13 +1,79 @@
+use std::fs;
+use std::path::PathBuf;
+
 use anyhow::Result;
-use plugin_app::Shell;
+use clap::Parser;
+use plugin_app::{PrivilegeLevel, Shell, ShellConfig};
+use tracing::Level;
 
 pub const WELCOME_MSG: &str = r#"Welcome to this app, in this app you can load and unload plugins at runtime.
 Type "help" to get some help."#;
 
+/// Command line arguments of the app.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// Print the full errors of the plugins, with their wasm backtrace.
+    #[arg(long)]
+    verbose: bool,
+    /// Run the commands read from the standard input without prompting, eg
+    /// when they are piped.
+    #[arg(long)]
+    batch: bool,
+    /// Build the shell from a TOML configuration file.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Run this command instead of reading the standard input, it can be
+    /// repeated to run several commands in order.
+    #[arg(long, value_name = "COMMAND")]
+    exec: Vec<String>,
+    /// Run the shell without the admin privilege, the commands like `load`
+    /// and `unload` are denied.
+    #[arg(long)]
+    restricted: bool,
+}
+
 fn main() -> Result<()> {
-    println!("{WELCOME_MSG}");
-    let mut shell = Shell::new();
-    shell.run()?;
+    let cli = Cli::parse();
+    let config = match &cli.config {
+        Some(path) => ShellConfig::from_toml(&fs::read_to_string(path)?)?,
+        None => ShellConfig::default(),
+    };
+    match &config.log_level {
+        Some(level) => tracing_subscriber::fmt()
+            .with_max_level(level.parse::<Level>()?)
+            .init(),
+        None => tracing_subscriber::fmt::init(),
+    }
+
+    let interactive = !cli.batch && cli.exec.is_empty();
+    if interactive {
+        println!("{WELCOME_MSG}");
+    }
+    let mut shell = Shell::from_config(&config)?
+        .with_verbose(cli.verbose || config.verbose)
+        .with_interactive(interactive);
+    // the user running the app owns it, unless told otherwise.
+    if !cli.restricted {
+        shell.set_privilege(PrivilegeLevel::Admin);
+    }
+
+    if cli.exec.is_empty() {
+        shell.run()?;
+        return Ok(());
+    }
+    for line in &cli.exec {
+        let mut args = Shell::parse_cmd(line);
+        if args.is_empty() {
+            continue;
+        }
+        let name = args.remove(0);
+        if let Err(err) = shell.run_cmd(&name, args) {
+            shell.shutdown();
+            return Err(err.into());
+        }
+    }
+    shell.shutdown();
 
     // println!();
     // let engine = wasmtime::Engine::default();
@@ -0,0 +1,38 @@
+/// A line of a [`Script`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptLine {
+    /// A command with its arguments.
+    Command(String, Vec<String>),
+    /// A comment, it isn't run.
+    Comment(String),
+}
+
+/// A sequence of commands, built programmatically and run with
+/// [`Shell::run_script_obj`](crate::Shell::run_script_obj).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    lines: Vec<ScriptLine>,
+}
+
+impl Script {
+    pub fn new() -> Script {
+        Script::default()
+    }
+
+    pub fn add_command(&mut self, name: &str, args: &[&str]) -> &mut Script {
+        self.lines.push(ScriptLine::Command(
+            name.to_string(),
+            args.iter().map(|arg| arg.to_string()).collect(),
+        ));
+        self
+    }
+
+    pub fn add_comment(&mut self, text: &str) -> &mut Script {
+        self.lines.push(ScriptLine::Comment(text.to_string()));
+        self
+    }
+
+    pub fn lines(&self) -> &[ScriptLine] {
+        &self.lines
+    }
+}
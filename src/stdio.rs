@@ -0,0 +1,297 @@
+use std::fmt::{Arguments, Debug};
+use std::io::{self, stdin, stdout, BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+/// Like `println!` but writes to the output of the shell of `$ctx`, an
+/// [`ExecutionCtx`](crate::ExecutionCtx), see [`Shell::with_stdout`](crate::Shell::with_stdout).
+macro_rules! outln {
+    ($ctx:expr) => {
+        $ctx.shared.output.write_line(format_args!(""))
+    };
+    ($ctx:expr, $($arg:tt)*) => {
+        $ctx.shared.output.write_line(format_args!($($arg)*))
+    };
+}
+
+/// Like `print!` but writes to the output of the shell of `$ctx`, the output
+/// is flushed.
+macro_rules! out {
+    ($ctx:expr, $($arg:tt)*) => {
+        $ctx.shared.output.write(format_args!($($arg)*))
+    };
+}
+
+pub(crate) use out;
+pub(crate) use outln;
+
+/// Transforms each line written to the output, see
+/// [`Shell::register_format_hook`](crate::Shell::register_format_hook).
+pub type FormatHook = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Where the shell and its plugins write, the standard output by default.
+#[derive(Clone)]
+pub struct Output {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    /// Applied to the text written, in order
+    hooks: Arc<Mutex<Vec<FormatHook>>>,
+    /// Records the text written, see [`Shell::record_session`](crate::Shell::record_session)
+    recorder: Arc<Mutex<Option<SessionRecorder>>>,
+}
+
+impl Output {
+    pub fn new(writer: impl Write + Send + 'static) -> Output {
+        Output {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+            hooks: Arc::default(),
+            recorder: Arc::default(),
+        }
+    }
+
+    /// Returns a new output writing to `writer`, with the format hooks and
+    /// the recorder of this output.
+    pub fn with_writer(&self, writer: impl Write + Send + 'static) -> Output {
+        Output {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+            hooks: Arc::new(Mutex::new(self.hooks.lock().unwrap().clone())),
+            recorder: Arc::new(Mutex::new(self.recorder())),
+        }
+    }
+
+    /// Returns an output writing to the same writer, with a copy of the
+    /// format hooks and of the recorder, the hooks added to one of them don't
+    /// apply to the other.
+    pub fn fork(&self) -> Output {
+        Output {
+            writer: self.writer.clone(),
+            hooks: Arc::new(Mutex::new(self.hooks.lock().unwrap().clone())),
+            recorder: Arc::new(Mutex::new(self.recorder())),
+        }
+    }
+
+    /// Records the text written to this output and to its clones, it replaces
+    /// the previous recorder.
+    pub fn set_recorder(&self, recorder: SessionRecorder) {
+        *self.recorder.lock().unwrap() = Some(recorder);
+    }
+
+    /// Returns the recorder of the output.
+    pub fn recorder(&self) -> Option<SessionRecorder> {
+        self.recorder.lock().unwrap().clone()
+    }
+
+    /// Removes the recorder of the output and returns it.
+    pub fn take_recorder(&self) -> Option<SessionRecorder> {
+        self.recorder.lock().unwrap().take()
+    }
+
+    /// Applies `hook` to each line written, after the hooks already added.
+    pub fn add_hook(&self, hook: FormatHook) {
+        self.hooks.lock().unwrap().push(hook);
+    }
+
+    fn format(&self, text: &str) -> String {
+        self.hooks
+            .lock()
+            .unwrap()
+            .iter()
+            .fold(text.to_string(), |text, hook| hook(&text))
+    }
+
+    /// Writes the text followed by a newline, each line of the text is
+    /// formatted by the hooks. The errors are ignored.
+    pub fn write_line(&self, args: Arguments<'_>) {
+        let text = args.to_string();
+        let mut writer = self.writer.lock().unwrap();
+        let mut written = String::new();
+        for line in text.split('\n') {
+            let line = self.format(line);
+            _ = writer.write_all(line.as_bytes());
+            _ = writer.write_all(b"\n");
+            written.push_str(&line);
+            written.push('\n');
+        }
+        if let Some(recorder) = self.recorder() {
+            recorder.record("o", &written);
+        }
+    }
+
+    /// Writes the text, formatted by the hooks, and flushes the output. The
+    /// errors are ignored.
+    pub fn write(&self, args: Arguments<'_>) {
+        let text = self.format(&args.to_string());
+        let mut writer = self.writer.lock().unwrap();
+        _ = writer.write_all(text.as_bytes());
+        _ = writer.flush();
+        if let Some(recorder) = self.recorder() {
+            recorder.record("o", &text);
+        }
+    }
+}
+
+impl Default for Output {
+    fn default() -> Output {
+        Output::new(stdout())
+    }
+}
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Output").finish_non_exhaustive()
+    }
+}
+
+/// Where the shell reads the input lines, the standard input by default.
+#[derive(Clone)]
+pub struct Input(Arc<Mutex<Box<dyn BufRead + Send>>>);
+
+impl Input {
+    pub fn new(reader: impl BufRead + Send + 'static) -> Input {
+        Input(Arc::new(Mutex::new(Box::new(reader))))
+    }
+
+    /// Reads a line, with its newline, and appends it to `buf`. Returns the
+    /// number of bytes read, 0 at the end of the input.
+    pub fn read_line(&self, buf: &mut String) -> io::Result<usize> {
+        self.0.lock().unwrap().read_line(buf)
+    }
+}
+
+impl Default for Input {
+    fn default() -> Input {
+        Input::new(BufReader::new(stdin()))
+    }
+}
+
+impl Debug for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Input").finish_non_exhaustive()
+    }
+}
+
+/// The output of a shell written to a buffer, see
+/// [`Shell::with_output_capture`](crate::Shell::with_output_capture).
+#[derive(Debug, Clone, Default)]
+pub struct OutputCapture(Arc<Mutex<Vec<u8>>>);
+
+impl OutputCapture {
+    /// Returns the lines written since the last call, without their newline.
+    pub fn take_lines(&self) -> Vec<String> {
+        let buf = std::mem::take(&mut *self.0.lock().unwrap());
+        String::from_utf8_lossy(&buf)
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+}
+
+impl Write for OutputCapture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the lifecycle events of a shell as newline-delimited JSON, see
+/// [`Shell::register_lifecycle_logger`](crate::Shell::register_lifecycle_logger).
+#[derive(Clone)]
+pub struct LifecycleLogger(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl LifecycleLogger {
+    pub fn new(writer: impl Write + Send + 'static) -> LifecycleLogger {
+        LifecycleLogger(Arc::new(Mutex::new(Box::new(writer))))
+    }
+
+    /// Writes the event with the fields of `fields`, a JSON object, the `ts`
+    /// field is the number of milliseconds since the Unix epoch. The errors
+    /// are ignored.
+    pub fn log(&self, event: &str, fields: Value) {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut record = json!({ "ts": ts, "event": event });
+        if let (Some(record), Value::Object(fields)) = (record.as_object_mut(), fields) {
+            record.extend(fields);
+        }
+
+        let mut writer = self.0.lock().unwrap();
+        _ = writeln!(writer, "{record}");
+        _ = writer.flush();
+    }
+}
+
+impl Debug for LifecycleLogger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LifecycleLogger").finish_non_exhaustive()
+    }
+}
+
+/// Records the input and the output of a shell in the asciinema v2 format,
+/// see [`Shell::record_session`](crate::Shell::record_session).
+#[derive(Clone)]
+pub struct SessionRecorder(Arc<Mutex<RecorderState>>);
+
+struct RecorderState {
+    writer: Box<dyn Write + Send>,
+    /// The times of the events are relative to this instant
+    start: Instant,
+    /// The final `EOF` marker was written, no event is written after it
+    finished: bool,
+}
+
+impl SessionRecorder {
+    /// Writes the header of the recording to `writer`.
+    pub fn new(writer: impl Write + Send + 'static) -> SessionRecorder {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let header = json!({ "version": 2, "width": 80, "height": 24, "timestamp": timestamp });
+
+        let mut writer: Box<dyn Write + Send> = Box::new(writer);
+        _ = writeln!(writer, "{header}");
+        _ = writer.flush();
+        SessionRecorder(Arc::new(Mutex::new(RecorderState {
+            writer,
+            start: Instant::now(),
+            finished: false,
+        })))
+    }
+
+    /// Writes an event, `kind` is `"i"` for the input and `"o"` for the
+    /// output. The newlines of the output are written as `\r\n` like a
+    /// terminal would. The errors are ignored.
+    pub fn record(&self, kind: &str, text: &str) {
+        let mut state = self.0.lock().unwrap();
+        if state.finished {
+            return;
+        }
+        let time = state.start.elapsed().as_secs_f64();
+        let text = match kind {
+            "o" => text.replace('\n', "\r\n"),
+            _ => text.to_string(),
+        };
+        _ = writeln!(state.writer, "{}", json!([time, kind, text]));
+        _ = state.writer.flush();
+    }
+
+    /// Writes the final `EOF` marker, the events recorded after it are
+    /// ignored.
+    pub fn finish(&self) {
+        self.record("m", "EOF");
+        self.0.lock().unwrap().finished = true;
+    }
+}
+
+impl Debug for SessionRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionRecorder").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,326 @@
+//! Plugin used by the integration tests of the shell, each command exercises
+//! a feature of the host. Some behaviours are enabled with cargo features, the
+//! tests build one variant of the plugin per set of features.
+
+#[cfg(not(feature = "optional-exports"))]
+wit_bindgen::generate!({
+    path: "../wit/plugin.wit",
+    world: "core",
+});
+#[cfg(all(feature = "optional-exports", not(feature = "mock-import")))]
+wit_bindgen::generate!({
+    path: "../wit/plugin.wit",
+    world: "plugin",
+});
+#[cfg(feature = "mock-import")]
+wit_bindgen::generate!({
+    path: ["../wit/plugin.wit", "wit/mock.wit"],
+    world: "test:plugin/mock-host",
+});
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[cfg(feature = "optional-exports")]
+use plugin_app::core::types::{EventDescriptor, PluginTelemetry};
+use plugin_app::core::{
+    host_app::{env_get, env_set, log, subscribe_event, Level},
+    types::{Command, CommandSpec, ParsedArgs},
+};
+
+pub struct TestPlugin;
+
+/// Number of times the command `tick` was run.
+static TICKS: AtomicU32 = AtomicU32::new(0);
+
+impl Guest for TestPlugin {
+    fn init() -> PluginInfo {
+        if env_get("TEST_PLUGIN_SLOW_INIT").is_some() {
+            loop {
+                std::hint::black_box(());
+            }
+        }
+        // only the first init fails, the failure is recorded in the
+        // environment shared by the instances.
+        if env_get("TEST_PLUGIN_FLAKY_INIT").is_some()
+            && env_get("test-plugin-init-failed").is_none()
+        {
+            env_set("test-plugin-init-failed", "1");
+            panic!("the first init of the plugin always fails");
+        }
+
+        let mut commands = vec![
+            Command {
+                name: "echo".to_string(),
+                usage: "echo [args..]".to_string(),
+                description: "Logs its arguments.".to_string(),
+                spec: None,
+            },
+            Command {
+                name: "greet".to_string(),
+                usage: "greet <name> [--loud] [--greeting <greeting>]".to_string(),
+                description: "Greets someone, its arguments are parsed by the host.".to_string(),
+                spec: Some(CommandSpec {
+                    positional: vec!["name".to_string()],
+                    flags: vec!["loud".to_string()],
+                    value_flags: vec!["greeting".to_string()],
+                }),
+            },
+            Command {
+                name: "spin".to_string(),
+                usage: "spin".to_string(),
+                description: "Never returns, until it's interrupted.".to_string(),
+                spec: None,
+            },
+            Command {
+                name: "count".to_string(),
+                usage: "count <n>".to_string(),
+                description: "Outputs the numbers below n, one per line.".to_string(),
+                spec: None,
+            },
+            Command {
+                name: "tick".to_string(),
+                usage: "tick".to_string(),
+                description: "Outputs the number of times it was run.".to_string(),
+                spec: None,
+            },
+            Command {
+                name: "alloc".to_string(),
+                usage: "alloc <kib>".to_string(),
+                description: "Allocates kib KiB, the memory of the plugin grows.".to_string(),
+                spec: None,
+            },
+            Command {
+                name: "flood".to_string(),
+                usage: "flood <n>".to_string(),
+                description: "Logs n messages.".to_string(),
+                spec: None,
+            },
+            Command {
+                name: "env-set".to_string(),
+                usage: "env-set <key> <value>".to_string(),
+                description: "Sets a variable of the shell environment.".to_string(),
+                spec: None,
+            },
+            Command {
+                name: "env-get".to_string(),
+                usage: "env-get <key>".to_string(),
+                description: "Outputs a variable of the shell environment.".to_string(),
+                spec: None,
+            },
+            Command {
+                name: "subscribe".to_string(),
+                usage: "subscribe <event>".to_string(),
+                description: "Subscribes to an event of the plugins.".to_string(),
+                spec: None,
+            },
+            Command {
+                name: "trap".to_string(),
+                usage: "trap".to_string(),
+                description: "Panics, the instance traps.".to_string(),
+                spec: None,
+            },
+        ];
+        #[cfg(feature = "mock-import")]
+        commands.push(Command {
+            name: "config".to_string(),
+            usage: "config <key>".to_string(),
+            description: "Returns the value of a configuration, from the mock import.".to_string(),
+            spec: None,
+        });
+        if cfg!(feature = "extra-command") {
+            commands.push(Command {
+                name: "extra".to_string(),
+                usage: "extra".to_string(),
+                description: "Only defined by the `extra-command` variant.".to_string(),
+                spec: None,
+            });
+        }
+
+        let mut name = env!("CARGO_PKG_NAME").to_string();
+        // lets a shell load several copies of the plugin, each copy counts
+        // itself in the environment shared by the plugins.
+        if env_get("TEST_PLUGIN_UNIQUE_NAMES").is_some() {
+            let count = env_get("test-plugin-count")
+                .and_then(|count| count.parse::<u32>().ok())
+                .unwrap_or(0);
+            env_set("test-plugin-count", &(count + 1).to_string());
+            name = format!("{name}-{count}");
+        }
+
+        PluginInfo {
+            name,
+            description: env!("CARGO_PKG_DESCRIPTION").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            categories: vec!["test".to_string()],
+            authors: vec![env!("CARGO_PKG_AUTHORS").to_string()],
+            required_features: env_get("TEST_PLUGIN_REQUIRED_FEATURES")
+                .map(|features| features.split(',').map(String::from).collect())
+                .unwrap_or_default(),
+            commands,
+        }
+    }
+
+    fn run_command(name: String, args: Vec<String>) {
+        match execute(&name, &args) {
+            Ok(output) => log(Level::Info, &output),
+            Err(err) => log(Level::Error, &err),
+        }
+    }
+
+    fn run_command_parsed(name: String, args: ParsedArgs) {
+        match name.as_str() {
+            "greet" => {
+                let greeting = args
+                    .values
+                    .iter()
+                    .find(|(flag, _)| flag == "greeting")
+                    .map_or("hello", |(_, value)| value.as_str());
+                let mut msg = format!("{greeting} {}", args.positional[0]);
+                if args.flags.iter().any(|flag| flag == "loud") {
+                    msg = msg.to_uppercase();
+                }
+                log(Level::Info, &msg);
+            }
+            _ => log(Level::Error, "command not defined in this plugin"),
+        }
+    }
+
+    fn run_command_with_output(name: String, args: Vec<String>) -> Result<String, String> {
+        execute(&name, &args)
+    }
+
+    #[cfg(feature = "optional-exports")]
+    fn run_task(name: String, _args: Vec<String>) -> Result<String, String> {
+        Err(format!("task {name:?} not defined in this plugin"))
+    }
+
+    #[cfg(feature = "optional-exports")]
+    fn export_state() -> Vec<u8> {
+        Vec::new()
+    }
+
+    #[cfg(feature = "optional-exports")]
+    fn import_state(data: Vec<u8>) {
+        // the plugin has no state, any other state is corrupted.
+        assert!(
+            data.is_empty(),
+            "the state of the test plugin must be empty"
+        );
+    }
+
+    #[cfg(feature = "optional-exports")]
+    fn describe_command(_name: String) -> Option<String> {
+        None
+    }
+
+    #[cfg(feature = "optional-exports")]
+    fn command_schema(_name: String) -> Option<String> {
+        None
+    }
+
+    #[cfg(feature = "optional-exports")]
+    fn list_events() -> Vec<EventDescriptor> {
+        Vec::new()
+    }
+
+    #[cfg(feature = "optional-exports")]
+    fn get_telemetry() -> PluginTelemetry {
+        PluginTelemetry {
+            command_calls: Vec::new(),
+            errors: 0,
+            last_error: None,
+        }
+    }
+
+    #[cfg(feature = "optional-exports")]
+    fn on_unload() {}
+
+    #[cfg(feature = "optional-exports")]
+    fn on_error(msg: String) {
+        log(Level::Error, &format!("on-error: {msg}"));
+    }
+
+    #[cfg(feature = "optional-exports")]
+    fn health_check() -> bool {
+        true
+    }
+
+    #[cfg(feature = "optional-exports")]
+    fn stream_command(name: String, args: Vec<String>) -> Result<(), String> {
+        use plugin_app::core::host_app::stream_write;
+
+        // each line is a chunk, the command stops once the host stops reading.
+        for line in execute(&name, &args)?.split_inclusive('\n') {
+            if !stream_write(line.as_bytes()) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Executes a command, returns its output or an error message.
+fn execute(name: &str, args: &[String]) -> Result<String, String> {
+    match name {
+        "echo" => Ok(args.join(" ")),
+        "count" => {
+            let n = args
+                .first()
+                .and_then(|n| n.parse::<u32>().ok())
+                .ok_or("count command expects a number as the first argument")?;
+            Ok((0..n).map(|i| format!("{i}\n")).collect())
+        }
+        "tick" => Ok(TICKS.fetch_add(1, Ordering::Relaxed).to_string()),
+        "alloc" => {
+            let kib = args
+                .first()
+                .and_then(|kib| kib.parse::<usize>().ok())
+                .ok_or("alloc command expects a size in KiB as the first argument")?;
+            // the memory of the instance never shrinks, even once freed.
+            std::hint::black_box(vec![1u8; kib * 1024]);
+            Ok(format!("allocated {kib} KiB"))
+        }
+        "flood" => {
+            let n = args
+                .first()
+                .and_then(|n| n.parse::<u32>().ok())
+                .filter(|n| *n > 0)
+                .ok_or("flood command expects a positive number as the first argument")?;
+            // the last message is the output, it's logged too.
+            for i in 1..n {
+                log(Level::Info, &format!("message {i}"));
+            }
+            Ok(format!("message {n}"))
+        }
+        "env-set" => match args {
+            [key, value] => {
+                env_set(key, value);
+                Ok(format!("{key} = {value}"))
+            }
+            _ => Err("env-set command expects a key and a value".to_string()),
+        },
+        "env-get" => {
+            let key = args
+                .first()
+                .ok_or("env-get command expects a key as the first argument")?;
+            env_get(key).ok_or_else(|| format!("the variable {key} isn't set"))
+        }
+        "subscribe" => {
+            let event = args
+                .first()
+                .ok_or("subscribe command expects an event as the first argument")?;
+            subscribe_event(event);
+            Ok(format!("subscribed to {event}"))
+        }
+        "spin" => loop {
+            std::hint::black_box(());
+        },
+        "trap" => panic!("the command `trap` always traps"),
+        "extra" if cfg!(feature = "extra-command") => Ok("extra".to_string()),
+        #[cfg(feature = "mock-import")]
+        "config" => Ok(test::plugin::mock::get_config(&args[0])),
+        _ => Err("command not defined in this plugin".to_string()),
+    }
+}
+
+export!(TestPlugin);
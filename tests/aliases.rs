@@ -0,0 +1,37 @@
+mod common;
+
+#[test]
+fn alias_runs_its_target() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    shell
+        .run_cmd("alias", vec!["say".to_string(), "echo".to_string()])
+        .unwrap();
+    // an alias of an alias stands for the same command.
+    shell.define_alias("speak", "say").unwrap();
+    output.take_lines();
+
+    for alias in ["say", "speak"] {
+        shell.run_cmd(alias, vec!["hi".to_string()]).unwrap();
+        assert_eq!(output.take_lines(), ["INFO: hi"]);
+    }
+    // the plugin runs `echo`, not the alias.
+    assert_eq!(shell.plugin_call_count("test-plugin"), Some(2));
+}
+
+#[test]
+fn aliases_are_listed_by_name() {
+    let (mut shell, output) = common::shell();
+    shell.run_cmd("aliases", Vec::new()).unwrap();
+    assert_eq!(output.take_lines(), ["There is currently no aliases!"]);
+
+    shell.define_alias("ls", "list-plugins").unwrap();
+    shell.define_alias("h", "help").unwrap();
+    assert_eq!(
+        shell.list_aliases(),
+        [
+            ("h".to_string(), "help".to_string()),
+            ("ls".to_string(), "list-plugins".to_string()),
+        ]
+    );
+}
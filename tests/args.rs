@@ -0,0 +1,74 @@
+mod common;
+
+use plugin_app::host::plugin_app::core::types::{CommandSpec, ParsedArgs as WitParsedArgs};
+use plugin_app::{Cmd, ParseError, ParsedArgs};
+
+fn spec() -> CommandSpec {
+    CommandSpec {
+        positional: vec!["name".to_string()],
+        flags: vec!["loud".to_string(), "quiet".to_string()],
+        value_flags: vec!["greeting".to_string(), "lang".to_string()],
+    }
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn parse_args_follows_the_spec() {
+    let parsed = Cmd::parse_args(
+        &spec(),
+        &args(&["--quiet", "bob", "--lang", "fr", "--loud"]),
+    )
+    .unwrap();
+    assert_eq!(parsed.positional, ["bob"]);
+    assert!(parsed.flags.contains("loud") && parsed.flags.contains("quiet"));
+    assert_eq!(parsed.values["lang"], "fr");
+
+    assert_eq!(
+        Cmd::parse_args(&spec(), &args(&["--loud"])),
+        Err(ParseError::MissingArg("name".to_string()))
+    );
+    assert_eq!(
+        Cmd::parse_args(&spec(), &args(&["bob", "--lang"])),
+        Err(ParseError::MissingValue("lang".to_string()))
+    );
+    assert_eq!(
+        Cmd::parse_args(&spec(), &args(&["bob", "--nope"])),
+        Err(ParseError::UnknownFlag("nope".to_string()))
+    );
+}
+
+#[test]
+fn parsed_args_are_sorted_for_the_plugin() {
+    let parsed = ParsedArgs {
+        positional: args(&["b", "a"]),
+        flags: ["quiet", "loud"].map(String::from).into(),
+        values: [("lang", "fr"), ("greeting", "hi")]
+            .map(|(flag, value)| (flag.to_string(), value.to_string()))
+            .into(),
+    };
+    let wit = WitParsedArgs::from(&parsed);
+    assert_eq!(wit.positional, ["b", "a"]);
+    assert_eq!(wit.flags, ["loud", "quiet"]);
+    assert_eq!(
+        wit.values,
+        [
+            ("greeting".to_string(), "hi".to_string()),
+            ("lang".to_string(), "fr".to_string())
+        ]
+    );
+}
+
+#[test]
+fn plugin_gets_the_parsed_args() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    output.take_lines();
+
+    shell
+        .run_cmd("greet", args(&["--greeting", "hi", "bob", "--loud"]))
+        .unwrap();
+    assert!(output.take_lines().contains(&"INFO: HI BOB".to_string()));
+}
@@ -0,0 +1,229 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+
+use plugin_app::{
+    BuiltinFn, Cmd, CmdError, CmdGroup, CmdRegistrationError, ExecuteIn, ExecutionCtx,
+    NativeCmdRegistry, Shell, ShellError,
+};
+
+/// The arguments of the last call to `keep`.
+static KEPT: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The thread of the last call to `record_thread`.
+static THREAD: Mutex<Option<ThreadId>> = Mutex::new(None);
+
+/// The command being run during the last call to `record_current`.
+static CURRENT: Mutex<Option<String>> = Mutex::new(None);
+
+fn keep(_: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    *KEPT.lock().unwrap() = args;
+    Ok(())
+}
+
+fn noop(_: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    Ok(())
+}
+
+fn fail(_: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    Err(())
+}
+
+fn record_thread(_: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    *THREAD.lock().unwrap() = Some(thread::current().id());
+    Ok(())
+}
+
+fn record_current(ctx: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    *CURRENT.lock().unwrap() = ctx.current_command().map(String::from);
+    Ok(())
+}
+
+#[test]
+fn builtins_own_their_arguments() {
+    let (mut shell, _output) = Shell::with_output_capture();
+    shell
+        .define_cmd(
+            "keep",
+            Cmd::new("keep [args..]", "Keeps its arguments."),
+            keep as BuiltinFn,
+        )
+        .unwrap();
+
+    // the line is dropped before the arguments are read.
+    let line = String::from("a b c");
+    shell.run_cmd("keep", Shell::parse_cmd(&line)).unwrap();
+    drop(line);
+    assert_eq!(*KEPT.lock().unwrap(), ["a", "b", "c"]);
+}
+
+#[test]
+fn builtins_run_in_the_thread_they_ask_for() {
+    let (mut shell, _output) = Shell::with_output_capture();
+    for (name, execute_in) in [
+        ("where", ExecuteIn::MainThread),
+        ("where-spawned", ExecuteIn::SpawnedThread),
+    ] {
+        let cmd = Cmd::new(name, "Records its thread.").with_execute_in(execute_in);
+        shell
+            .define_cmd(name, cmd, record_thread as BuiltinFn)
+            .unwrap();
+    }
+
+    shell.run_cmd("where", Vec::new()).unwrap();
+    assert_eq!(*THREAD.lock().unwrap(), Some(thread::current().id()));
+    shell.run_cmd("where-spawned", Vec::new()).unwrap();
+    assert_ne!(*THREAD.lock().unwrap(), Some(thread::current().id()));
+}
+
+#[test]
+fn invalid_command_names_are_errors() {
+    let mut shell = Shell::new();
+    let define = |shell: &mut Shell, name: &str| {
+        shell.define_cmd(name, Cmd::new(name, "A command."), keep as BuiltinFn)
+    };
+
+    assert_eq!(define(&mut shell, ""), Err(CmdRegistrationError::EmptyName));
+    assert_eq!(
+        define(&mut shell, "a b"),
+        Err(CmdRegistrationError::ContainsWhitespace("a b".to_string()))
+    );
+    assert_eq!(
+        define(&mut shell, "a.b"),
+        Err(CmdRegistrationError::NotAlphanumeric("a.b".to_string()))
+    );
+    assert!(shell.get_cmd("a.b").is_none());
+    assert_eq!(define(&mut shell, "a-b"), Ok(()));
+}
+
+#[test]
+fn registry_defines_its_commands() {
+    let mut registry = NativeCmdRegistry::new();
+    registry
+        .register("keep-a", Cmd::new("keep-a", "Keeps nothing."), keep)
+        .register("keep-b", Cmd::new("keep-b", "Keeps nothing."), keep);
+
+    let mut shell = Shell::new();
+    shell.import_native_cmds(&registry).unwrap();
+    assert!(shell.get_cmd("keep-a").is_some());
+    assert!(shell.get_cmd("keep-b").is_some());
+
+    registry.register("a b", Cmd::new("a b", "Invalid."), keep);
+    assert!(shell.import_native_cmds(&registry).is_err());
+}
+
+#[test]
+fn experimental_commands_are_warned_about() {
+    let (mut shell, output) = Shell::with_output_capture();
+    for (name, experimental) in [("stable", false), ("beta", true)] {
+        let cmd = Cmd::new(name, "A command.").with_experimental(experimental);
+        shell.define_cmd(name, cmd, noop as BuiltinFn).unwrap();
+    }
+
+    shell.run_cmd("stable", Vec::new()).unwrap();
+    assert!(output.take_lines().is_empty());
+    shell.run_cmd("beta", Vec::new()).unwrap();
+    assert_eq!(
+        output.take_lines(),
+        ["WARNING: 'beta' is an experimental command and may change"]
+    );
+}
+
+#[test]
+fn group_is_defined_entirely_or_not_at_all() {
+    let mut shell = Shell::new();
+    let cmd = |name: &str| {
+        (
+            name.to_string(),
+            Cmd::new(name, "A command."),
+            keep as BuiltinFn,
+        )
+    };
+    shell
+        .register_cmd_group(CmdGroup {
+            group_name: "git".to_string(),
+            commands: vec![cmd("git-add"), cmd("git-commit")],
+        })
+        .unwrap();
+    assert_eq!(shell.get_cmd("git-add").unwrap().group(), Some("git"));
+    assert_eq!(shell.get_cmd("git-commit").unwrap().group(), Some("git"));
+    assert_eq!(shell.get_cmd("help").unwrap().group(), None);
+
+    // the commands defined before the invalid one are rolled back.
+    assert_eq!(
+        shell.register_cmd_group(CmdGroup {
+            group_name: "broken".to_string(),
+            commands: vec![cmd("help"), cmd("broken-a"), cmd("a b")],
+        }),
+        Err(CmdRegistrationError::ContainsWhitespace("a b".to_string()))
+    );
+    assert!(shell.get_cmd("broken-a").is_none());
+    assert_eq!(shell.get_cmd("help").unwrap().group(), None);
+    assert!(shell
+        .list_commands()
+        .iter()
+        .all(|(_, cmd)| cmd.group() != Some("broken")));
+}
+
+#[test]
+fn builtins_are_unregistered_only_if_unprotected() {
+    let mut shell = Shell::new();
+    shell
+        .define_cmd("keep", Cmd::new("keep", "Keeps."), keep as BuiltinFn)
+        .unwrap();
+
+    assert_eq!(shell.unregister_cmd("keep"), Ok(()));
+    assert!(shell.get_cmd("keep").is_none());
+    assert_eq!(
+        shell.unregister_cmd("keep"),
+        Err(CmdError::NotFound("keep".to_string()))
+    );
+
+    assert_eq!(
+        shell.unregister_cmd("load"),
+        Err(CmdError::IsBuiltin("load".to_string()))
+    );
+    assert!(shell.get_cmd("load").is_some());
+    shell.protect_builtins(false);
+    assert_eq!(shell.unregister_cmd("load"), Ok(()));
+    assert!(matches!(
+        shell.run_cmd("load", Vec::new()),
+        Err(ShellError::UnknownCommand(_))
+    ));
+}
+
+#[test]
+fn current_command_is_known_while_it_runs() {
+    let mut shell = Shell::new();
+    shell
+        .define_cmd(
+            "whoami",
+            Cmd::new("whoami", "Records its name."),
+            record_current as BuiltinFn,
+        )
+        .unwrap();
+    assert_eq!(shell.current_command(), None);
+
+    shell.run_cmd("whoami", Vec::new()).unwrap();
+    assert_eq!(CURRENT.lock().unwrap().as_deref(), Some("whoami"));
+    assert_eq!(shell.current_command(), None);
+}
+
+#[test]
+fn after_hook_is_called_after_a_successful_run() {
+    let mut shell = Shell::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    for (name, func) in [("succeed", noop as BuiltinFn), ("fail", fail as BuiltinFn)] {
+        let runs = Arc::clone(&runs);
+        shell
+            .define_cmd_with_hook(name, Cmd::new(name, "A command."), func, move |ctx| {
+                assert_eq!(ctx.current_command(), Some(name));
+                runs.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+    }
+
+    shell.run_cmd("succeed", Vec::new()).unwrap();
+    assert!(shell.run_cmd("fail", Vec::new()).is_err());
+    assert_eq!(runs.load(Ordering::Relaxed), 1);
+}
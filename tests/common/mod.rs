@@ -0,0 +1,106 @@
+//! Helpers shared by the integration tests, mostly to build the plugins used
+//! as fixtures. A plugin is built once per test binary and per variant.
+
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use plugin_app::{OutputCapture, PrivilegeLevel, Shell};
+
+/// The plugins already built, by variant.
+static BUILT: Mutex<BTreeMap<String, PathBuf>> = Mutex::new(BTreeMap::new());
+
+/// Builds the plugin crate `package` of the workspace with the cargo
+/// `features`, and turns it into a component. Returns the path to the
+/// component, it needs the `wasm32-unknown-unknown` target.
+#[track_caller]
+pub fn build_plugin(package: &str, features: &[&str]) -> PathBuf {
+    let variant = [package]
+        .into_iter()
+        .chain(features.iter().copied())
+        .collect::<Vec<_>>()
+        .join("+");
+    let mut built = BUILT.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(path) = built.get(&variant) {
+        return path.clone();
+    }
+
+    assert_wasm_target_installed();
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = root.join("target").join("test-plugins").join(&variant);
+    let status = Command::new(env!("CARGO"))
+        .current_dir(root)
+        .args(["build", "--release", "--target", "wasm32-unknown-unknown"])
+        .args(["-p", package, "--features", &features.join(",")])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(
+        status.success(),
+        "failed to build the plugin {variant}, see the output of cargo above"
+    );
+
+    let module = target_dir
+        .join("wasm32-unknown-unknown")
+        .join("release")
+        .join(format!("{}.wasm", package.replace('-', "_")));
+    let bytes = std::fs::read(&module).expect("failed to read the module of the plugin");
+    let component = wit_component::ComponentEncoder::default()
+        .module(&bytes)
+        .and_then(|encoder| encoder.validate(true).encode())
+        .expect("failed to create the component of the plugin");
+
+    let path = target_dir.join(format!("{variant}.wasm"));
+    std::fs::write(&path, component).expect("failed to write the component of the plugin");
+    built.insert(variant, path.clone());
+    path
+}
+
+/// Panics with a clear message if the `wasm32-unknown-unknown` target isn't
+/// installed, instead of the errors of cargo about a missing `core` crate.
+#[track_caller]
+fn assert_wasm_target_installed() {
+    let output = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .output()
+        .expect("failed to run rustc");
+    let sysroot = String::from_utf8_lossy(&output.stdout);
+    let target = Path::new(sysroot.trim())
+        .join("lib")
+        .join("rustlib")
+        .join("wasm32-unknown-unknown");
+    assert!(
+        target.exists(),
+        "the tests build their plugins for the wasm32-unknown-unknown target, \
+         install it with `rustup target add wasm32-unknown-unknown`"
+    );
+}
+
+/// The `test-plugin` crate, without features.
+pub fn test_plugin() -> PathBuf {
+    build_plugin("test-plugin", &[])
+}
+
+/// The `plugin-ie` crate, the plugin used as a demo.
+pub fn plugin_ie() -> PathBuf {
+    build_plugin("plugin-ie", &[])
+}
+
+/// A shell writing its output to the returned capture, run by an admin.
+pub fn shell() -> (Shell, OutputCapture) {
+    let (mut shell, output) = Shell::with_output_capture();
+    shell.set_privilege(PrivilegeLevel::Admin);
+    (shell, output)
+}
+
+/// Loads the plugin at `path` in `shell` with the `load` command.
+#[track_caller]
+pub fn load(shell: &mut Shell, path: &Path) {
+    shell
+        .run_cmd("load", vec![path.display().to_string()])
+        .expect("failed to load the plugin");
+}
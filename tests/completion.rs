@@ -0,0 +1,19 @@
+use plugin_app::Shell;
+
+#[test]
+fn commands_and_arguments_are_completed() {
+    let mut shell = Shell::new();
+    shell.register_completion_hook("load", |partial| {
+        ["a.wasm", "b.wasm"]
+            .into_iter()
+            .filter(|file| file.starts_with(partial))
+            .map(String::from)
+            .collect()
+    });
+
+    assert_eq!(shell.complete("alia"), ["alias", "aliases"]);
+    assert_eq!(shell.complete("load "), ["a.wasm", "b.wasm"]);
+    assert_eq!(shell.complete("load b"), ["b.wasm"]);
+    // the other commands have no completion hook.
+    assert!(shell.complete("help ").is_empty());
+}
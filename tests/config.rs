@@ -0,0 +1,35 @@
+mod common;
+
+use plugin_app::{ConfigError, Shell};
+
+#[test]
+fn plugins_of_the_config_are_loaded() {
+    let path = common::test_plugin();
+    let toml = format!(
+        r#"
+        plugins = [{:?}]
+        disabled-commands = ["load"]
+
+        [aliases]
+        say = "echo"
+        "#,
+        path.display().to_string()
+    );
+
+    let shell = Shell::import_from_toml(&toml).unwrap();
+    assert_eq!(shell.plugin_count(), 1);
+    assert!(shell.get_cmd("echo").is_some());
+    assert!(shell.get_cmd("say").is_some());
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+    assert!(matches!(
+        Shell::import_from_toml("plugins = 1"),
+        Err(ConfigError::Parse(_))
+    ));
+    assert!(matches!(
+        Shell::import_from_toml(r#"plugins = ["does-not-exist.wasm"]"#),
+        Err(ConfigError::Plugin(..))
+    ));
+}
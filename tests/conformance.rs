@@ -0,0 +1,91 @@
+mod common;
+
+use std::path::Path;
+
+use plugin_app::host::{check_conformance, ConformanceError, PluginHost, SharedCtx};
+use plugin_app::{BuiltinFn, Cmd, CmdRegistrationError, ExecutionCtx, Shell, MAX_CMD_NAME_LEN};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+fn noop(_: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    Ok(())
+}
+
+#[test]
+fn command_names_up_to_the_max_length_are_valid() {
+    let mut shell = Shell::new();
+    let name = "a".repeat(MAX_CMD_NAME_LEN);
+    shell
+        .define_cmd(&name, Cmd::new("a", "A command."), noop as BuiltinFn)
+        .unwrap();
+
+    let name = "a".repeat(MAX_CMD_NAME_LEN + 1);
+    assert_eq!(
+        shell.define_cmd(&name, Cmd::new("a", "A command."), noop as BuiltinFn),
+        Err(CmdRegistrationError::NameTooLong(name))
+    );
+}
+
+#[test]
+fn empty_component_misses_the_required_exports() {
+    let engine = Engine::default();
+    let component = Component::new(&engine, "(component)").unwrap();
+    let errors = check_conformance(&engine, &component).unwrap_err();
+    assert!(errors.contains(&ConformanceError::Missing("init".to_string())));
+    // the optional exports may be missing.
+    assert!(!errors.contains(&ConformanceError::Missing("on-error".to_string())));
+    assert!(!errors.contains(&ConformanceError::Missing("on-unload".to_string())));
+}
+
+#[test]
+fn check_conformance_builtin_accepts_the_plugins() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    common::load(&mut shell, &common::plugin_ie());
+
+    for name in ["test-plugin", "plugin-ie"] {
+        output.take_lines();
+        shell
+            .run_cmd("check-conformance", vec![name.to_string()])
+            .unwrap();
+        assert_eq!(
+            output.take_lines(),
+            [format!(
+                "The plugin {name} conforms to the plugin interface."
+            )]
+        );
+    }
+}
+
+#[test]
+fn exports_are_checked_against_a_wit_file() {
+    let wit_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("wit")
+        .join("plugin.wit");
+    let wit = std::fs::read_to_string(&wit_path).unwrap();
+    let host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .unwrap();
+    assert!(host
+        .validate_exports_against_wit_source(&wit_path)
+        .unwrap()
+        .is_empty());
+
+    // a WIT the plugin wasn't compiled with, with one more export.
+    let modified = wit.replacen(
+        "world core {",
+        "world core {\n  export fake-export: func(x: u32) -> string;\n",
+        1,
+    );
+    assert_ne!(modified, wit);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("plugin.wit");
+    std::fs::write(&path, modified).unwrap();
+    assert_eq!(
+        host.validate_exports_against_wit_source(&path).unwrap(),
+        [ConformanceError::Missing("fake-export".to_string())]
+    );
+}
@@ -0,0 +1,49 @@
+use std::io::{self, BufRead, Read};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use plugin_app::{Shell, ShellError};
+
+/// Yields a line of input every 50ms, for ever.
+struct SlowReader;
+
+impl Read for SlowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let line = self.fill_buf()?;
+        let len = line.len().min(buf.len());
+        buf[..len].copy_from_slice(&line[..len]);
+        Ok(len)
+    }
+}
+
+impl BufRead for SlowReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        thread::sleep(Duration::from_millis(50));
+        Ok(b"aliases\n")
+    }
+
+    fn consume(&mut self, _: usize) {}
+}
+
+#[test]
+fn shell_stops_at_its_deadline() {
+    let (shell, _output) = Shell::with_output_capture();
+    let mut shell = shell.with_stdin(SlowReader);
+
+    let start = Instant::now();
+    let res = shell.run_with_deadline(start + Duration::from_millis(100));
+    assert_eq!(res, Err(ShellError::DeadlineExceeded));
+    assert!(start.elapsed() < Duration::from_secs(1));
+    // the lines read after the deadline aren't run.
+    assert!(shell.metrics().total_invocations <= 3);
+}
+
+#[test]
+fn shell_without_input_stops_before_its_deadline() {
+    let (shell, _output) = Shell::with_output_capture();
+    let mut shell = shell.with_stdin(io::Cursor::new("aliases\naliases\n"));
+
+    let res = shell.run_with_deadline(Instant::now() + Duration::from_secs(60));
+    assert_eq!(res, Ok(()));
+    assert_eq!(shell.metrics().total_invocations, 2);
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "debug")]
+
+mod common;
+
+use plugin_app::host::{PluginHost, SharedCtx};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+#[test]
+fn plugin_is_disassembled() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    output.take_lines();
+
+    shell
+        .run_cmd("disasm", vec!["test-plugin".to_string()])
+        .unwrap();
+    let lines = output.take_lines();
+    assert!(lines[0].starts_with("(component"), "{}", lines[0]);
+}
+
+#[test]
+fn precompiled_plugin_cant_be_disassembled() {
+    let engine = Engine::default();
+    let component = Component::from_file(&engine, common::test_plugin()).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test_plugin.cwasm");
+    std::fs::write(&path, component.serialize().unwrap()).unwrap();
+
+    let host = PluginHost::try_new_precompiled(engine, SharedCtx::default(), &path).unwrap();
+    assert!(host.try_print_wasm_disassembly().is_err());
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use plugin_app::{PrivilegeLevel, Shell};
+
+#[test]
+fn plugins_share_the_shell_environment() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    output.take_lines();
+
+    shell
+        .run_cmd("env-set", vec!["greeting".to_string(), "hi".to_string()])
+        .unwrap();
+    output.take_lines();
+    shell.run_cmd("env", Vec::new()).unwrap();
+    assert_eq!(output.take_lines(), [format!("  {:16} = hi", "greeting")]);
+
+    shell
+        .run_cmd("env-get", vec!["greeting".to_string()])
+        .unwrap();
+    assert_eq!(output.take_lines(), ["INFO: hi"]);
+}
+
+#[test]
+fn sandboxed_env_hides_the_process_environment() {
+    std::env::set_var("TEST_PLUGIN_HOST_VAR", "host");
+    let get = || vec!["TEST_PLUGIN_HOST_VAR".to_string()];
+
+    for sandboxed in [false, true] {
+        let (shell, output) = Shell::with_output_capture();
+        let mut shell = if sandboxed {
+            shell.with_sandboxed_env()
+        } else {
+            shell
+        };
+        shell.set_privilege(PrivilegeLevel::Admin);
+        common::load(&mut shell, &common::test_plugin());
+        output.take_lines();
+
+        shell.run_cmd("env-get", get()).unwrap();
+        let expected = if sandboxed {
+            "ERROR: the variable TEST_PLUGIN_HOST_VAR isn't set"
+        } else {
+            "INFO: host"
+        };
+        assert_eq!(output.take_lines(), [expected]);
+    }
+}
+
+#[test]
+fn forked_shell_has_a_copy_of_the_environment() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    let set = |shell: &mut Shell, value: &str| {
+        shell
+            .run_cmd("env-set", vec!["greeting".to_string(), value.to_string()])
+            .unwrap();
+    };
+    set(&mut shell, "hi");
+
+    let mut child = shell.fork();
+    assert_eq!(child.plugin_count(), 0);
+    assert!(child.get_cmd("help").is_some());
+    assert!(child.get_cmd("echo").is_none());
+    // the child writes to the same output.
+    common::load(&mut child, &common::test_plugin());
+    output.take_lines();
+    child
+        .run_cmd("env-get", vec!["greeting".to_string()])
+        .unwrap();
+    assert_eq!(output.take_lines(), ["INFO: hi"]);
+    set(&mut child, "bye");
+
+    output.take_lines();
+    shell
+        .run_cmd("env-get", vec!["greeting".to_string()])
+        .unwrap();
+    assert_eq!(output.take_lines(), ["INFO: hi"]);
+}
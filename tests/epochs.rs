@@ -0,0 +1,105 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use plugin_app::host::{PluginHost, PluginHostBuilder, SharedCtx, INIT_MAX_EPOCHS};
+use plugin_app::{PrivilegeLevel, Shell};
+use wasmtime::{Config, Engine, Trap};
+
+fn engine() -> Engine {
+    let mut config = Config::new();
+    config.epoch_interruption(true);
+    Engine::new(&config).unwrap()
+}
+
+#[test]
+fn call_past_max_epochs_is_interrupted() {
+    let engine = engine();
+    let _epochs = PluginHost::start_epoch_thread(engine.clone(), Duration::from_millis(10));
+    let mut host = PluginHostBuilder::new(engine, SharedCtx::default(), common::test_plugin())
+        .with_max_epochs(3)
+        .build()
+        .unwrap();
+    host.try_call_init().unwrap();
+
+    let err = host.try_call_run_command("spin", &[]).unwrap_err();
+    assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::Interrupt));
+}
+
+#[test]
+fn call_past_timeout_is_interrupted() {
+    let engine = engine();
+    let _epochs = PluginHost::start_epoch_thread(engine.clone(), Duration::from_millis(10));
+    let mut host = PluginHostBuilder::new(engine, SharedCtx::default(), common::test_plugin())
+        .with_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    host.try_call_init().unwrap();
+
+    let start = Instant::now();
+    let err = host.try_call_run_command("spin", &[]).unwrap_err();
+    assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::Interrupt));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn shell_interrupts_plugins_with_its_epoch_thread() {
+    let (shell, _output) = Shell::with_output_capture();
+    let mut shell = shell.with_plugin_max_epochs(2);
+    shell.set_privilege(PrivilegeLevel::Admin);
+    common::load(&mut shell, &common::test_plugin());
+
+    let start = Instant::now();
+    assert!(shell.run_cmd("spin", Vec::new()).is_err());
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn init_past_its_max_epochs_is_interrupted() {
+    let engine = engine();
+    let shared = SharedCtx::default();
+    shared
+        .env
+        .lock()
+        .unwrap()
+        .insert("TEST_PLUGIN_SLOW_INIT".to_string(), "1".to_string());
+    let mut host = PluginHost::try_new(engine.clone(), shared, common::test_plugin()).unwrap();
+
+    // no epoch thread, the epoch is only incremented here.
+    let done = Arc::new(AtomicBool::new(false));
+    let ticker = {
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                engine.increment_epoch();
+                thread::sleep(Duration::from_millis(1));
+            }
+        })
+    };
+    let err = host.try_call_init().unwrap_err();
+    done.store(true, Ordering::SeqCst);
+    ticker.join().unwrap();
+
+    assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::Interrupt));
+    assert_eq!(host.dump_store_stats().epoch, INIT_MAX_EPOCHS);
+}
+
+#[test]
+fn idle_epochs_dont_count_against_a_call() {
+    let engine = engine();
+    let tick = Duration::from_millis(500);
+    let _epochs = PluginHost::start_epoch_thread(engine.clone(), tick);
+    let mut host = PluginHostBuilder::new(engine, SharedCtx::default(), common::test_plugin())
+        .with_max_epochs(1)
+        .build()
+        .unwrap();
+    host.try_call_init().unwrap();
+
+    // the epoch advances while the plugin is idle.
+    thread::sleep(tick * 3);
+    host.try_call_run_command("echo", &["hi".to_string()])
+        .unwrap();
+}
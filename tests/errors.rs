@@ -0,0 +1,26 @@
+mod common;
+
+use plugin_app::{PrivilegeLevel, Shell};
+
+#[test]
+fn verbose_shell_prints_the_wasm_backtrace() {
+    for verbose in [false, true] {
+        let (shell, output) = Shell::with_output_capture();
+        let mut shell = shell.with_verbose(verbose);
+        shell.set_privilege(PrivilegeLevel::Admin);
+        common::load(&mut shell, &common::test_plugin());
+        output.take_lines();
+
+        assert!(shell.run_cmd("trap", Vec::new()).is_err());
+        let lines = output.take_lines();
+        assert!(
+            lines[0].starts_with("ERR: the plugin test-plugin "),
+            "{lines:?}"
+        );
+        assert_eq!(
+            lines.iter().any(|line| line.contains("wasm backtrace")),
+            verbose,
+            "{lines:?}"
+        );
+    }
+}
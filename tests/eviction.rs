@@ -0,0 +1,33 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use plugin_app::{PrivilegeLevel, Shell};
+
+#[test]
+fn idle_plugins_are_unloaded() {
+    let (shell, output) = Shell::with_output_capture();
+    let mut shell = shell.with_plugin_idle_ttl(Duration::from_millis(50));
+    shell.set_privilege(PrivilegeLevel::Admin);
+    shell
+        .run_cmd(
+            "load",
+            vec![
+                "--no-evict".to_string(),
+                common::plugin_ie().display().to_string(),
+            ],
+        )
+        .unwrap();
+    common::load(&mut shell, &common::test_plugin());
+    output.take_lines();
+
+    thread::sleep(Duration::from_millis(100));
+    shell.run_cmd("help", Vec::new()).unwrap();
+    assert!(output
+        .take_lines()
+        .contains(&"Plugin test-plugin unloaded, it was idle".to_string()));
+    assert_eq!(shell.plugin_count(), 1);
+    assert!(shell.get_cmd("echo").is_none());
+    assert_eq!(shell.evict_idle_plugins(), Vec::<String>::new());
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use plugin_app::host::WasmFeatureSet;
+use plugin_app::{PrivilegeLevel, Shell};
+use wasmtime::{Config, Engine};
+
+fn no_simd() -> Config {
+    let mut config = Config::new();
+    config.wasm_relaxed_simd(false).wasm_simd(false);
+    config
+}
+
+#[test]
+fn features_of_the_engine_are_detected() {
+    let features = WasmFeatureSet::of_engine(&Engine::default());
+    assert!(features.simd);
+
+    let features = WasmFeatureSet::of_engine(&Engine::new(&no_simd()).unwrap());
+    assert!(!features.simd);
+    assert_eq!(features.supports("simd"), Some(false));
+    assert_eq!(features.supports("nope"), None);
+}
+
+#[test]
+fn plugin_requiring_a_disabled_feature_is_reported() {
+    // test-plugin declares the features of this variable, the plugins can
+    // read the environment of the tests.
+    std::env::set_var("TEST_PLUGIN_REQUIRED_FEATURES", "simd");
+
+    let (shell, output) = Shell::with_output_capture();
+    let mut shell = shell.with_wasm_config(no_simd());
+    shell.set_privilege(PrivilegeLevel::Admin);
+    common::load(&mut shell, &common::test_plugin());
+    assert!(output.take_lines().contains(
+        &"WARNING: the plugin test-plugin uses the WASM feature simd, it isn't enabled".to_string()
+    ));
+
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    assert!(!output
+        .take_lines()
+        .iter()
+        .any(|line| line.starts_with("WARNING")));
+}
@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use plugin_app::{Shell, ShellError};
+
+#[test]
+fn history_references_are_expanded() {
+    let history = ["help", "aliases", "env"]
+        .into_iter()
+        .map(|line| (Instant::now(), line.to_string()))
+        .collect::<VecDeque<_>>();
+    let expand = |input| Shell::parse_cmd_with_history_expansion(input, &history);
+
+    assert_eq!(expand("!!"), Ok("env".to_string()));
+    assert_eq!(expand("!1 x"), Ok("help x".to_string()));
+    assert_eq!(expand("!-2"), Ok("aliases".to_string()));
+    assert_eq!(expand("hi! !-"), Ok("hi! !-".to_string()));
+    assert_eq!(
+        expand("!4"),
+        Err(ShellError::HistoryOutOfRange("!4".to_string()))
+    );
+    assert_eq!(
+        expand("!-0"),
+        Err(ShellError::HistoryOutOfRange("!-0".to_string()))
+    );
+}
+
+#[test]
+fn history_is_filtered_by_time() {
+    let (shell, _output) = Shell::with_output_capture();
+    let mut shell = shell
+        .with_interactive(false)
+        .with_stdin(io::Cursor::new("help\n\n"));
+    shell.run().unwrap();
+    thread::sleep(Duration::from_millis(10));
+    let since = Instant::now();
+    let mut shell = shell.with_stdin(io::Cursor::new("aliases\n"));
+    shell.run().unwrap();
+
+    let lines = |history: Vec<(Instant, String)>| {
+        history
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+    };
+    // the empty lines aren't kept.
+    assert_eq!(lines(shell.full_history()), ["help", "aliases"]);
+    assert_eq!(lines(shell.command_history_since(since)), ["aliases"]);
+}
@@ -0,0 +1,376 @@
+mod common;
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use plugin_app::host::{
+    PluginCallError, PluginEvent, PluginHost, PluginHostBuilder, RunOptions, SharedCtx,
+    MAX_EVENT_LOG_LEN,
+};
+use wasmtime::{Config, Engine, Trap};
+
+#[test]
+fn optional_exports_can_be_missing() {
+    for path in [
+        common::test_plugin(),
+        common::build_plugin("test-plugin", &["optional-exports"]),
+    ] {
+        let mut host = PluginHost::try_new(Engine::default(), SharedCtx::default(), &path)
+            .expect("a plugin without the optional exports must load");
+        host.try_call_init().unwrap();
+        assert!(host.is_alive());
+        assert_eq!(host.try_call_describe_command("echo").unwrap(), None);
+        assert_eq!(host.try_call_command_schema("echo").unwrap(), None);
+        assert!(host.try_call_list_events().unwrap().is_empty());
+        assert_eq!(host.try_call_get_telemetry().unwrap().errors, 0);
+
+        // the error of the command is returned even if `on-error` is missing
+        // or fails.
+        let err = host.try_call_run_command("trap", &[]).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Trap>(),
+            Some(&Trap::UnreachableCodeReached)
+        );
+        assert!(!host.is_alive());
+    }
+}
+
+#[test]
+fn lifecycle_exports_fall_back_when_missing() {
+    let mut host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .unwrap();
+    host.try_call_init().unwrap();
+    assert_eq!(host.export_state().unwrap(), None);
+    host.import_state(b"ignored").unwrap();
+    host.call_on_unload().unwrap();
+
+    let mut host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::build_plugin("test-plugin", &["optional-exports"]),
+    )
+    .unwrap();
+    host.try_call_init().unwrap();
+    assert_eq!(host.export_state().unwrap(), Some(Vec::new()));
+    host.import_state(&[]).unwrap();
+    host.call_on_unload().unwrap();
+}
+
+#[test]
+fn store_stats_report_the_fuel_only_if_its_enabled() {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let mut host = PluginHostBuilder::new(
+        Engine::new(&config).unwrap(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .with_fuel_limit(u64::MAX)
+    .build()
+    .unwrap();
+    host.try_call_init().unwrap();
+    host.try_call_run_command("echo", &["hello".to_string()])
+        .unwrap();
+    assert!(host
+        .dump_store_stats()
+        .fuel_consumed
+        .is_some_and(|fuel| fuel > 0));
+
+    let mut host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .unwrap();
+    host.try_call_init().unwrap();
+    host.try_call_run_command("echo", &["hello".to_string()])
+        .unwrap();
+    assert_eq!(host.dump_store_stats().fuel_consumed, None);
+}
+
+#[test]
+fn init_is_retried_in_a_new_store() {
+    let flaky = || {
+        let shared = SharedCtx::default();
+        shared
+            .env
+            .lock()
+            .unwrap()
+            .insert("TEST_PLUGIN_FLAKY_INIT".to_string(), "1".to_string());
+        PluginHostBuilder::new(Engine::default(), shared, common::test_plugin())
+    };
+
+    let mut host = flaky().build().unwrap();
+    let err = host.try_call_init().unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<Trap>(),
+        Some(&Trap::UnreachableCodeReached)
+    );
+
+    let mut host = flaky()
+        .with_init_retries(1, Duration::from_millis(1))
+        .build()
+        .unwrap();
+    assert_eq!(host.try_call_init().unwrap().name, "test-plugin");
+    assert!(host.is_alive());
+}
+
+#[test]
+fn warmup_measures_the_init() {
+    let mut host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .unwrap();
+    let duration = host.warmup().unwrap();
+    assert!(duration > Duration::ZERO);
+    // the plugin is initialized.
+    host.try_call_run_command("echo", &[]).unwrap();
+}
+
+#[test]
+fn plugin_infos_are_equal_by_name_and_version() {
+    let init = |path| {
+        PluginHost::try_new(Engine::default(), SharedCtx::default(), path)
+            .unwrap()
+            .try_call_init()
+            .unwrap()
+    };
+    let info = init(common::test_plugin());
+    let mut other = init(common::build_plugin("test-plugin", &["optional-exports"]));
+    // the other fields differ, but not the name and the version.
+    other.description.push_str(" Changed.");
+    assert_eq!(info, other);
+    assert_eq!(HashSet::from([info.clone(), other.clone()]).len(), 1);
+    assert!(!info.conflicts_with(&other));
+
+    other.version = "99.0.0".to_string();
+    assert_ne!(info, other);
+    assert!(info.conflicts_with(&other));
+    assert!(!info.conflicts_with(&init(common::plugin_ie())));
+}
+
+#[test]
+fn output_of_a_command_is_returned() {
+    let mut host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .unwrap();
+    host.try_call_init().unwrap();
+
+    assert_eq!(
+        host.call_run_command_returning_string("echo", &["hi".to_string()]),
+        Ok("hi".to_string())
+    );
+    assert_eq!(
+        host.call_run_command_returning_string("env-get", &["nope".to_string()]),
+        Err("the variable nope isn't set".to_string())
+    );
+    assert_eq!(host.call_count(), 2);
+}
+
+#[test]
+fn compiled_component_is_cached() {
+    let dir = tempfile::tempdir().unwrap();
+    for _ in 0..2 {
+        let mut host = PluginHostBuilder::new(
+            Engine::default(),
+            SharedCtx::default(),
+            common::test_plugin(),
+        )
+        .with_cache_dir(dir.path())
+        .build()
+        .unwrap();
+        assert_eq!(host.try_call_init().unwrap().name, "test-plugin");
+    }
+
+    let cached = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(cached.len(), 1);
+    assert_eq!(cached[0].extension().unwrap(), "cwasm");
+}
+
+#[test]
+fn options_apply_to_a_single_call() {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let mut host = PluginHostBuilder::new(
+        Engine::new(&config).unwrap(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .with_fuel_limit(u64::MAX)
+    .build()
+    .unwrap();
+    host.try_call_init().unwrap();
+
+    let capture = RunOptions {
+        capture_output: true,
+        ..RunOptions::default()
+    };
+    let res = host
+        .call_run_command_opts("echo", &["hi".to_string()], capture.clone())
+        .unwrap();
+    assert_eq!(res.output.as_deref(), Some("hi"));
+    let res = host
+        .call_run_command_opts("echo", &["hi".to_string()], RunOptions::default())
+        .unwrap();
+    assert_eq!(res.output, None);
+    assert!(matches!(
+        host.call_run_command_opts("env-get", &["nope".to_string()], capture),
+        Err(PluginCallError::CommandFailed(msg)) if msg == "the variable nope isn't set"
+    ));
+
+    let starved = RunOptions {
+        fuel_override: Some(1),
+        ..RunOptions::default()
+    };
+    let Err(PluginCallError::Wasm(err)) = host.call_run_command_opts("echo", &[], starved) else {
+        panic!("the call must run out of fuel");
+    };
+    assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::OutOfFuel));
+}
+
+#[test]
+fn resources_used_by_a_plugin_are_tracked() {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let mut host = PluginHostBuilder::new(
+        Engine::new(&config).unwrap(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .with_fuel_limit(u64::MAX)
+    .build()
+    .unwrap();
+    host.try_call_init().unwrap();
+    let before = host.resource_stats();
+    assert_eq!(before.call_count, 0);
+
+    host.try_call_run_command("alloc", &["1024".to_string()])
+        .unwrap();
+    let after = host.resource_stats();
+    assert_eq!(after.call_count, 1);
+    assert!(after.fuel_consumed > before.fuel_consumed);
+    // 1MiB is 16 pages.
+    assert!(after.peak_memory_pages >= before.peak_memory_pages + 16);
+}
+
+#[test]
+fn calls_to_the_imports_are_logged() {
+    let mut host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .unwrap();
+    host.try_call_init().unwrap();
+    host.flush_event_log();
+
+    host.try_call_run_command("env-set", &["a".to_string(), "b".to_string()])
+        .unwrap();
+    let events = host.flush_event_log();
+    assert_eq!(
+        events[0],
+        PluginEvent::EnvSet("a".to_string(), "b".to_string())
+    );
+    assert!(matches!(&events[1], PluginEvent::Log { msg, .. } if msg == "a = b"));
+    assert_eq!(events.len(), 2);
+    assert!(host.flush_event_log().is_empty());
+}
+
+#[test]
+fn event_log_keeps_the_last_calls() {
+    let mut host = PluginHostBuilder::new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .with_max_log_per_call(1)
+    .build()
+    .unwrap();
+    host.try_call_init().unwrap();
+    host.flush_event_log();
+
+    let n = MAX_EVENT_LOG_LEN + 500;
+    host.try_call_run_command("flood", &[n.to_string()])
+        .unwrap();
+    let events = host.flush_event_log();
+    assert_eq!(events.len(), MAX_EVENT_LOG_LEN);
+    assert!(matches!(&events[0], PluginEvent::Log { msg, .. } if msg == "message 501"));
+}
+
+#[test]
+fn latencies_of_the_calls_are_recorded() {
+    let mut host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .unwrap();
+    assert_eq!(host.p50_latency(), None);
+    host.try_call_init().unwrap();
+    assert!(host.p50_latency().is_some());
+
+    for _ in 0..20 {
+        host.try_call_run_command("echo", &[]).unwrap();
+    }
+    host.try_call_run_command("alloc", &["4096".to_string()])
+        .unwrap();
+    let (p50, p95, p99) = (
+        host.p50_latency().unwrap(),
+        host.p95_latency().unwrap(),
+        host.p99_latency().unwrap(),
+    );
+    assert!(p50 <= p95 && p95 <= p99);
+}
+
+#[test]
+fn every_way_of_running_a_command_is_counted() {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let mut host = PluginHostBuilder::new(
+        Engine::new(&config).unwrap(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .with_fuel_limit(u64::MAX)
+    .build()
+    .unwrap();
+    host.try_call_init().unwrap();
+
+    type Run = fn(&mut PluginHost, &[String]) -> wasmtime::Result<()>;
+    let runs: [Run; 3] = [
+        |host, args| host.try_call_run_command("echo", args),
+        |host, args| {
+            host.call_run_command_opts("echo", args, RunOptions::default())
+                .map(|_| ())
+                .map_err(|err| wasmtime::Error::msg(err.to_string()))
+        },
+        |host, args| {
+            host.try_call_run_command_returning_string("echo", args)
+                .map(|_| ())
+        },
+    ];
+
+    let args = ["hi".to_string()];
+    let mut fuel = host.resource_stats().fuel_consumed;
+    for (i, run) in runs.into_iter().enumerate() {
+        let before = Instant::now();
+        run(&mut host, &args).unwrap();
+        assert_eq!(host.call_count(), i as u64 + 1);
+        assert!(host.last_called_at().is_some_and(|at| at >= before));
+        let consumed = host.resource_stats().fuel_consumed;
+        assert!(consumed > fuel);
+        fuel = consumed;
+    }
+}
@@ -0,0 +1,21 @@
+#![cfg(feature = "test-utils")]
+
+mod common;
+
+use plugin_app::host::{PluginHost, SharedCtx};
+use wasmtime::Engine;
+
+#[test]
+fn commands_with_a_different_output_are_not_idempotent() {
+    let mut host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .unwrap();
+    host.try_call_init().unwrap();
+
+    assert!(host.call_run_command_idempotency_check("echo", &["hello".to_string()]));
+    assert!(!host.call_run_command_idempotency_check("tick", &[]));
+    assert!(host.flush_event_log().is_empty());
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use plugin_app::host::{PluginHostBuilder, SharedCtx};
+use wasmtime::Engine;
+
+#[test]
+fn plugin_calls_the_mock_import() {
+    let path = common::build_plugin("test-plugin", &["mock-import"]);
+    let mut host = PluginHostBuilder::new(Engine::default(), SharedCtx::default(), path)
+        .add_import("test:plugin/mock", "get-config", |(_key,): (String,)| {
+            Ok(("test-value".to_string(),))
+        })
+        .build()
+        .unwrap();
+    host.try_call_init().unwrap();
+
+    let output = host
+        .try_call_run_command_returning_string("config", &["key".to_string()])
+        .unwrap();
+    assert_eq!(output, Ok("test-value".to_string()));
+}
+
+#[test]
+fn plugin_without_the_mock_import_fails_to_load() {
+    let path = common::build_plugin("test-plugin", &["mock-import"]);
+    assert!(
+        PluginHostBuilder::new(Engine::default(), SharedCtx::default(), path)
+            .build()
+            .is_err()
+    );
+}
@@ -0,0 +1,21 @@
+use plugin_app::{InterceptResult, Shell, ShellError};
+
+#[test]
+fn interceptor_denies_or_replaces_commands() {
+    let (shell, _output) = Shell::with_output_capture();
+    let mut shell = shell.with_command_interceptor(|name, _| match name {
+        "load" => InterceptResult::Deny("no plugins in this shell".to_string()),
+        "al" => InterceptResult::Replace("aliases".to_string(), Vec::new()),
+        _ => InterceptResult::Allow,
+    });
+
+    assert_eq!(
+        shell.run_cmd("load", vec!["plugin.wasm".to_string()]),
+        Err(ShellError::Denied("no plugins in this shell".to_string()))
+    );
+    assert_eq!(shell.plugin_count(), 0);
+
+    shell.run_cmd("al", Vec::new()).unwrap();
+    assert_eq!(shell.metrics().invocations.get("aliases"), Some(&1));
+    assert_eq!(shell.run_cmd("env", Vec::new()), Ok(()));
+}
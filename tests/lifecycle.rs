@@ -0,0 +1,34 @@
+mod common;
+
+use plugin_app::OutputCapture;
+use serde_json::Value;
+
+#[test]
+fn lifecycle_events_are_logged_as_json() {
+    let (mut shell, _output) = common::shell();
+    let log = OutputCapture::default();
+    shell.register_lifecycle_logger(log.clone());
+
+    common::load(&mut shell, &common::test_plugin());
+    _ = shell.run_cmd("nope", Vec::new());
+    shell.unload_plugin("test-plugin");
+
+    let records = log
+        .take_lines()
+        .iter()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    let events = records
+        .iter()
+        .map(|record| record["event"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        ["command-start", "load", "command-end", "error", "unload"]
+    );
+    assert!(records.iter().all(|record| record["ts"].is_u64()));
+    assert_eq!(records[1]["plugin"], "test-plugin");
+    assert_eq!(records[2]["success"], true);
+    assert_eq!(records[3]["command"], "nope");
+    assert_eq!(records[4]["plugin"], "test-plugin");
+}
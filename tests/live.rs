@@ -0,0 +1,22 @@
+mod common;
+
+use plugin_app::host::{PluginHost, SharedCtx};
+use plugin_app::live_plugin_count;
+use wasmtime::Engine;
+
+// the only test of this binary, no other host is alive meanwhile.
+#[test]
+fn live_hosts_are_counted() {
+    let path = common::test_plugin();
+    assert_eq!(live_plugin_count(), 0);
+
+    let engine = Engine::default();
+    let first = PluginHost::try_new(engine.clone(), SharedCtx::default(), &path).unwrap();
+    let second = PluginHost::try_new(engine, SharedCtx::default(), &path).unwrap();
+    assert_eq!(live_plugin_count(), 2);
+
+    drop(first);
+    assert_eq!(live_plugin_count(), 1);
+    drop(second);
+    assert_eq!(live_plugin_count(), 0);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use plugin_app::{AutoLoadErrors, PrivilegeLevel, Shell};
+
+#[test]
+fn loading_a_plugin_twice_fails() {
+    let (mut shell, output) = common::shell();
+    let path = common::test_plugin();
+    common::load(&mut shell, &path);
+    output.take_lines();
+
+    assert!(shell
+        .run_cmd("load", vec![path.display().to_string()])
+        .is_err());
+    assert!(output.take_lines().contains(
+        &"ERR: failed to load the plugin, a plugin named \"test-plugin\" is already loaded"
+            .to_string()
+    ));
+    assert_eq!(shell.plugin_count(), 1);
+}
+
+#[test]
+fn plugins_with_a_rejected_name_fail_to_load() {
+    let (shell, output) = Shell::with_output_capture();
+    let mut shell = shell.with_plugin_name_validator(|name| name.starts_with("plugin-"));
+    shell.set_privilege(PrivilegeLevel::Admin);
+
+    assert!(shell
+        .run_cmd("load", vec![common::test_plugin().display().to_string()])
+        .is_err());
+    assert!(output.take_lines().contains(
+        &"ERR: failed to load the plugin, the plugin name \"test-plugin\" isn't allowed in this shell"
+            .to_string()
+    ));
+    assert_eq!(shell.plugin_count(), 0);
+
+    common::load(&mut shell, &common::plugin_ie());
+    assert_eq!(shell.plugin_count(), 1);
+}
+
+#[test]
+fn plugins_of_a_directory_are_loaded() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::copy(common::test_plugin(), dir.path().join("a.wasm")).unwrap();
+    std::fs::copy(common::plugin_ie(), dir.path().join("b.wasm")).unwrap();
+    std::fs::write(dir.path().join("broken.wasm"), "not wasm").unwrap();
+    std::fs::write(dir.path().join("README.md"), "not a plugin").unwrap();
+
+    let Err(AutoLoadErrors(errors)) = Shell::new().with_auto_load_dir(dir.path()) else {
+        panic!("the broken plugin must fail to load");
+    };
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, dir.path().join("broken.wasm"));
+
+    let shell = Shell::new().with_auto_load_dir_lenient(dir.path());
+    assert_eq!(shell.plugin_count(), 2);
+    assert!(shell.get_cmd("echo").is_some());
+    assert!(shell.get_cmd("hello").is_some());
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use plugin_app::{LoadOrder, Shell};
+
+/// Copies of the test plugin, the copies name themselves differently.
+const COPIES: usize = 4;
+
+/// Loads the plugins of `dir` in the `order`, returns the shortest time out
+/// of a few tries.
+fn load_time(dir: &Path, order: LoadOrder) -> Duration {
+    (0..3)
+        .map(|_| {
+            let (shell, _output) = Shell::with_output_capture();
+            let start = Instant::now();
+            let shell = shell
+                .with_plugin_init_order(order)
+                .with_auto_load_dir(dir)
+                .unwrap();
+            let elapsed = start.elapsed();
+            assert_eq!(shell.plugin_count(), COPIES);
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+#[test]
+fn loading_the_plugins_in_parallel_is_faster() {
+    // test-plugin reads this variable of the tests, every copy gets a name
+    // of its own.
+    std::env::set_var("TEST_PLUGIN_UNIQUE_NAMES", "1");
+    let path = common::test_plugin();
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..COPIES {
+        std::fs::copy(&path, dir.path().join(format!("copy-{i}.wasm"))).unwrap();
+    }
+
+    let sequential = load_time(dir.path(), LoadOrder::Sequential);
+    let parallel = load_time(dir.path(), LoadOrder::Parallel(COPIES));
+    if std::thread::available_parallelism().map_or(1, |n| n.get()) > 1 {
+        assert!(
+            parallel < sequential,
+            "parallel: {parallel:?}, sequential: {sequential:?}"
+        );
+    }
+}
@@ -0,0 +1,25 @@
+mod common;
+
+use plugin_app::{PrivilegeLevel, Shell};
+
+#[test]
+fn greeting_is_in_the_language_of_the_locale() {
+    for (locale, greeting) in [
+        (None, "Hello!"),
+        (Some("fr-FR"), "Bonjour!"),
+        (Some("de"), "Hallo!"),
+        (Some("ja"), "Hello!"),
+    ] {
+        let (shell, output) = Shell::with_output_capture();
+        let mut shell = match locale {
+            Some(locale) => shell.with_locale(locale),
+            None => shell,
+        };
+        shell.set_privilege(PrivilegeLevel::Admin);
+        common::load(&mut shell, &common::plugin_ie());
+        output.take_lines();
+
+        shell.run_cmd("hello", Vec::new()).unwrap();
+        assert_eq!(output.take_lines(), [format!("INFO: {greeting}")]);
+    }
+}
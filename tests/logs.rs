@@ -0,0 +1,24 @@
+mod common;
+
+use plugin_app::{PrivilegeLevel, Shell};
+
+#[test]
+fn logs_past_the_limit_of_a_call_are_dropped() {
+    let (shell, output) = Shell::with_output_capture();
+    let mut shell = shell.with_max_log_messages_per_call(10);
+    shell.set_privilege(PrivilegeLevel::Admin);
+    common::load(&mut shell, &common::test_plugin());
+    output.take_lines();
+
+    shell.run_cmd("flood", vec!["200".to_string()]).unwrap();
+    let lines = output.take_lines();
+    let expected = (1..=10)
+        .map(|i| format!("INFO: message {i}"))
+        .chain(["WARNING: log limit reached, 190 messages of the plugin were dropped".to_string()])
+        .collect::<Vec<_>>();
+    assert_eq!(lines, expected);
+
+    // the limit is per call.
+    shell.run_cmd("echo", vec!["hello".to_string()]).unwrap();
+    assert_eq!(output.take_lines(), ["INFO: hello"]);
+}
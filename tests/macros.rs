@@ -0,0 +1,42 @@
+mod common;
+
+#[test]
+fn recorded_macro_runs_its_commands() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    shell
+        .run_cmd("macro-record", vec!["greet".to_string()])
+        .unwrap();
+    for word in ["hello", "world"] {
+        shell.run_cmd("echo", vec![word.to_string()]).unwrap();
+    }
+    shell.run_cmd("macro-end", Vec::new()).unwrap();
+    output.take_lines();
+
+    shell.run_cmd("greet", Vec::new()).unwrap();
+    assert_eq!(output.take_lines(), ["INFO: hello", "INFO: world"]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("greet.toml");
+    shell
+        .run_cmd(
+            "macro-save",
+            vec!["greet".to_string(), path.display().to_string()],
+        )
+        .unwrap();
+    let saved: toml::Value = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let commands = saved["commands"].as_array().unwrap();
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[0]["name"].as_str(), Some("echo"));
+    assert_eq!(commands[0]["args"][0].as_str(), Some("hello"));
+}
+
+#[test]
+fn macro_cant_replace_a_command() {
+    let (mut shell, _output) = common::shell();
+    assert!(shell.define_macro("help", Vec::new()).is_err());
+    shell
+        .define_macro("twice", vec![("aliases".to_string(), Vec::new()); 2])
+        .unwrap();
+    shell.define_macro("twice", Vec::new()).unwrap();
+}
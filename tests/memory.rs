@@ -0,0 +1,63 @@
+mod common;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use plugin_app::host::{PluginHostBuilder, SharedCtx};
+use wasmtime::Engine;
+
+/// The logs written by `tracing`.
+#[derive(Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl Logs {
+    fn take(&self) -> String {
+        String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap()
+    }
+}
+
+impl Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn memory_usage_above_the_threshold_is_warned() {
+    let logs = Logs::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_writer({
+            let logs = logs.clone();
+            move || logs.clone()
+        })
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, || {
+        // 8MiB, the plugin starts with about 1MiB, its stack.
+        let mut host = PluginHostBuilder::new(
+            Engine::default(),
+            SharedCtx::default(),
+            common::test_plugin(),
+        )
+        .with_memory_limit(128)
+        .with_memory_warn_threshold(0.5)
+        .build()
+        .unwrap();
+        host.try_call_init().unwrap();
+
+        host.try_call_run_command("echo", &[]).unwrap();
+        assert!(!logs.take().contains("of its memory limit"));
+
+        host.try_call_run_command("alloc", &["4096".to_string()])
+            .unwrap();
+        let warnings = logs.take();
+        assert!(warnings.contains("WARN"), "{warnings}");
+        assert!(warnings.contains("% of its memory limit"), "{warnings}");
+    });
+}
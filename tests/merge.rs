@@ -0,0 +1,76 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use plugin_app::{BuiltinFn, Cmd, ExecutionCtx, PrivilegeLevel, ShadowPolicy, Shell};
+
+fn succeed(_: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    Ok(())
+}
+
+fn fail(_: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    Err(())
+}
+
+/// A shell where `greet` succeeds, and another one where it fails with the
+/// test plugin loaded, merged with `policy`.
+fn merged(policy: ShadowPolicy) -> Shell {
+    let (shell, _output) = Shell::with_output_capture();
+    let mut shell = shell.with_shadow_policy(policy);
+    let greet = || Cmd::new("greet", "Greets.");
+    shell
+        .define_cmd("greet", greet(), succeed as BuiltinFn)
+        .unwrap();
+
+    let (mut other, _output) = common::shell();
+    other
+        .define_cmd("greet", greet(), fail as BuiltinFn)
+        .unwrap();
+    common::load(&mut other, &common::test_plugin());
+
+    shell.merge_into(other);
+    shell
+}
+
+#[test]
+fn merged_shell_brings_its_commands_and_plugins() {
+    let mut shell = merged(ShadowPolicy::KeepExisting);
+    assert_eq!(shell.plugin_count(), 1);
+    shell.run_cmd("echo", vec!["hi".to_string()]).unwrap();
+    assert!(shell.run_cmd("greet", Vec::new()).is_ok());
+
+    let mut shell = merged(ShadowPolicy::Replace);
+    assert!(shell.run_cmd("greet", Vec::new()).is_err());
+}
+
+#[test]
+fn plugin_loaded_in_both_shells_is_kept_once() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    let (mut other, _output) = common::shell();
+    common::load(&mut other, &common::test_plugin());
+    output.take_lines();
+
+    shell.merge_into(other);
+    assert_eq!(shell.plugin_count(), 1);
+    assert_eq!(
+        output.take_lines(),
+        ["WARNING: the plugin test-plugin is loaded in both shells, only one is kept"]
+    );
+}
+
+#[test]
+fn merged_plugin_can_still_be_interrupted() {
+    let (mut shell, _output) = common::shell();
+    let (other, _output) = Shell::with_output_capture();
+    let mut other = other.with_plugin_max_epochs(2);
+    other.set_privilege(PrivilegeLevel::Admin);
+    common::load(&mut other, &common::test_plugin());
+
+    // the plugin runs in the engine of `other`, whose epoch thread must
+    // outlive it.
+    shell.merge_into(other);
+    let start = Instant::now();
+    assert!(shell.run_cmd("spin", Vec::new()).is_err());
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
@@ -0,0 +1,49 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use plugin_app::{BuiltinFn, Cmd, ExecuteIn, ExecutionCtx, Shell, ShellError};
+
+fn boom(_: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    panic!("boom");
+}
+
+/// A shell catching the panics, with the command `boom` panicking on the main
+/// thread and `boom-thread` in a spawned thread.
+fn shell() -> Shell {
+    let (mut shell, _output) = Shell::with_output_capture();
+    shell.register_panic_hook();
+    shell
+        .define_cmd("boom", Cmd::new("boom", "Panics."), boom as BuiltinFn)
+        .unwrap();
+    shell
+        .define_cmd(
+            "boom-thread",
+            Cmd::new("boom-thread", "Panics in a thread.")
+                .with_execute_in(ExecuteIn::SpawnedThread),
+            boom as BuiltinFn,
+        )
+        .unwrap();
+    shell
+}
+
+#[test]
+fn panics_of_the_builtins_are_errors() {
+    let mut shell = shell();
+    for cmd in ["boom", "boom-thread"] {
+        assert_eq!(
+            shell.run_cmd(cmd, Vec::new()),
+            Err(ShellError::NativePanic("boom".to_string()))
+        );
+        // the shell is still usable.
+        assert_eq!(shell.run_cmd("aliases", Vec::new()), Ok(()));
+    }
+}
+
+#[test]
+fn shell_keeps_reading_its_input_after_a_panic() {
+    let mut shell = shell().with_stdin(io::Cursor::new("boom\nboom-thread\naliases\n"));
+    shell
+        .run_with_deadline(Instant::now() + Duration::from_secs(60))
+        .unwrap();
+    assert_eq!(shell.metrics().total_invocations, 3);
+}
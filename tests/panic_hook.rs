@@ -0,0 +1,43 @@
+//! The panic hook is global to the process, this test has its own binary so
+//! that no other test replaces it.
+
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use plugin_app::{BuiltinFn, Cmd, ExecutionCtx, Shell, ShellError};
+
+/// Number of panics passed to the hook installed before the shells.
+static FORWARDED: AtomicUsize = AtomicUsize::new(0);
+
+fn boom(_: &mut ExecutionCtx, _: &str, _: Vec<String>) -> Result<(), ()> {
+    panic!("boom");
+}
+
+#[test]
+fn caught_panics_are_not_passed_to_the_previous_hook() {
+    panic::set_hook(Box::new(|_| {
+        FORWARDED.fetch_add(1, Ordering::SeqCst);
+    }));
+
+    let mut shells = (0..2)
+        .map(|_| {
+            let (mut shell, _output) = Shell::with_output_capture();
+            shell.register_panic_hook();
+            shell
+                .define_cmd("boom", Cmd::new("boom", "Panics."), boom as BuiltinFn)
+                .unwrap();
+            shell
+        })
+        .collect::<Vec<_>>();
+    for shell in &mut shells {
+        assert_eq!(
+            shell.run_cmd("boom", Vec::new()),
+            Err(ShellError::NativePanic("boom".to_string()))
+        );
+    }
+    assert_eq!(FORWARDED.load(Ordering::SeqCst), 0);
+
+    // the other panics are still passed to the previous hook.
+    assert!(panic::catch_unwind(|| panic!("elsewhere")).is_err());
+    assert_eq!(FORWARDED.load(Ordering::SeqCst), 1);
+}
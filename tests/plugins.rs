@@ -0,0 +1,346 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use plugin_app::{
+    BuiltinFn, Cmd, ExecutionCtx, OutputCapture, PluginLoadError, PluginPipelineError, Shell,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+/// A shell with the test plugin and plugin-ie loaded, its output is cleared.
+fn shell() -> (Shell, OutputCapture) {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    common::load(&mut shell, &common::plugin_ie());
+    output.take_lines();
+    (shell, output)
+}
+
+#[test]
+fn search_matches_the_categories() {
+    let (mut shell, output) = shell();
+
+    shell.run_cmd("search", vec!["GREET".to_string()]).unwrap();
+    assert_eq!(
+        output.take_lines(),
+        [format!(
+            "  {:16} - A simple plugin for demonstration purposes.",
+            "plugin-ie"
+        )]
+    );
+
+    shell
+        .run_cmd("search", vec!["nothing".to_string()])
+        .unwrap();
+    assert_eq!(output.take_lines(), ["No plugin matches \"nothing\"."]);
+}
+
+#[test]
+fn commands_of_a_plugin_are_counted() {
+    let (mut shell, _output) = shell();
+    assert_eq!(shell.plugin_call_count("test-plugin"), Some(0));
+
+    shell.run_cmd("echo", Vec::new()).unwrap();
+    shell.run_cmd("echo", Vec::new()).unwrap();
+    shell.run_cmd("hello", vec!["english".to_string()]).unwrap();
+    assert_eq!(shell.plugin_call_count("test-plugin"), Some(2));
+    assert_eq!(shell.plugin_call_count("plugin-ie"), Some(1));
+    assert_eq!(shell.plugin_call_count("nope"), None);
+}
+
+#[test]
+fn uptime_of_a_plugin_is_shown() {
+    let (mut shell, output) = shell();
+    let uptime = shell.plugin_uptime("test-plugin").unwrap();
+    thread::sleep(Duration::from_millis(10));
+    assert!(shell.plugin_uptime("test-plugin").unwrap() > uptime);
+    assert_eq!(shell.plugin_uptime("nope"), None);
+
+    shell
+        .run_cmd("plugin-info", vec!["test-plugin".to_string()])
+        .unwrap();
+    assert!(output.take_lines().contains(&"  loaded 0s ago".to_string()));
+}
+
+#[test]
+fn command_graph_links_the_commands_to_their_plugin() {
+    let (mut shell, _output) = shell();
+    shell.define_alias("say", "echo").unwrap();
+
+    let dot = shell.export_command_graph();
+    assert!(dot.starts_with("digraph commands {\n"));
+    assert!(dot.contains("    \"plugin:test-plugin\" -> \"cmd:echo\";\n"));
+    assert!(dot.contains("    \"shell\" -> \"cmd:help\";\n"));
+    assert!(dot.contains("    \"cmd:say\" -> \"cmd:echo\" [style=dashed];\n"));
+    // an alias isn't a built-in command.
+    assert!(!dot.contains("\"shell\" -> \"cmd:say\""));
+}
+
+#[test]
+fn interface_of_a_plugin_is_summarized() {
+    let (mut shell, output) = shell();
+    shell
+        .run_cmd("inspect-wit", vec!["test-plugin".to_string()])
+        .unwrap();
+    let lines = output.take_lines();
+    assert_eq!(lines.first().map(String::as_str), Some("world {"));
+    for line in [
+        "  import plugin-app:core/host-app {",
+        "    log: func;",
+        "  export init: func;",
+        "  export run-command: func;",
+    ] {
+        assert!(lines.contains(&line.to_string()), "{line:?} is missing");
+    }
+
+    assert!(shell
+        .run_cmd("inspect-wit", vec!["nope".to_string()])
+        .is_err());
+    assert_eq!(
+        output.take_lines(),
+        ["ERR: there is no plugin named \"nope\"."]
+    );
+}
+
+#[test]
+fn hash_of_a_plugin_is_verified() {
+    let (mut shell, output) = shell();
+    let hash: [u8; 32] = Sha256::digest(std::fs::read(common::test_plugin()).unwrap()).into();
+    shell.verify_plugin_hash("test-plugin", &hash).unwrap();
+    assert!(matches!(
+        shell.verify_plugin_hash("test-plugin", &[0; 32]),
+        Err(PluginLoadError::HashMismatch(name)) if name == "test-plugin"
+    ));
+    assert!(matches!(
+        shell.verify_plugin_hash("nope", &hash),
+        Err(PluginLoadError::NotLoaded(name)) if name == "nope"
+    ));
+
+    shell
+        .run_cmd("plugin-info", vec!["test-plugin".to_string()])
+        .unwrap();
+    let hex = hash.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    assert!(output.take_lines().contains(&format!("  sha256: {hex}")));
+}
+
+/// Succeeds if the plugin named by the first argument is loaded.
+fn check_plugin(ctx: &mut ExecutionCtx, _: &str, args: Vec<String>) -> Result<(), ()> {
+    let valid = ctx.validate_plugin(&args[0]).is_ok();
+    assert_eq!(valid, ctx.is_plugin_loaded(&args[0]));
+    valid.then_some(()).ok_or(())
+}
+
+#[test]
+fn names_of_unloaded_plugins_are_invalid() {
+    let (mut shell, _output) = shell();
+    shell
+        .define_cmd(
+            "check-plugin",
+            Cmd::new("check-plugin <name>", "Checks that a plugin is loaded."),
+            check_plugin as BuiltinFn,
+        )
+        .unwrap();
+    let check = |shell: &mut Shell| shell.run_cmd("check-plugin", vec!["test-plugin".to_string()]);
+
+    assert_eq!(check(&mut shell), Ok(()));
+    assert!(shell.unload_plugin("test-plugin"));
+    assert!(check(&mut shell).is_err());
+}
+
+#[test]
+fn plugins_are_unloaded_on_shutdown() {
+    let (mut shell, output) = shell();
+    shell.shutdown();
+    assert_eq!(shell.plugin_count(), 0);
+    assert!(shell.get_cmd("echo").is_none());
+    // `on-unload` was called.
+    assert_eq!(output.take_lines(), ["DEBUG: Goodbye my friend!"]);
+}
+
+#[test]
+fn events_are_listed_with_their_subscribers() {
+    let (mut shell, output) = shell();
+    shell
+        .run_cmd("subscribe", vec!["greeted".to_string()])
+        .unwrap();
+    output.take_lines();
+
+    shell
+        .run_cmd("events", vec!["plugin-ie".to_string()])
+        .unwrap();
+    assert_eq!(
+        output.take_lines(),
+        [
+            "Events of the plugin plugin-ie:".to_string(),
+            format!("  {:16} - Emitted after saying hello.", "greeted"),
+            format!("  {:16}   subscribed by test-plugin", ""),
+            format!("  {:16} - Emitted when a task is done.", "task-done"),
+        ]
+    );
+
+    shell
+        .run_cmd("events", vec!["test-plugin".to_string()])
+        .unwrap();
+    assert_eq!(
+        output.take_lines(),
+        ["The plugin test-plugin doesn't emit any event!"]
+    );
+}
+
+#[test]
+fn authors_of_a_plugin_are_shown() {
+    let (mut shell, output) = shell();
+    shell
+        .run_cmd("plugin-info", vec!["plugin-ie".to_string()])
+        .unwrap();
+    let lines = output.take_lines();
+    assert_eq!(lines[0], "plugin-ie v0.1.0");
+    assert!(lines.contains(&"  authors: thi8v".to_string()));
+}
+
+#[test]
+fn telemetry_of_a_plugin_counts_its_calls_and_errors() {
+    let (mut shell, output) = shell();
+    for language in ["english", "french", "klingon"] {
+        _ = shell.run_cmd("hello", vec![language.to_string()]);
+    }
+    output.take_lines();
+
+    shell
+        .run_cmd("telemetry", vec!["plugin-ie".to_string()])
+        .unwrap();
+    assert_eq!(
+        output.take_lines(),
+        [
+            "plugin-ie: 1 errors".to_string(),
+            "  last error: unsupported language klingon".to_string(),
+            format!("  {:16} - 3 calls", "hello"),
+        ]
+    );
+
+    let telemetry = shell.collect_all_telemetry();
+    let names = telemetry
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["plugin-ie", "test-plugin"]);
+    assert_eq!(telemetry[1].1.errors, 0);
+}
+
+#[test]
+fn openapi_spec_describes_the_arguments_of_the_commands() {
+    let (shell, _output) = shell();
+    let spec: serde_json::Value = serde_json::from_str(&shell.export_openapi_spec()).unwrap();
+    assert_eq!(spec["openapi"], "3.0.3");
+
+    let greet = &spec["paths"]["/commands/greet"]["post"];
+    assert_eq!(greet["operationId"], "greet");
+    let schema = &greet["requestBody"]["content"]["application/json"]["schema"];
+    assert_eq!(
+        *schema,
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "loud": { "type": "boolean" },
+                "greeting": { "type": "string" },
+            },
+            "required": ["name"],
+        })
+    );
+
+    // the commands without a spec take a list of arguments.
+    let echo = &spec["paths"]["/commands/echo"]["post"];
+    assert_eq!(
+        echo["requestBody"]["content"]["application/json"]["schema"]["properties"]["args"]["type"],
+        "array"
+    );
+}
+
+#[test]
+fn output_of_a_command_is_piped_to_another_one() {
+    let (mut shell, output) = shell();
+    shell
+        .pipe_through_plugin(
+            "test-plugin",
+            "count",
+            &["3".to_string()],
+            "test-plugin",
+            "echo",
+        )
+        .unwrap();
+    assert_eq!(output.take_lines(), ["INFO: 0 1 2"]);
+
+    let nope = ["nope".to_string()];
+    assert!(matches!(
+        shell.pipe_through_plugin("test-plugin", "env-get", &nope, "plugin-ie", "hello"),
+        Err(PluginPipelineError::CommandFailed(msg)) if msg == "the variable nope isn't set"
+    ));
+    assert!(matches!(
+        shell.pipe_through_plugin("test-plugin", "echo", &[], "nope", "echo"),
+        Err(PluginPipelineError::NotLoaded(_))
+    ));
+    assert!(output.take_lines().is_empty());
+}
+
+#[test]
+fn help_of_a_command_shows_its_long_description() {
+    let (mut shell, output) = shell();
+    shell.run_cmd("help", vec!["hello".to_string()]).unwrap();
+    let lines = output.take_lines();
+    assert_eq!(lines[0], "usage: hello [language]");
+    assert_eq!(lines[1], "Says \"hello\" in the specified language.");
+    assert!(lines.contains(&"  hello french    prints \"Bonjour!\"".to_string()));
+
+    // the short description is shown if there is no long one.
+    shell.run_cmd("help", vec!["echo".to_string()]).unwrap();
+    assert_eq!(
+        output.take_lines(),
+        ["usage: echo [args..]", "Logs its arguments."]
+    );
+}
+
+#[test]
+fn latency_of_a_plugin_is_shown() {
+    let (mut shell, output) = shell();
+    shell.run_cmd("echo", Vec::new()).unwrap();
+    output.take_lines();
+
+    shell
+        .run_cmd("latency", vec!["test-plugin".to_string()])
+        .unwrap();
+    let lines = output.take_lines();
+    assert_eq!(lines[0], "Latency of the plugin test-plugin:");
+    for (line, percentile) in lines[1..].iter().zip(["p50", "p95", "p99"]) {
+        assert!(line.starts_with(&format!("  {percentile}: ")), "{line}");
+    }
+}
+
+#[test]
+fn format_hooks_transform_the_output_lines() {
+    let (mut shell, output) = shell();
+    shell.register_format_hook(|line| format!("[{line}]"));
+    shell.register_format_hook(|line| line.to_uppercase());
+
+    shell.run_cmd("echo", vec!["hi".to_string()]).unwrap();
+    shell.run_cmd("aliases", Vec::new()).unwrap();
+    assert_eq!(
+        output.take_lines(),
+        ["[INFO: HI]", "[THERE IS CURRENTLY NO ALIASES!]"]
+    );
+
+    // the hooks of a forked shell don't apply to its parent.
+    let mut child = shell.fork();
+    child.register_format_hook(|line| format!("> {line}"));
+    child.run_cmd("aliases", Vec::new()).unwrap();
+    shell.run_cmd("aliases", Vec::new()).unwrap();
+    assert_eq!(
+        output.take_lines(),
+        [
+            "> [THERE IS CURRENTLY NO ALIASES!]",
+            "[THERE IS CURRENTLY NO ALIASES!]"
+        ]
+    );
+}
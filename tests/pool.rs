@@ -0,0 +1,58 @@
+mod common;
+
+use plugin_app::host::{PluginHost, PluginHostPool, SharedCtx};
+use wasmtime::Engine;
+
+#[test]
+fn pool_is_refilled_to_min_idle() {
+    let host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .unwrap();
+    let mut pool = PluginHostPool::new(&host, 2).unwrap();
+    assert_eq!(pool.idle_count(), 2);
+
+    let mut acquired = pool.acquire().unwrap();
+    assert_eq!(pool.idle_count(), 1);
+    pool.refill().unwrap();
+    assert_eq!(pool.idle_count(), 2);
+    // the spare instances are initialized.
+    acquired.try_call_run_command("echo", &[]).unwrap();
+}
+
+#[test]
+fn recovered_plugin_runs_its_commands() {
+    let (shell, output) = plugin_app::Shell::with_output_capture();
+    let mut shell = shell.with_plugin_pool_size(1);
+    shell.set_privilege(plugin_app::PrivilegeLevel::Admin);
+    common::load(&mut shell, &common::test_plugin());
+
+    assert!(shell.run_cmd("trap", Vec::new()).is_err());
+    shell.try_recover_plugin("test-plugin").unwrap();
+    output.take_lines();
+    shell.run_cmd("echo", vec!["again".to_string()]).unwrap();
+    assert!(output.take_lines().contains(&"INFO: again".to_string()));
+}
+
+#[test]
+fn plugin_without_a_pool_is_recovered_by_reloading_it() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+
+    assert!(shell.run_cmd("trap", Vec::new()).is_err());
+    shell.try_recover_plugin("test-plugin").unwrap();
+    output.take_lines();
+    shell.run_cmd("echo", vec!["again".to_string()]).unwrap();
+    assert!(output.take_lines().contains(&"INFO: again".to_string()));
+}
+
+#[test]
+fn recovering_an_unknown_plugin_fails() {
+    let (mut shell, _output) = common::shell();
+    assert!(matches!(
+        shell.try_recover_plugin("nope"),
+        Err(plugin_app::PluginLoadError::NotLoaded(_))
+    ));
+}
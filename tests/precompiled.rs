@@ -0,0 +1,25 @@
+mod common;
+
+use plugin_app::host::{PluginHost, SharedCtx};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+#[test]
+fn precompiled_plugin_is_loaded() {
+    let engine = Engine::default();
+    let component = Component::from_file(&engine, common::test_plugin()).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test_plugin.cwasm");
+    std::fs::write(&path, component.serialize().unwrap()).unwrap();
+
+    let mut host =
+        PluginHost::try_new_precompiled(engine.clone(), SharedCtx::default(), &path).unwrap();
+    assert_eq!(host.try_call_init().unwrap().name, "test-plugin");
+    host.try_call_run_command("echo", &[]).unwrap();
+
+    // the file isn't a precompiled component.
+    assert!(
+        PluginHost::try_new_precompiled(engine, SharedCtx::default(), common::test_plugin())
+            .is_err()
+    );
+}
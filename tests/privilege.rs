@@ -0,0 +1,37 @@
+mod common;
+
+use plugin_app::{PrivilegeLevel, Shell, ShellError};
+
+#[test]
+fn user_cannot_load_or_unload_plugins() {
+    let (mut shell, _output) = Shell::with_output_capture();
+    let path = common::test_plugin();
+
+    assert!(matches!(
+        shell.run_cmd("load", vec![path.display().to_string()]),
+        Err(ShellError::PermissionDenied(_))
+    ));
+    assert_eq!(shell.plugin_count(), 0);
+
+    shell.set_privilege(PrivilegeLevel::Admin);
+    common::load(&mut shell, &path);
+    shell.set_privilege(PrivilegeLevel::User);
+    assert!(matches!(
+        shell.run_cmd("unload", vec!["test-plugin".to_string()]),
+        Err(ShellError::PermissionDenied(_))
+    ));
+    assert_eq!(shell.plugin_count(), 1);
+}
+
+#[test]
+fn admin_unloads_a_plugin() {
+    let (mut shell, _output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    assert!(shell.get_cmd("echo").is_some());
+
+    shell
+        .run_cmd("unload", vec!["test-plugin".to_string()])
+        .unwrap();
+    assert_eq!(shell.plugin_count(), 0);
+    assert!(shell.get_cmd("echo").is_none());
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use std::io;
+
+use plugin_app::{OutputCapture, PrivilegeLevel, RecoveryStrategy, Shell};
+
+/// A shell reading `input`, that recovers from errors with `strategy`.
+fn recovering_shell(strategy: RecoveryStrategy, input: String) -> (Shell, OutputCapture) {
+    let (shell, output) = Shell::with_output_capture();
+    let mut shell = shell
+        .with_interactive(false)
+        .with_error_recovery(strategy)
+        .with_stdin(io::Cursor::new(input));
+    shell.set_privilege(PrivilegeLevel::Admin);
+    (shell, output)
+}
+
+#[test]
+fn shell_continues_or_stops_after_an_error() {
+    let (mut shell, _output) =
+        recovering_shell(RecoveryStrategy::Continue, "nope\nhelp\n".to_string());
+    shell.run().unwrap();
+    assert_eq!(shell.metrics().invocations.get("help"), Some(&1));
+
+    let (mut shell, _output) =
+        recovering_shell(RecoveryStrategy::StopOnError, "nope\nhelp\n".to_string());
+    assert!(shell.run().is_err());
+    assert_eq!(shell.metrics().invocations.get("help"), None);
+}
+
+#[test]
+fn plugin_is_restarted_after_an_error() {
+    let input = format!(
+        "load {}\ntrap\necho again\n",
+        common::test_plugin().display()
+    );
+    let (mut shell, output) = recovering_shell(RecoveryStrategy::RestartPlugin, input);
+    shell.run().unwrap();
+
+    let lines = output.take_lines();
+    let recovered = lines
+        .iter()
+        .position(|line| line == "Plugin test-plugin recovered")
+        .expect("the plugin must be recovered");
+    assert_eq!(lines[recovered + 1], "INFO: again");
+}
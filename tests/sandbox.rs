@@ -0,0 +1,52 @@
+mod common;
+
+use std::fs;
+
+use plugin_app::{PrivilegeLevel, Shell};
+
+#[test]
+fn plugins_only_read_the_files_of_the_sandbox() {
+    let root = tempfile::tempdir().unwrap();
+    let sandbox = root.path().join("sandbox");
+    fs::create_dir(&sandbox).unwrap();
+    fs::write(sandbox.join("inside.txt"), "inside").unwrap();
+    fs::write(root.path().join("outside.txt"), "outside").unwrap();
+
+    let (shell, output) = Shell::with_output_capture();
+    let mut shell = shell.with_plugin_sandbox_dir(sandbox);
+    shell.set_privilege(PrivilegeLevel::Admin);
+    common::load(&mut shell, &common::plugin_ie());
+    output.take_lines();
+
+    shell
+        .run_cmd("cat", vec!["inside.txt".to_string()])
+        .unwrap();
+    assert_eq!(output.take_lines(), ["INFO: inside"]);
+
+    shell
+        .run_cmd("cat", vec!["../outside.txt".to_string()])
+        .unwrap();
+    assert_eq!(
+        output.take_lines(),
+        ["ERROR: ../outside.txt: permission denied, the file is outside of the sandbox"]
+    );
+}
+
+#[test]
+fn plugins_cant_read_files_without_a_sandbox() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file.txt");
+    fs::write(&path, "content").unwrap();
+
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::plugin_ie());
+    output.take_lines();
+
+    shell
+        .run_cmd("cat", vec![path.display().to_string()])
+        .unwrap();
+    assert_eq!(
+        output.take_lines(),
+        ["ERROR: permission denied: no sandbox directory"]
+    );
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use plugin_app::Cmd;
+use serde_json::json;
+
+#[test]
+fn schema_is_compiled_when_the_command_is_defined() {
+    let (mut shell, _output) = common::shell();
+    common::load(&mut shell, &common::plugin_ie());
+    // `fib` is the only command of plugin-ie with a schema.
+    let fib = shell.get_cmd("fib").unwrap();
+    assert!(fib.input_schema().is_some());
+    assert!(fib.input_validator().is_some());
+}
+
+#[test]
+fn arguments_not_matching_the_schema_are_rejected() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::plugin_ie());
+    output.take_lines();
+
+    // the schema of `fib` only accepts positive integers.
+    assert!(shell.run_cmd("fib", vec!["abc".to_string()]).is_err());
+    let lines = output.take_lines();
+    let prefix = "ERR: the argument \"abc\" doesn't match the schema of the command: ";
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.starts_with(prefix) && line.contains("integer")),
+        "{lines:?}"
+    );
+
+    shell.run_cmd("fib", vec!["10".to_string()]).unwrap();
+}
+
+#[test]
+fn invalid_schema_is_rejected() {
+    assert!(Cmd::new("cmd", "A command.")
+        .try_with_input_schema(json!({ "type": 5 }))
+        .is_err());
+}
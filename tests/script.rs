@@ -0,0 +1,35 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use plugin_app::{Script, Shell, ShellError};
+
+#[test]
+fn history_is_written_as_a_shell_script() {
+    let (shell, _output) = Shell::with_output_capture();
+    let mut shell = shell.with_stdin(io::Cursor::new("aliases\n\nhelp 'aliases'\n"));
+    shell
+        .run_with_deadline(Instant::now() + Duration::from_secs(60))
+        .unwrap();
+
+    let mut script = Vec::new();
+    shell.emit_shell_script(&mut script).unwrap();
+    assert_eq!(
+        String::from_utf8(script).unwrap(),
+        "#!/bin/sh\nplugin-app \\\n  --exec 'aliases' \\\n  --exec 'help '\\''aliases'\\'''\n"
+    );
+}
+
+#[test]
+fn script_runs_its_commands_and_skips_its_comments() {
+    let (mut shell, _output) = Shell::with_output_capture();
+    let mut script = Script::new();
+    script
+        .add_comment("lists the aliases")
+        .add_command("aliases", &[])
+        .add_command("nope", &["arg"]);
+
+    assert_eq!(
+        shell.run_script_obj(&script),
+        [Ok(()), Err(ShellError::UnknownCommand("nope".to_string()))]
+    );
+}
@@ -0,0 +1,194 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use plugin_app::{CmdKind, DryRunInfo, OutputCapture, Shell, ShellError};
+
+#[test]
+fn default_shell_has_the_builtins() {
+    let shell = Shell::default();
+    assert_eq!(shell.plugin_count(), 0);
+    for cmd in ["help", "load", "list-plugins"] {
+        assert!(shell.get_cmd(cmd).is_some(), "{cmd} is missing");
+    }
+}
+
+#[test]
+fn waiting_for_an_undefined_command_times_out() {
+    let mut shell = Shell::new();
+    assert_eq!(shell.wait_for_command("help", Duration::ZERO), Ok(()));
+
+    let start = Instant::now();
+    assert_eq!(
+        shell.wait_for_command("nope", Duration::from_millis(50)),
+        Err(())
+    );
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn dry_run_checks_the_command_without_running_it() {
+    let shell = Shell::new();
+    assert_eq!(
+        shell.dry_run_command("load"),
+        Ok(DryRunInfo {
+            found: true,
+            cmd_name: "load".to_string(),
+            args: Vec::new(),
+            arg_count_valid: false,
+            kind: Some(CmdKind::Builtin),
+        })
+    );
+    assert_eq!(shell.plugin_count(), 0);
+
+    let info = shell.dry_run_command("nope").unwrap();
+    assert!(!info.found && !info.arg_count_valid);
+    assert_eq!(info.kind, None);
+    assert_eq!(shell.dry_run_command("  "), Err(ShellError::EmptyInput));
+}
+
+#[test]
+fn metrics_count_the_commands_and_their_errors() {
+    let (mut shell, _output) = Shell::with_output_capture();
+    shell.run_cmd("aliases", Vec::new()).unwrap();
+    shell.run_cmd("aliases", Vec::new()).unwrap();
+    // `search` fails without a keyword.
+    assert!(shell.run_cmd("search", Vec::new()).is_err());
+
+    let metrics = shell.metrics();
+    assert_eq!(metrics.total_invocations, 3);
+    assert_eq!(metrics.invocations.get("aliases"), Some(&2));
+    assert_eq!(metrics.errors.get("aliases"), None);
+    assert_eq!(metrics.errors.get("search"), Some(&1));
+}
+
+#[test]
+fn input_lines_longer_than_the_maximum_are_rejected() {
+    let (shell, output) = Shell::with_output_capture();
+    let input = format!("aliases\naliases {}\n", "a".repeat(20));
+    let mut shell = shell
+        .with_interactive(false)
+        .with_max_command_length(10)
+        .with_stdin(io::Cursor::new(input));
+
+    shell.run().unwrap();
+    assert_eq!(shell.metrics().total_invocations, 1);
+    assert_eq!(
+        output.take_lines(),
+        [
+            "There is currently no aliases!",
+            "ERR: the input is too long, it must be 10 bytes or shorter",
+        ]
+    );
+}
+
+#[test]
+fn shell_reads_its_input_and_writes_its_output() {
+    let output = OutputCapture::default();
+    let mut shell = Shell::new()
+        .with_stdout(output.clone())
+        .with_stdin(io::Cursor::new("aliases\n"));
+
+    // the shell stops at the end of its input.
+    shell.run().unwrap();
+    assert_eq!(
+        output.take_lines(),
+        [">> There is currently no aliases!", ">> "]
+    );
+}
+
+#[test]
+fn line_endings_are_removed_from_the_input() {
+    // CRLF line endings, and a last line without a line ending.
+    let (shell, output) = Shell::with_output_capture();
+    let mut shell = shell
+        .with_interactive(false)
+        .with_stdin(io::Cursor::new("aliases\r\naliases"));
+    shell.run().unwrap();
+    assert_eq!(
+        output.take_lines(),
+        [
+            "There is currently no aliases!",
+            "There is currently no aliases!"
+        ]
+    );
+    let history = shell
+        .full_history()
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect::<Vec<_>>();
+    assert_eq!(history, ["aliases", "aliases"]);
+}
+
+#[test]
+fn prompt_is_only_written_in_interactive_mode() {
+    for (interactive, expected) in [
+        (true, vec![">> There is currently no aliases!", ">> "]),
+        (false, vec!["There is currently no aliases!"]),
+    ] {
+        let (shell, output) = Shell::with_output_capture();
+        let mut shell = shell
+            .with_interactive(interactive)
+            .with_stdin(io::Cursor::new("aliases\n"));
+        shell.run().unwrap();
+        assert_eq!(output.take_lines(), expected);
+    }
+}
+
+#[test]
+fn disabled_commands_are_listed_and_refused() {
+    let (mut shell, output) = Shell::with_output_capture();
+    shell.run_cmd("disabled-cmds", Vec::new()).unwrap();
+    assert_eq!(
+        output.take_lines(),
+        ["There is currently no disabled commands!"]
+    );
+
+    shell.disable_cmd("load").unwrap();
+    shell.disable_cmd("aliases").unwrap();
+    assert!(shell.disable_cmd("nope").is_err());
+    assert_eq!(shell.list_disabled_commands(), ["aliases", "load"]);
+    assert_eq!(
+        shell.run_cmd("aliases", Vec::new()),
+        Err(ShellError::Disabled("aliases".to_string()))
+    );
+
+    shell.run_cmd("disabled-cmds", Vec::new()).unwrap();
+    assert_eq!(
+        output.take_lines(),
+        ["Disabled commands:", "  aliases", "  load"]
+    );
+
+    assert!(shell.enable_cmd("aliases"));
+    assert!(!shell.enable_cmd("aliases"));
+    assert_eq!(shell.run_cmd("aliases", Vec::new()), Ok(()));
+}
+
+#[test]
+fn session_is_recorded_in_the_asciinema_format() {
+    let (shell, _output) = Shell::with_output_capture();
+    let mut shell = shell
+        .with_interactive(false)
+        .with_stdin(io::Cursor::new("aliases\n"));
+    let recording = OutputCapture::default();
+    shell.record_session(recording.clone());
+    shell.run().unwrap();
+
+    let lines = recording
+        .take_lines()
+        .iter()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines[0]["version"], 2);
+    let events = lines[1..]
+        .iter()
+        .map(|event| (event[1].as_str().unwrap(), event[2].as_str().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        [
+            ("i", "aliases\n"),
+            ("o", "There is currently no aliases!\r\n"),
+            ("m", "EOF"),
+        ]
+    );
+}
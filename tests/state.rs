@@ -0,0 +1,55 @@
+mod common;
+
+#[test]
+fn plugin_states_are_saved_and_restored() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mut shell, _output) = common::shell();
+    common::load(&mut shell, &common::plugin_ie());
+    for _ in 0..2 {
+        shell.run_cmd("hello", vec!["english".to_string()]).unwrap();
+    }
+    shell.save_plugin_states(dir.path()).unwrap();
+    // plugin-ie saves how many times it said hello.
+    let state = std::fs::read(dir.path().join("plugin-ie.state")).unwrap();
+    assert_eq!(state, 2u64.to_le_bytes());
+
+    let (mut shell, _output) = common::shell();
+    common::load(&mut shell, &common::plugin_ie());
+    shell.load_plugin_states(dir.path()).unwrap();
+    let restored = tempfile::tempdir().unwrap();
+    shell.save_plugin_states(restored.path()).unwrap();
+    assert_eq!(
+        std::fs::read(restored.path().join("plugin-ie.state")).unwrap(),
+        state
+    );
+}
+
+#[test]
+fn failing_to_save_or_restore_a_state_is_an_error() {
+    let plugin = common::build_plugin("test-plugin", &["optional-exports"]);
+    let dir = tempfile::tempdir().unwrap();
+    let (mut shell, _output) = common::shell();
+    common::load(&mut shell, &plugin);
+
+    std::fs::write(dir.path().join("test-plugin.state"), "corrupted").unwrap();
+    assert!(shell.load_plugin_states(dir.path()).is_err());
+
+    // the instance is unusable once it trapped.
+    let (mut shell, _output) = common::shell();
+    common::load(&mut shell, &plugin);
+    assert!(shell.run_cmd("trap", Vec::new()).is_err());
+    assert!(shell.save_plugin_states(dir.path()).is_err());
+}
+
+#[test]
+fn plugins_without_the_state_exports_have_no_state() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mut shell, _output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    shell.save_plugin_states(dir.path()).unwrap();
+    assert!(!dir.path().join("test-plugin.state").exists());
+
+    // a saved state is ignored.
+    std::fs::write(dir.path().join("test-plugin.state"), "corrupted").unwrap();
+    shell.load_plugin_states(dir.path()).unwrap();
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use plugin_app::ShellError;
+
+#[test]
+fn error_of_the_last_command_is_kept() {
+    let (mut shell, output) = common::shell();
+    shell.run_cmd("error", Vec::new()).unwrap();
+    assert_eq!(output.take_lines(), ["The last command succeeded."]);
+
+    let err = shell.run_cmd("nope", Vec::new()).unwrap_err();
+    assert_eq!(err, ShellError::UnknownCommand("nope".to_string()));
+    shell.run_cmd("error", Vec::new()).unwrap();
+    assert_eq!(
+        output.take_lines(),
+        [format!("The last command failed: {err}")]
+    );
+}
+
+#[test]
+fn status_of_the_last_command_is_expanded() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    output.take_lines();
+
+    _ = shell.run_cmd("nope", Vec::new());
+    shell
+        .run_cmd("echo", vec!["status $?".to_string()])
+        .unwrap();
+    assert_eq!(output.take_lines(), ["INFO: status 1"]);
+    shell
+        .run_cmd("echo", vec!["status $?".to_string()])
+        .unwrap();
+    assert_eq!(output.take_lines(), ["INFO: status 0"]);
+}
+
+#[test]
+fn error_hooks_are_called_with_the_errors() {
+    let (mut shell, _output) = common::shell();
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    for _ in 0..2 {
+        let errors = Arc::clone(&errors);
+        shell.on_error(move |err| errors.lock().unwrap().push(err.clone()));
+    }
+
+    shell.run_cmd("help", Vec::new()).unwrap();
+    assert!(errors.lock().unwrap().is_empty());
+    _ = shell.run_cmd("nope", Vec::new());
+    let err = ShellError::UnknownCommand("nope".to_string());
+    assert_eq!(*errors.lock().unwrap(), [err.clone(), err]);
+}
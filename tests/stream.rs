@@ -0,0 +1,87 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use plugin_app::host::{PluginHost, SharedCtx};
+use wasmtime::Engine;
+
+fn host(path: &std::path::Path) -> PluginHost {
+    let mut host = PluginHost::try_new(Engine::default(), SharedCtx::default(), path).unwrap();
+    host.try_call_init().unwrap();
+    host
+}
+
+#[test]
+fn chunks_are_read_while_the_plugin_writes_them() {
+    let mut host = host(&common::build_plugin("test-plugin", &["optional-exports"]));
+    let chunks = Arc::new(Mutex::new(Vec::new()));
+
+    // the sink stops reading after 3 chunks, the plugin stops writing then.
+    let sink = {
+        let chunks = Arc::clone(&chunks);
+        move |chunk: &[u8]| {
+            let mut chunks = chunks.lock().unwrap();
+            chunks.push(String::from_utf8(chunk.to_vec()).unwrap());
+            chunks.len() < 3
+        }
+    };
+    host.try_call_stream_command("count", &["100".to_string()], sink)
+        .unwrap()
+        .unwrap();
+    assert_eq!(*chunks.lock().unwrap(), ["0\n", "1\n", "2\n"]);
+
+    let err = host
+        .try_call_stream_command("count", &["abc".to_string()], |_| true)
+        .unwrap();
+    assert_eq!(
+        err,
+        Err("count command expects a number as the first argument".to_string())
+    );
+}
+
+#[test]
+fn output_without_stream_command_is_a_single_chunk() {
+    let mut host = host(&common::test_plugin());
+    let chunks = Arc::new(Mutex::new(Vec::new()));
+
+    let sink = {
+        let chunks = Arc::clone(&chunks);
+        move |chunk: &[u8]| {
+            chunks.lock().unwrap().push(chunk.to_vec());
+            true
+        }
+    };
+    host.try_call_stream_command("count", &["3".to_string()], sink)
+        .unwrap()
+        .unwrap();
+    assert_eq!(*chunks.lock().unwrap(), [b"0\n1\n2\n".to_vec()]);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn first_chunk_is_available_before_the_command_is_done() {
+    use plugin_app::host::STREAM_BUFFER_SIZE;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+    let host = host(&common::build_plugin("test-plugin", &["optional-exports"]));
+    // many more lines than the buffer of the stream can hold, the plugin
+    // waits until they're read.
+    let n = STREAM_BUFFER_SIZE * 4;
+    let (reader, handle) = host.call_run_command_stream("count".to_string(), vec![n.to_string()]);
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    rt.block_on(reader.read_line(&mut line)).unwrap();
+    assert_eq!(line, "0\n");
+    assert!(!handle.is_finished());
+
+    let mut rest = String::new();
+    rt.block_on(reader.read_to_string(&mut rest)).unwrap();
+    let (_host, res) = handle.join().unwrap();
+    res.unwrap().unwrap();
+    assert_eq!(rest.lines().count(), n - 1);
+    assert_eq!(rest.lines().last(), Some((n - 1).to_string().as_str()));
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn task_spawned_by_a_plugin_is_polled_to_completion() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::plugin_ie());
+    output.take_lines();
+
+    shell.run_cmd("fib", vec!["10".to_string()]).unwrap();
+    assert_eq!(output.take_lines(), ["INFO: spawned the task #0"]);
+
+    let start = Instant::now();
+    loop {
+        shell.run_cmd("task", vec!["0".to_string()]).unwrap();
+        let lines = output.take_lines();
+        if lines != ["INFO: task #0 is running"] {
+            assert_eq!(lines, ["INFO: task #0 is done: 55"]);
+            break;
+        }
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "the task never ended"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use std::path::Path;
+
+use plugin_app::PluginLoadError;
+
+#[test]
+fn upgraded_plugin_defines_the_commands_of_the_new_version() {
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    shell.define_alias("say", "echo").unwrap();
+    assert!(shell.get_cmd("extra").is_none());
+
+    let extra = common::build_plugin("test-plugin", &["extra-command"]);
+    let info = shell.upgrade_plugin("test-plugin", &extra).unwrap();
+    assert_eq!(info.name, "test-plugin");
+    output.take_lines();
+    shell.run_cmd("extra", Vec::new()).unwrap();
+    assert_eq!(output.take_lines(), ["INFO: extra"]);
+
+    // the command of the previous version is removed.
+    shell
+        .upgrade_plugin("test-plugin", &common::test_plugin())
+        .unwrap();
+    assert!(shell.get_cmd("extra").is_none());
+    assert!(shell.get_cmd("echo").is_some());
+    assert!(shell.get_cmd("say").is_some());
+}
+
+#[test]
+fn upgrade_fails_if_the_file_is_missing() {
+    let (mut shell, _output) = common::shell();
+    common::load(&mut shell, &common::test_plugin());
+    assert!(matches!(
+        shell.upgrade_plugin("test-plugin", Path::new("missing.wasm")),
+        Err(PluginLoadError::Io(_))
+    ));
+    assert!(shell.get_cmd("echo").is_some());
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::fs;
+
+use plugin_app::host::{PluginHost, SharedCtx};
+use wasmtime::Engine;
+
+/// Encodes an unsigned LEB128 integer.
+fn leb128(mut n: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Encodes a custom section, it's valid at the end of a component.
+fn custom_section(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut content = leb128(name.len());
+    content.extend_from_slice(name.as_bytes());
+    content.extend_from_slice(data);
+
+    let mut section = vec![0];
+    section.extend(leb128(content.len()));
+    section.extend(content);
+    section
+}
+
+#[test]
+fn version_is_read_from_the_custom_section() {
+    let mut bytes = fs::read(common::test_plugin()).unwrap();
+    bytes.extend(custom_section("plugin-version", b"1.2.3-test"));
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("versioned.wasm");
+    fs::write(&path, bytes).unwrap();
+
+    let host = PluginHost::try_new(Engine::default(), SharedCtx::default(), &path).unwrap();
+    assert_eq!(host.version_string(), Some("1.2.3-test"));
+}
+
+#[test]
+fn version_is_none_without_the_custom_section() {
+    let host = PluginHost::try_new(
+        Engine::default(),
+        SharedCtx::default(),
+        common::test_plugin(),
+    )
+    .unwrap();
+    assert_eq!(host.version_string(), None);
+}
+
+#[test]
+fn version_of_a_module_of_the_component_is_read() {
+    // plugin-ie puts its version in a custom section of its core module.
+    let host =
+        PluginHost::try_new(Engine::default(), SharedCtx::default(), common::plugin_ie()).unwrap();
+    assert_eq!(host.version_string(), Some("0.1.0"));
+}
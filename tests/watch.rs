@@ -0,0 +1,40 @@
+mod common;
+
+use std::fs;
+use std::time::Duration;
+
+#[test]
+fn reload_defines_the_new_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test_plugin.wasm");
+    fs::copy(common::test_plugin(), &path).unwrap();
+
+    let (mut shell, output) = common::shell();
+    common::load(&mut shell, &path);
+    shell
+        .run_cmd(
+            "watch",
+            vec!["test-plugin".to_string(), path.display().to_string()],
+        )
+        .unwrap();
+    assert!(shell.get_cmd("extra").is_none());
+
+    // the file is replaced at once, so the watcher never reads half of it.
+    let tmp = dir.path().join("test_plugin.wasm.tmp");
+    fs::copy(
+        common::build_plugin("test-plugin", &["extra-command"]),
+        &tmp,
+    )
+    .unwrap();
+    fs::rename(&tmp, &path).unwrap();
+
+    shell
+        .wait_for_command("extra", Duration::from_secs(10))
+        .expect("the command of the new version wasn't defined");
+    assert!(output
+        .take_lines()
+        .contains(&"Plugin test-plugin reloaded".to_string()));
+
+    shell.run_cmd("extra", Vec::new()).unwrap();
+    assert!(output.take_lines().contains(&"INFO: extra".to_string()));
+}